@@ -0,0 +1,25 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports, unused_variables, unused_mut, dead_code))]
+
+// This PoC reflects the leakage caused by a Drop impl that releases the raw pointer fields on some paths only.
+// The enum owns the heap data of every variant through raw pointers, while its 'Drop' matches on self and
+// recovers the ownership of 'Data::String' only, thus the buffer held by 'Data::Bytes' is never deallocated.
+// Note that the drop glue of the compiler never releases raw pointers, the Drop impl is the only chance.
+
+enum Data {
+    String(*mut String),
+    Bytes(*mut Vec<u8>),
+}
+
+impl Drop for Data {
+    fn drop(&mut self) {
+        match self {
+            Data::String(ptr) => unsafe { drop(Box::from_raw(*ptr)); },
+            Data::Bytes(_) => (),
+        }
+    }
+}
+
+fn main() {
+    let s = Data::String(Box::into_raw(Box::new(String::from("boxed"))));
+    let b = Data::Bytes(Box::into_raw(Box::new(vec![1u8; 16])));
+}
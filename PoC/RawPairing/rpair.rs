@@ -0,0 +1,25 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports, unused_variables, unused_mut, dead_code))]
+
+use std::sync::Arc;
+
+// This PoC reflects the leakage caused by recovering a raw pointer with another constructor than the one it escapes by.
+// The pointer escaped by 'Arc::into_raw' points into the allocation of the Arc, behind the reference counts, while
+// 'Box::from_raw' takes it as the start of a Box allocation, thus the allocation is freed with a wrong pointer and layout.
+// Note that the escape and the recovery are usually in different functions, e.g., the new and the free of an ffi.
+
+struct Shared {
+    name: String,
+}
+
+fn shared_new() -> *const Shared {
+    Arc::into_raw(Arc::new(Shared { name: String::from("shared") }))
+}
+
+unsafe fn shared_free(ptr: *const Shared) {
+    drop(Box::from_raw(ptr as *mut Shared));
+}
+
+fn main() {
+    let ptr = shared_new();
+    unsafe { shared_free(ptr) };
+}
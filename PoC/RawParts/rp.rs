@@ -0,0 +1,17 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports, unused_variables, unused_mut, dead_code))]
+
+use std::mem;
+
+// This PoC reflects the leakage caused by rebuilding a forgotten Vec with a capacity other than its own.
+// The buffer is kept by the raw parts after 'mem::forget', and 'Vec::from_raw_parts' takes the length as the
+// capacity, thus the buffer is deallocated with a layout smaller than the one it is allocated with.
+// Note that the length equals the capacity only if 'shrink_to_fit' is called on the Vec before.
+
+fn main() {
+    let mut v: Vec<u8> = Vec::with_capacity(64);
+    v.extend_from_slice(b"boxed");
+    let ptr = v.as_mut_ptr();
+    let len = v.len();
+    mem::forget(v);
+    let rebuilt = unsafe { Vec::from_raw_parts(ptr, len, len) };
+}
@@ -2,16 +2,22 @@ pub mod ownership;
 pub mod order;
 pub mod intro_visitor;
 pub mod inter_visitor;
+pub mod known_fn;
+pub mod script;
 
 use rustc_middle::ty::TyCtxt;
 use rustc_span::def_id::DefId;
-use rustc_middle::mir::Body;
+use rustc_middle::mir::{Body, Local, Place};
 
 use crate::{Elapsed, rlc_info, RlcGlobalCtxt};
 use crate::analysis::type_analysis::{AdtOwner, OwnershipLayout, Unique};
 use crate::analysis::type_analysis::type_visitor::{TyWithIndex, mir_body};
 use crate::analysis::flow_analysis::ownership::{IntroVar, Taint};
+use crate::analysis::flow_analysis::intro_visitor::{ref_assigns, stable_discriminants};
 use crate::analysis::{IcxMut, IcxSliceMut, Rcx, RcxMut};
+use crate::components::report::TraceStep;
+
+use z3::ast;
 
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
@@ -20,12 +26,59 @@ use std::env;
 pub type MirGraph = HashMap<DefId, Graph>;
 pub type ToPo = Vec<usize>;
 pub type Edges = Vec<Vec<usize>>;
+pub type Scc = Vec<usize>;
+pub type Guards = HashMap<(usize, usize), EdgeGuard>;
+pub type FnSummaries = HashMap<DefId, OwnershipSummary>;
+// The args (0-based) that the ownership of one local may come from.
+pub type Origin = HashSet<usize>;
+
+// The times that one loop (a cyclic strongly connected component) will be visited in intro analysis.
+// Each round creates fresh variables for the blocks in the loop, so that the ownership state can be
+// carried back to the loop entry through the back edges.
+pub const LOOP_UNROLL_BOUND: usize = 2;
+
+// EdgeGuard is the condition for the control flow to take one edge (from, to) of the cfg,
+// the edges without guard are always taken once their source block is reached.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EdgeGuard {
+    // the operand of SwitchInt equals one of the values
+    Values(Vec<u128>),
+    // the operand of SwitchInt equals none of the values, i.e., the otherwise target
+    Otherwise(Vec<u128>),
+    // the terminator unwinds (true) or goes to its normal target (false)
+    Unwind(bool),
+}
+
+impl EdgeGuard {
+    // Union the guards of two edges with the same source and target, e.g., two values of a switch
+    // jump to the same block, the result is None if the edge is always taken.
+    pub fn union(&self, other: &EdgeGuard) -> Option<EdgeGuard> {
+        match (self, other) {
+            (EdgeGuard::Values(v1), EdgeGuard::Values(v2)) => {
+                let mut values = v1.clone();
+                values.extend(v2.iter().filter(|v| !v1.contains(v)));
+                Some(EdgeGuard::Values(values))
+            },
+            (EdgeGuard::Values(v), EdgeGuard::Otherwise(o))
+            | (EdgeGuard::Otherwise(o), EdgeGuard::Values(v)) => {
+                let values = o.iter().filter(|each| !v.contains(each)).copied().collect();
+                Some(EdgeGuard::Otherwise(values))
+            },
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Graph {
     e: Edges,
     pre: Edges,
     topo: ToPo,
+    // the strongly connected components of the cfg in topo order,
+    // the blocks in each component are also ordered from the entry of the component
+    scc: Vec<Scc>,
+    // the guards of the edges leaving SwitchInt and the terminators with an unwind edge
+    guards: Guards,
 }
 
 impl Default for Graph {
@@ -34,6 +87,8 @@ impl Default for Graph {
             e: Vec::default(),
             pre: Vec::default(),
             topo: Vec::default(),
+            scc: Vec::default(),
+            guards: HashMap::default(),
         }
     }
 }
@@ -44,6 +99,8 @@ impl Graph {
             e: vec![Vec::new() ; len],
             pre: vec![Vec::new() ; len],
             topo: Vec::new(),
+            scc: Vec::new(),
+            guards: HashMap::new(),
         }
     }
 
@@ -70,6 +127,35 @@ impl Graph {
     pub fn get_topo_mut(&mut self) -> &mut ToPo {
         &mut self.topo
     }
+
+    pub fn get_scc(&self) -> &Vec<Scc> {
+        &self.scc
+    }
+
+    pub fn get_scc_mut(&mut self) -> &mut Vec<Scc> {
+        &mut self.scc
+    }
+
+    pub fn get_guards(&self) -> &Guards {
+        &self.guards
+    }
+
+    pub fn get_guards_mut(&mut self) -> &mut Guards {
+        &mut self.guards
+    }
+
+    // An scc is cyclic if it has more than one block or its only block jumps to itself.
+    pub fn is_scc_cyclic(&self, scc: &Scc) -> bool {
+        scc.len() > 1 || self.e[scc[0]].contains(&scc[0])
+    }
+
+    // The block is in a loop if its scc is cyclic.
+    pub fn is_in_loop(&self, bidx: usize) -> bool {
+        self.scc
+            .iter()
+            .find(|scc| scc.contains(&bidx))
+            .map_or(false, |scc| self.is_scc_cyclic(scc))
+    }
 }
 
 pub struct FlowAnalysis<'tcx, 'a> {
@@ -105,8 +191,12 @@ impl<'tcx, 'a> FlowAnalysis<'tcx, 'a> {
         // this phase determines the final order of all basic blocks for us to visit
         // Note: we will not visit the clean-up blocks (unwinding)
         self.order();
+        // this phase computes the ownership summaries of all functions bottom-up over the call graph,
+        // the summaries are applied at the call sites in the intro procedural visitor
+        if self.rcx().config().grain().has_summaries() {
+            self.inter_run();
+        }
         // this phase will generate the intro procedural visitor for us to visit the block
-        // note that the inter procedural part is inside in this function but cod in module inter_visitor
         self.intro_run();
 
         // rlc_info!("@@@@@@@@@@@@@Build Analysis:{:?}", self.rcx().get_time_build());
@@ -164,31 +254,145 @@ impl<'tcx> NodeOrder<'tcx> {
 
 }
 
-struct InterFlowAnalysis<'tcx, 'ctx, 'a> {
-    rcx:&'a RlcGlobalCtxt<'tcx>,
-    ifa: IntroFlowAnalysis<'tcx, 'ctx, 'a>,
-    root_did: DefId,
-    root_body: &'a Body<'tcx>,
+// OwnershipSummary describes how one function treats the owning bits of its arguments,
+// the index of each vector is the position of the argument (0-based, i.e., local _1 is 0).
+// For a raw pointer argument, the summary describes the ownership that the pointer carries.
+#[derive(Debug, Clone, Default, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OwnershipSummary {
+    // the owning bits are released inside the function
+    consumed: Vec<bool>,
+    // the owning bits flow into the return value
+    returned: Vec<bool>,
+    // the owning bits escape into a raw pointer (returned or stored through a pointer)
+    escaped: Vec<bool>,
+}
+
+impl OwnershipSummary {
+    pub fn new(arg_count: usize) -> Self {
+        Self {
+            consumed: vec![false ; arg_count],
+            returned: vec![false ; arg_count],
+            escaped: vec![false ; arg_count],
+        }
+    }
+
+    pub fn arg_count(&self) -> usize {
+        self.consumed.len()
+    }
+
+    pub fn is_consumed(&self, idx: usize) -> bool {
+        self.consumed.get(idx).copied().unwrap_or(false)
+    }
+
+    pub fn is_returned(&self, idx: usize) -> bool {
+        self.returned.get(idx).copied().unwrap_or(false)
+    }
+
+    pub fn is_escaped(&self, idx: usize) -> bool {
+        self.escaped.get(idx).copied().unwrap_or(false)
+    }
+
+    pub fn has_escaped(&self) -> bool {
+        self.escaped.contains(&true)
+    }
+
+    pub fn consumed_mut(&mut self) -> &mut Vec<bool> {
+        &mut self.consumed
+    }
+
+    pub fn returned_mut(&mut self) -> &mut Vec<bool> {
+        &mut self.returned
+    }
+
+    pub fn escaped_mut(&mut self) -> &mut Vec<bool> {
+        &mut self.escaped
+    }
+}
+
+// InterFlowAnalysis computes the ownership summary for one function by a flow-insensitive origin
+// analysis: each local records the args whose owning bits it may hold (owning) and the args whose
+// owning bits it may carry as a raw pointer (raw). The blocks releasing each arg are recorded, thus an
+// arg is summarized as consumed only if it is released on all paths to the return.
+struct InterFlowAnalysis<'tcx, 'a> {
+    rcx: &'a RlcGlobalCtxt<'tcx>,
+    did: DefId,
+    body: &'a Body<'tcx>,
+    owning: Vec<Origin>,
+    raw: Vec<Origin>,
+    released_in: Vec<HashSet<usize>>,
+    summary: OwnershipSummary,
 }
 
-impl<'tcx, 'ctx, 'a> InterFlowAnalysis<'tcx, 'ctx, 'a> {
+impl<'tcx, 'a> InterFlowAnalysis<'tcx, 'a> {
     pub fn new(
         rcx: &'a RlcGlobalCtxt<'tcx>,
         did: DefId,
-        unique: &'a mut Unique,
     ) -> Self
     {
+        let body = mir_body(rcx.tcx(), did);
+        let v_len = body.local_decls.len();
+        let mut owning = vec![Origin::default() ; v_len];
+        for arg_idx in 0..body.arg_count {
+            owning[arg_idx + 1].insert(arg_idx);
+        }
+
         Self {
             rcx,
-            ifa: IntroFlowAnalysis::new(rcx, did, unique),
-            root_did: did,
-            root_body: mir_body(rcx.tcx(), did),
+            did,
+            body,
+            owning,
+            raw: vec![Origin::default() ; v_len],
+            released_in: vec![HashSet::default() ; body.arg_count],
+            summary: OwnershipSummary::new(body.arg_count),
         }
     }
 
+    pub fn did(&self) -> DefId {
+        self.did
+    }
+
+    pub fn body(&self) -> &'a Body<'tcx> {
+        self.body
+    }
+
+    pub fn owning(&self) -> &Vec<Origin> {
+        &self.owning
+    }
+
+    pub fn owning_mut(&mut self) -> &mut Vec<Origin> {
+        &mut self.owning
+    }
+
+    pub fn raw(&self) -> &Vec<Origin> {
+        &self.raw
+    }
+
+    pub fn raw_mut(&mut self) -> &mut Vec<Origin> {
+        &mut self.raw
+    }
+
+    pub fn released_in(&self) -> &Vec<HashSet<usize>> {
+        &self.released_in
+    }
+
+    pub fn released_in_mut(&mut self) -> &mut Vec<HashSet<usize>> {
+        &mut self.released_in
+    }
+
+    pub fn summary(&self) -> &OwnershipSummary {
+        &self.summary
+    }
+
+    pub fn summary_mut(&mut self) -> &mut OwnershipSummary {
+        &mut self.summary
+    }
+
+    pub fn owner(&self) -> &AdtOwner {
+        self.rcx().adt_owner()
+    }
 }
 
-impl<'tcx, 'ctx, 'o, 'a> Rcx<'tcx, 'o, 'a> for InterFlowAnalysis<'tcx, 'ctx, 'a> {
+impl<'tcx, 'o, 'a> Rcx<'tcx, 'o, 'a> for InterFlowAnalysis<'tcx, 'a> {
     #[inline(always)]
     fn rcx(&'o self) -> &'a RlcGlobalCtxt<'tcx> {
         self.rcx
@@ -208,6 +412,26 @@ struct IntroFlowAnalysis<'tcx, 'ctx, 'a> {
     ref_fn_unique: &'a mut Unique,
     elasped: Elapsed,
     taint_flag: bool,
+    // the current round of visiting the loop, it is 0 for the blocks outside of loops
+    round: usize,
+    // whether the block has been visited, only visited predecessors are merged into one block
+    visited: Vec<bool>,
+    // the out state of the block along its unwind edge, i.e., before the destination of the call is assigned
+    unwind_out: HashMap<usize, IcxSliceFroBlock<'tcx, 'ctx>>,
+    // the steps of all tracked constraints, the label `trace_{idx}` in the solver maps to the idx-th step
+    trace_steps: Vec<TraceStep>,
+    // the location being visited: the block and the statement (None for the terminator)
+    loc: (usize, Option<usize>),
+    // the path condition of each block in its latest visit, only used at the path-sensitive grain
+    paths: Vec<Option<ast::Bool<'ctx>>>,
+    // the encoded guards of the edges, the edges absent here are always taken from their source
+    edge_guards: HashMap<(usize, usize), ast::Bool<'ctx>>,
+    // the locals holding the values wrapped by `ManuallyDrop::new`, their owning bits are manually managed
+    manual_locals: HashSet<usize>,
+    // whether the discriminant of each local is the same wherever it is read
+    stable_discriminants: Vec<bool>,
+    // the place that each local is borrowed or moved from, the references are resolved by them
+    ref_assigns: HashMap<Local, (Place<'tcx>, bool)>,
 }
 
 impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
@@ -232,6 +456,16 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             ref_fn_unique: unique,
             elasped: (0, 0),
             taint_flag: false,
+            round: 0,
+            visited: vec![false ; b_len],
+            unwind_out: HashMap::default(),
+            trace_steps: Vec::default(),
+            loc: (0, None),
+            paths: vec![None ; b_len],
+            edge_guards: HashMap::default(),
+            manual_locals: HashSet::default(),
+            stable_discriminants: stable_discriminants(body, graph),
+            ref_assigns: ref_assigns(body),
         }
     }
 
@@ -267,12 +501,76 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         self.elasped.0
     }
 
-    pub fn add_time_solve(&mut self, time: i64) {
-        self.elasped.1 =  self.elasped.1 + time;
+    pub fn round(&self) -> usize {
+        self.round
+    }
+
+    pub fn set_round(&mut self, round: usize) {
+        self.round = round;
+    }
+
+    pub fn visited(&self) -> &Vec<bool> {
+        &self.visited
+    }
+
+    pub fn visited_mut(&mut self) -> &mut Vec<bool> {
+        &mut self.visited
+    }
+
+    pub fn unwind_out(&self) -> &HashMap<usize, IcxSliceFroBlock<'tcx, 'ctx>> {
+        &self.unwind_out
+    }
+
+    pub fn unwind_out_mut(&mut self) -> &mut HashMap<usize, IcxSliceFroBlock<'tcx, 'ctx>> {
+        &mut self.unwind_out
+    }
+
+    pub fn trace_steps(&self) -> &Vec<TraceStep> {
+        &self.trace_steps
+    }
+
+    pub fn trace_steps_mut(&mut self) -> &mut Vec<TraceStep> {
+        &mut self.trace_steps
     }
 
-    pub fn get_time_solve(&self) -> i64 {
-        self.elasped.1
+    pub fn paths(&self) -> &Vec<Option<ast::Bool<'ctx>>> {
+        &self.paths
+    }
+
+    pub fn paths_mut(&mut self) -> &mut Vec<Option<ast::Bool<'ctx>>> {
+        &mut self.paths
+    }
+
+    pub fn edge_guards(&self) -> &HashMap<(usize, usize), ast::Bool<'ctx>> {
+        &self.edge_guards
+    }
+
+    pub fn edge_guards_mut(&mut self) -> &mut HashMap<(usize, usize), ast::Bool<'ctx>> {
+        &mut self.edge_guards
+    }
+
+    pub fn manual_locals(&self) -> &HashSet<usize> {
+        &self.manual_locals
+    }
+
+    pub fn manual_locals_mut(&mut self) -> &mut HashSet<usize> {
+        &mut self.manual_locals
+    }
+
+    pub fn stable_discriminants(&self) -> &Vec<bool> {
+        &self.stable_discriminants
+    }
+
+    pub fn ref_assigns(&self) -> &HashMap<Local, (Place<'tcx>, bool)> {
+        &self.ref_assigns
+    }
+
+    pub fn loc(&self) -> (usize, Option<usize>) {
+        self.loc
+    }
+
+    pub fn set_loc(&mut self, loc: (usize, Option<usize>)) {
+        self.loc = loc;
     }
 
 }
@@ -637,7 +935,7 @@ impl<'tcx, 'ctx> Debug for IcxSliceFroBlock<'tcx, 'ctx> {
     }
 }
 
-#[derive(Debug, Copy, Clone, Hash)]
+#[derive(Debug, Copy, Clone, Hash, Serialize, Deserialize)]
 pub enum Z3GoalDisplay {
     Verbose,
     Disabled,
@@ -650,15 +948,48 @@ pub fn is_z3_goal_verbose() -> bool {
     }
 }
 
-#[derive(Debug, Copy, Clone, Hash)]
+#[derive(Debug, Copy, Clone, Hash, Serialize, Deserialize)]
 pub enum IcxSliceDisplay {
     Verbose,
     Disabled,
 }
 
+// The panic-path mode: if it is enabled, the cleanup blocks are visited as well and the leaks on
+// the unwinding paths are reported.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum UnwindMode {
+    Enabled,
+    Disabled,
+}
+
 pub fn is_icx_slice_verbose() -> bool {
     match env::var_os("ICX_SLICE") {
         Some(_)  => true,
         _ => false,
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edge_guard_union_values() {
+        let united = EdgeGuard::Values(vec![1, 2]).union(&EdgeGuard::Values(vec![2, 3]));
+        assert_eq!(united, Some(EdgeGuard::Values(vec![1, 2, 3])));
+    }
+
+    #[test]
+    fn edge_guard_union_values_and_otherwise() {
+        let otherwise = EdgeGuard::Otherwise(vec![0, 1, 2]);
+        assert_eq!(EdgeGuard::Values(vec![1]).union(&otherwise), Some(EdgeGuard::Otherwise(vec![0, 2])));
+        assert_eq!(otherwise.union(&EdgeGuard::Values(vec![1])), Some(EdgeGuard::Otherwise(vec![0, 2])));
+    }
+
+    #[test]
+    fn edge_guard_union_always_taken() {
+        assert_eq!(EdgeGuard::Unwind(false).union(&EdgeGuard::Unwind(true)), None);
+        assert_eq!(EdgeGuard::Otherwise(vec![1]).union(&EdgeGuard::Otherwise(vec![2])), None);
+        assert_eq!(EdgeGuard::Values(vec![1]).union(&EdgeGuard::Unwind(true)), None);
+    }
+}
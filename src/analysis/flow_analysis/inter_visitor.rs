@@ -1,33 +1,415 @@
-use crate::analysis::RcxMut;
-use crate::analysis::flow_analysis::{FlowAnalysis, InterFlowAnalysis};
+use rustc_middle::ty;
+use rustc_middle::mir::{BasicBlock, Body, Operand, Place, Rvalue, Statement, StatementKind};
+use rustc_middle::mir::{Terminator, TerminatorKind};
+use rustc_span::def_id::DefId;
+
+use crate::analysis::{Rcx, RcxMut};
+use crate::analysis::flow_analysis::{Edges, FlowAnalysis, InterFlowAnalysis, Origin, OwnershipSummary};
+use crate::analysis::flow_analysis::order::tarjan_scc;
+use crate::analysis::flow_analysis::known_fn::KnownFn;
+use crate::analysis::flow_analysis::intro_visitor::{extract_default_ty_layout, is_place_containing_ptr};
 use crate::analysis::type_analysis::type_visitor::mir_body;
-use crate::analysis::type_analysis::Unique;
+use crate::components::cache::fn_cache_key;
 
+use std::collections::{BTreeMap, HashMap, HashSet};
 use stopwatch::Stopwatch;
 
+// The max times to recompute the summaries of one recursive component in the call graph.
+pub const SUMMARY_FIXPOINT_BOUND: usize = 8;
+
 impl<'tcx, 'a> FlowAnalysis<'tcx, 'a> {
     pub fn inter_run(&mut self) {
+        let mut sw = Stopwatch::start_new();
+
         let tcx = self.tcx();
-        let mir_keys = tcx.mir_keys(());
 
-        for each_mir in mir_keys {
-            let mut unique = Unique::new();
-            let sw = Stopwatch::start_new();
-            let def_id = each_mir.to_def_id();
-            let body = mir_body(tcx, def_id);
+        let fns: Vec<DefId> = self.rcx().analyzed_fns();
+        let index: HashMap<DefId, usize> = fns
+            .iter()
+            .enumerate()
+            .map(|(idx, did)| (*did, idx))
+            .collect();
+
+        // build the call graph of the analyzed functions, the other callees are ignored
+        let mut cg: Edges = vec![Vec::new() ; fns.len()];
+        for (idx, did) in fns.iter().enumerate() {
+            let body = mir_body(tcx, *did);
+            for data in body.basic_blocks().iter() {
+                if let TerminatorKind::Call { func, .. } = &data.terminator().kind {
+                    let callee = match callee_def_id(func) {
+                        Some(callee) => callee,
+                        None => continue,
+                    };
+                    if let Some(cidx) = index.get(&callee) {
+                        if !cg[idx].contains(cidx) {
+                            cg[idx].push(*cidx);
+                        }
+                    }
+                }
+            }
+        }
+
+        // the sccs are generated in reverse topo order, thus the callees are summarized before callers
+        // for the recursive functions, the summaries are recomputed until they are stable
+        let roots: Vec<usize> = (0..fns.len()).collect();
+        for scc in tarjan_scc(&cg, &roots) {
+            let recursive = scc.len() > 1 || cg[scc[0]].contains(&scc[0]);
+            let bound = if recursive { SUMMARY_FIXPOINT_BOUND } else { 1 };
+            for _ in 0..bound {
+                let mut changed = false;
+                for node in scc.iter() {
+                    let did = fns[*node];
+
+                    // the summary of an unchanged function is taken from the cache, the callees of a
+                    // recursive component are not stable yet, thus they are always summarized
+                    if !recursive {
+                        let cached = self.rcx()
+                            .cache()
+                            .and_then(|cache| cache.load(&fn_cache_key(self.rcx(), did)))
+                            .and_then(|entry| entry.summary().cloned());
+                        if let Some(summary) = cached {
+                            self.rcx_mut().fn_summaries_mut().insert(did, summary);
+                            continue;
+                        }
+                    }
+
+                    let mut inter_visitor = InterFlowAnalysis::new(self.rcx, did);
+                    inter_visitor.summarize();
+                    let summary = inter_visitor.summary().clone();
+
+                    if self.rcx().fn_summaries().get(&did) != Some(&summary) {
+                        changed = true;
+                        self.rcx_mut().fn_summaries_mut().insert(did, summary);
+                    }
+                }
+                if !changed { break; }
+            }
+        }
+
+        self.rcx_mut().add_time_build(sw.elapsed_ms());
+        sw.stop();
+    }
+}
+
+impl<'tcx, 'a> InterFlowAnalysis<'tcx, 'a> {
+    pub(crate) fn summarize(&mut self) {
+        // the origin analysis is flow-insensitive, we iterate all blocks until the origins are stable
+        // the summary only grows with the origins, so the last round gives the final summary
+        loop {
+            let mut changed = false;
+            for (bidx, data) in self.body().basic_blocks().iter().enumerate() {
+                for stmt in data.statements.iter() {
+                    changed |= self.visit_statement(stmt);
+                }
+                changed |= self.visit_terminator(bidx, data.terminator());
+            }
+            if !changed { break; }
+        }
+
+        // an arg released only on some paths is not consumed, otherwise the caller would clear its owning
+        // bits while the callee leaks it on the other paths
+        for idx in 0..self.released_in().len() {
+            let consumed = is_released_on_all_paths(self.body(), &self.released_in()[idx]);
+            self.summary_mut().consumed_mut()[idx] = consumed;
+        }
+    }
+
+    fn visit_statement(&mut self, stmt: &Statement<'tcx>) -> bool {
+        match &stmt.kind {
+            StatementKind::Assign(
+                box(place, rvalue)
+            ) => {
+                let (owning, raw) = match rvalue {
+                    Rvalue::Use(op)
+                    | Rvalue::Repeat(op, ..)
+                    | Rvalue::Cast(_, op, ..)
+                    | Rvalue::ShallowInitBox(op, ..) => self.operand_origin(op),
+                    Rvalue::Aggregate(_, ops) => {
+                        let mut owning = Origin::default();
+                        let mut raw = Origin::default();
+                        for op in ops.iter() {
+                            let (o, r) = self.operand_origin(op);
+                            owning.extend(o);
+                            raw.extend(r);
+                        }
+                        (owning, raw)
+                    },
+                    // the reference and pointer do not hold the ownership of the place, but they still
+                    // carry the ownership that the place carries as a raw pointer
+                    Rvalue::Ref(_, _, rplace)
+                    | Rvalue::AddressOf(_, rplace) => (Origin::default(), self.raw()[rplace.local.as_usize()].clone()),
+                    _ => return false,
+                };
+                self.assign_to(place, owning, raw)
+            },
+            _ => false,
+        }
+    }
+
+    fn visit_terminator(&mut self, bidx: usize, terminator: &Terminator<'tcx>) -> bool {
+        match &terminator.kind {
+            TerminatorKind::Drop { place, .. } => {
+                let owning = self.owning()[place.local.as_usize()].clone();
+                self.consume(bidx, &owning);
+                false
+            },
+            TerminatorKind::DropAndReplace { place, value, .. } => {
+                let owning = self.owning()[place.local.as_usize()].clone();
+                self.consume(bidx, &owning);
+                let (owning, raw) = self.operand_origin(value);
+                self.assign_to(place, owning, raw)
+            },
+            TerminatorKind::Call { func, args, destination, .. } => {
+                self.visit_call(bidx, func, args, destination)
+            },
+            TerminatorKind::Return => {
+                let owning = self.owning()[0].clone();
+                let raw = self.raw()[0].clone();
+                for idx in owning {
+                    self.summary_mut().returned_mut()[idx] = true;
+                }
+                for idx in raw {
+                    self.summary_mut().escaped_mut()[idx] = true;
+                }
+                false
+            },
+            _ => false,
+        }
+    }
+
+    fn visit_call(
+        &mut self,
+        bidx: usize,
+        func: &Operand<'tcx>,
+        args: &Vec<Operand<'tcx>>,
+        dest: &Place<'tcx>,
+    ) -> bool {
+        let mut owning = Origin::default();
+        let mut raw = Origin::default();
+
+        let callee = callee_def_id(func);
+        let summary = callee
+            .and_then(|callee| self.rcx().fn_summaries().get(&callee))
+            .cloned();
+        let known = callee.and_then(|callee| KnownFn::resolve(self.tcx(), callee));
+
+        if let Some(known) = known {
+            let (o, r) = match args.first() {
+                Some(arg) => self.operand_origin(arg),
+                None => (Origin::default(), Origin::default()),
+            };
+            match known {
+                KnownFn::MemDrop => self.consume(bidx, &o),
+                KnownFn::ManuallyDropDrop => self.consume(bidx, &r),
+                // the ownership is kept by the ManuallyDrop or leaked by forget
+                KnownFn::MemForget => (),
+                KnownFn::ManuallyDropNew | KnownFn::ManuallyDropIntoInner => owning.extend(o),
+                // the value is taken out of the referent ManuallyDrop
+                KnownFn::ManuallyDropTake => owning.extend(r),
+                _ if known.is_source() => raw.extend(o),
+                _ if known.is_recovery() => owning.extend(r),
+                _ => (),
+            }
+            return self.assign_to(dest, owning, raw);
+        }
+
+        // the modeled callee (e.g., ffi) consumes the ownership carried by the taken pointers, and the
+        // owned pointer it returns is a fresh allocation, which carries nothing of the args
+        if let Some(model) = callee.and_then(|callee| self.rcx().fn_model_of(callee)) {
+            for (aidx, arg) in args.iter().enumerate() {
+                if model.is_taken(aidx) {
+                    let (o, r) = self.operand_origin(arg);
+                    let carried: Origin = o.union(&r).copied().collect();
+                    self.consume(bidx, &carried);
+                }
+            }
+            return self.assign_to(dest, owning, raw);
+        }
+
+        match summary {
+            // the callee has been summarized, thus we apply its summary to the args
+            Some(summary) => {
+                for (aidx, arg) in args.iter().enumerate() {
+                    let (o, r) = self.operand_origin(arg);
+                    let carried: Origin = o.union(&r).copied().collect();
+                    if summary.is_consumed(aidx) {
+                        self.consume(bidx, &carried);
+                    }
+                    if summary.is_returned(aidx) {
+                        owning.extend(carried.iter().copied());
+                    }
+                    if summary.is_escaped(aidx) {
+                        raw.extend(carried.iter().copied());
+                    }
+                }
+            },
+            // the callee is a black box, we use the same heuristics as the intro procedural visitor:
+            // fn(self) -> * is a source and fn(*) -> Self is a recovery
+            None => {
+                let tcx = self.tcx();
+                let l_place_ty = dest.ty(&self.body().local_decls, tcx);
+                let is_source = args.len() == 1 && is_place_containing_ptr(&l_place_ty.ty);
+                let l_layout = extract_default_ty_layout(tcx, self.owner(), l_place_ty.ty, l_place_ty.variant_index);
+                let is_recovery = l_layout.get_requirement() && !l_layout.is_empty();
 
-            if body.basic_blocks.is_cfg_cyclic() { continue; }
+                for arg in args.iter() {
+                    let (o, r) = self.operand_origin(arg);
+                    let a_ty = arg.ty(&self.body().local_decls, tcx);
+                    if a_ty.is_unsafe_ptr() || a_ty.is_region_ptr() {
+                        if is_recovery {
+                            owning.extend(r);
+                        }
+                        continue;
+                    }
+                    if let Operand::Move(_) = arg {
+                        if is_source {
+                            raw.extend(o);
+                        } else {
+                            self.consume(bidx, &o);
+                        }
+                    }
+                }
+            },
+        }
+
+        self.assign_to(dest, owning, raw)
+    }
 
-            let mut cfg = z3::Config::new();
-            cfg.set_model_generation(true);
-            cfg.set_timeout_msec(1000);
+    fn operand_origin(&self, op: &Operand<'tcx>) -> (Origin, Origin) {
+        match op {
+            Operand::Move(place) | Operand::Copy(place) => {
+                let local = place.local.as_usize();
+                (self.owning()[local].clone(), self.raw()[local].clone())
+            },
+            Operand::Constant(..) => (Origin::default(), Origin::default()),
+        }
+    }
+
+    fn assign_to(&mut self, place: &Place<'tcx>, owning: Origin, raw: Origin) -> bool {
+        // store through a pointer, the ownership escapes into the memory that the pointer points to
+        if place.is_indirect() {
+            for idx in owning.iter().chain(raw.iter()) {
+                self.summary_mut().escaped_mut()[*idx] = true;
+            }
+            return false;
+        }
+
+        let local = place.local.as_usize();
+        let len_before = self.owning()[local].len() + self.raw()[local].len();
+        self.owning_mut()[local].extend(owning);
+        self.raw_mut()[local].extend(raw);
+        len_before != self.owning()[local].len() + self.raw()[local].len()
+    }
 
-            let ctx = z3::Context::new(&cfg);
-            let goal = z3::Goal::new(&ctx, true, false, false);
-            let solver = z3::Solver::new(&ctx);
+    // Record the block releasing the args in the origin, they are summarized as consumed after the analysis.
+    fn consume(&mut self, bidx: usize, origin: &Origin) {
+        for idx in origin.iter() {
+            self.released_in_mut()[*idx].insert(bidx);
+        }
+    }
+}
 
-            let inter_visitor = InterFlowAnalysis::new(self.rcx, def_id, &mut unique);
+// Whether each path from the entry to a return passes through one of the releasing blocks. The paths are
+// pruned by the bool locals assigned by constants, i.e., the drop flags, thus the drop of an arg moved on
+// the other branch is not taken as a path skipping its release. The cleanup blocks are skipped, since the
+// unwinding paths never return.
+fn is_released_on_all_paths(body: &Body, released_in: &HashSet<usize>) -> bool {
+    if released_in.is_empty() {
+        return false;
+    }
+    let mut visited: HashSet<(usize, BTreeMap<usize, bool>)> = HashSet::default();
+    let mut worklist: Vec<(usize, BTreeMap<usize, bool>)> = vec![(0, BTreeMap::default())];
+    while let Some((bidx, mut flags)) = worklist.pop() {
+        if released_in.contains(&bidx) || !visited.insert((bidx, flags.clone())) {
+            continue;
+        }
+        let data = &body.basic_blocks()[BasicBlock::from_usize(bidx)];
+        if data.is_cleanup {
+            continue;
+        }
+        for stmt in data.statements.iter() {
+            if let StatementKind::Assign(box(place, rvalue)) = &stmt.kind {
+                let local = match place.as_local() {
+                    Some(local) => local.as_usize(),
+                    None => continue,
+                };
+                match rvalue {
+                    Rvalue::Use(Operand::Constant(constant)) if constant.ty().is_bool() => {
+                        match constant.literal.try_to_bool() {
+                            Some(value) => flags.insert(local, value),
+                            None => flags.remove(&local),
+                        };
+                    },
+                    _ => { flags.remove(&local); },
+                }
+            }
+        }
 
+        let terminator = data.terminator();
+        match &terminator.kind {
+            TerminatorKind::Return => return false,
+            TerminatorKind::SwitchInt { discr: Operand::Copy(place) | Operand::Move(place), targets, .. }
+                if place.as_local().map_or(false, |local| flags.contains_key(&local.as_usize())) => {
+                let value = flags[&place.local.as_usize()];
+                worklist.push((targets.target_for_value(value as u128).as_usize(), flags));
+            },
+            _ => {
+                if let TerminatorKind::Call { destination, .. } = &terminator.kind {
+                    flags.remove(&destination.local.as_usize());
+                }
+                worklist.extend(terminator.successors().map(|succ| (succ.as_usize(), flags.clone())));
+            },
         }
     }
-}
\ No newline at end of file
+    true
+}
+
+// Get the DefId of the callee if the function operand is a constant fn item.
+pub(crate) fn callee_def_id(func: &Operand) -> Option<DefId> {
+    match func {
+        Operand::Constant(constant) => {
+            match constant.literal.ty().kind() {
+                ty::FnDef(id, ..) => Some(*id),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{with_tcx_of, RlcConfig};
+    use crate::components::context::RlcGlobalCtxt;
+
+    // Summarize each function of the source by its name.
+    fn summaries_of(name: &str, source: &str) -> HashMap<String, OwnershipSummary> {
+        with_tcx_of(name, source, |tcx| {
+            let rcx = RlcGlobalCtxt::new(tcx, RlcConfig::default());
+            tcx.hir()
+                .body_owners()
+                .map(|did| {
+                    let mut inter_visitor = InterFlowAnalysis::new(&rcx, did.to_def_id());
+                    inter_visitor.summarize();
+                    (tcx.def_path_str(did.to_def_id()), inter_visitor.summary().clone())
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn partial_release_is_not_consumed() {
+        let source = "pub fn drop_all(b: Box<u8>) { drop(b); }\n\
+                      pub fn drop_some(b: Box<u8>, c: bool) { if c { drop(b); } }\n\
+                      pub fn forget_some(b: Box<u8>, c: bool) { if c { drop(b); } else { std::mem::forget(b); } }\n\
+                      pub fn keep(b: Box<u8>) -> Box<u8> { b }\n";
+        let summaries = summaries_of("partial_release", source);
+        assert!(summaries["drop_all"].is_consumed(0));
+        // the arg not moved into drop is dropped at the end of the scope under its drop flag
+        assert!(summaries["drop_some"].is_consumed(0));
+        assert!(!summaries["forget_some"].is_consumed(0));
+        assert!(!summaries["keep"].is_consumed(0));
+        assert!(summaries["keep"].is_returned(0));
+    }
+}
@@ -1,24 +1,36 @@
 use rustc_span::def_id::DefId;
-use rustc_middle::ty::{self, Ty, TyKind, TypeFoldable, TypeVisitable};
+use rustc_middle::ty::{self, Ty, TyCtxt, TyKind, TypeFoldable, TypeVisitable};
 use rustc_middle::mir::{Body, BasicBlock, BasicBlockData, Statement, StatementKind,
                         Terminator, Place, Rvalue, Local, Operand, ProjectionElem,
-                        CastKind, TerminatorKind};
+                        CastKind, TerminatorKind, AggregateKind, BorrowKind};
 use rustc_target::abi::VariantIdx;
+use rustc_span::Span;
 
 use crate::{rlc_error, rlc_info};
 use crate::analysis::{Rcx, RcxMut, IcxMut, IcxSliceMut};
 use crate::analysis::type_analysis::ownership::{OwnershipLayoutResult, RawTypeOwner};
 use crate::analysis::type_analysis::type_visitor::{mir_body, TyWithIndex};
-use crate::analysis::type_analysis::{DefaultOwnership, OwnershipLayout, RustBV, Unique};
-use crate::analysis::flow_analysis::{IntroFlowAnalysis, FlowAnalysis, IcxSliceFroBlock,
-                                     is_z3_goal_verbose, is_icx_slice_verbose};
+use crate::analysis::type_analysis::{AdtOwner, DefaultOwnership, OwnershipLayout, RustBV, Unique};
+use crate::analysis::type_analysis::reachability::AllocEffect;
+use crate::analysis::flow_analysis::{IntroFlowAnalysis, FlowAnalysis, IcxSliceFroBlock, OwnershipSummary, UnwindMode, EdgeGuard,
+                                     Graph, is_z3_goal_verbose, is_icx_slice_verbose, LOOP_UNROLL_BOUND};
+use crate::analysis::flow_analysis::inter_visitor::callee_def_id;
+use crate::analysis::flow_analysis::known_fn::KnownFn;
 use crate::analysis::flow_analysis::ownership::IntroVar;
+use crate::analysis::flow_analysis::script::{trace_label, ConstraintScript, ExitQuery, ExitResult, ScriptJob, SolverPool, EXIT_LABEL};
+use crate::components::cache::{fn_cache_key, CacheEntry};
+use crate::components::context::RlcGlobalCtxt;
 use crate::components::display::Display;
+use crate::components::model::{FnModel, ParamModel};
+use crate::components::report::{InconclusiveFn, InconclusiveReason, LeakPath, LeakReport, LeakVerdict, TraceKind, TraceStep};
 
 use colorful::{Color, Colorful};
 use z3::ast::{self, Ast};
 
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::ops::Add;
+use std::time::{Duration, Instant};
 use stopwatch::Stopwatch;
 use std::borrow::Borrow;
 // Fixme: arg.0
@@ -35,35 +47,133 @@ pub enum AsgnKind {
 impl<'tcx, 'a> FlowAnalysis<'tcx, 'a>{
     pub fn intro_run(&mut self) {
         let tcx = self.tcx();
-        let mir_keys = tcx.mir_keys(());
         let mut unique = Unique::new();
+        // the budget is shared by building and solving, the functions reached after the deadline
+        // are neither visited nor solved
+        let deadline = self.rcx()
+            .config()
+            .analysis_budget()
+            .map(|budget| Instant::now() + Duration::from_millis(budget));
+        let mut pool = SolverPool::new(self.rcx().config().jobs(), deadline);
+        // the functions in the order of the analysis, with the cached reports or the jobs submitted to the pool
+        let mut submitted: Vec<SubmittedFn> = Vec::default();
+
+        for def_id in self.rcx().analyzed_fns() {
+
+            let sw = Stopwatch::start_new();
+
+            // the functions out of the included paths of the project config are skipped, the paths
+            // only apply to the local crate
+            if !self.rcx().is_def_analyzed(def_id) {
+                continue;
+            }
 
-        for each_mir in mir_keys {
+            // the functions unchanged since the last run take the cached reports without solving
+            let cache_key = self.rcx().cache().map(|_| fn_cache_key(self.rcx(), def_id));
+            if let Some(key) = &cache_key {
+                let cached = self.rcx()
+                    .cache()
+                    .and_then(|cache| cache.load(key))
+                    .and_then(|entry| entry.reports(tcx, def_id));
+                let cache = self.rcx_mut().cache_mut().unwrap();
+                match cached {
+                    Some(mut reports) => {
+                        cache.add_hit();
+                        let grain = self.rcx().config().grain();
+                        reports.iter_mut().for_each(|report| report.set_grain(grain));
+                        submitted.push(SubmittedFn::Cached(def_id, reports));
+                        continue;
+                    },
+                    None => cache.add_miss(),
+                }
+            }
 
-            let mut sw = Stopwatch::start_new();
+            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                submitted.push(SubmittedFn::OverBudget(def_id));
+                continue;
+            }
+            let timeout = self.rcx().config().solver_timeout_of(&tcx.def_path_str(def_id));
 
-            let def_id = each_mir.to_def_id();
             let body = mir_body(tcx, def_id);
-            // for loop fee function analysis
-            if body.basic_blocks.is_cfg_cyclic() { continue; }
 
-            let mut cfg = z3::Config::new();
-            cfg.set_model_generation(true);
-            cfg.set_timeout_msec(1000);
-            let ctx = z3::Context::new(&cfg);
+            // the context of this thread only builds the constraints, they are solved by the pool
+            let ctx = z3::Context::new(&z3::Config::new());
             let goal = z3::Goal::new(&ctx, true, false, false);
-            let solver = z3::Solver::new(&ctx);
+            let script = ConstraintScript::new(&ctx);
 
             let mut intro_visitor = IntroFlowAnalysis::new(self.rcx, def_id, &mut unique);
-            intro_visitor.visit_body(&ctx, &goal, &solver, body, &sw);
+            intro_visitor.visit_body(&ctx, &goal, &script, body, &sw);
 
             let sec_build = intro_visitor.get_time_build();
-            let sec_solve = intro_visitor.get_time_solve();
+            let trace_steps = intro_visitor.trace_steps().clone();
+            let manual_locals = intro_visitor.manual_locals().clone();
+            drop(intro_visitor);
 
+            pool.submit(ScriptJob::new(script.take_steps(), timeout));
+            submitted.push(SubmittedFn::Solving(def_id, cache_key, trace_steps, manual_locals));
             self.rcx_mut().add_time_build(sec_build);
-            self.rcx_mut().add_time_solve(sec_solve);
 
         }
+
+        // the results are merged in the order of the functions, thus the reports are the same
+        // whatever the number of the workers and the cache hits are
+        let mut results = pool.finish().into_iter();
+        for submitted_fn in submitted {
+            let (def_id, cache_key, trace_steps, manual_locals) = match submitted_fn {
+                SubmittedFn::Cached(def_id, mut reports) => {
+                    set_cross_crate_entry(self.rcx(), def_id, &mut reports);
+                    self.rcx_mut().leak_reports_mut().extend(reports);
+                    continue;
+                },
+                SubmittedFn::OverBudget(def_id) => {
+                    let inconclusive = InconclusiveFn::new(def_id, InconclusiveReason::Budget, 0, Vec::default());
+                    self.rcx_mut().inconclusive_fns_mut().push(inconclusive);
+                    continue;
+                },
+                SubmittedFn::Solving(def_id, cache_key, trace_steps, manual_locals) =>
+                    (def_id, cache_key, trace_steps, manual_locals),
+            };
+            // the pool returns one result for each job in the order of the submission
+            let result = results.next().unwrap();
+            if result.is_failed() {
+                let inconclusive = InconclusiveFn::new(def_id, InconclusiveReason::SolverError, 0, Vec::default());
+                self.rcx_mut().inconclusive_fns_mut().push(inconclusive);
+                continue;
+            }
+            let timeout = match result.timeout() {
+                Some(timeout) => timeout,
+                None => {
+                    let inconclusive = InconclusiveFn::new(def_id, InconclusiveReason::Budget, 0, Vec::default());
+                    self.rcx_mut().inconclusive_fns_mut().push(inconclusive);
+                    continue;
+                },
+            };
+
+            let mut reports: Vec<LeakReport> = result
+                .exits()
+                .iter()
+                .flat_map(|exit| exit_leak_reports(self.rcx(), def_id, &trace_steps, &manual_locals, exit))
+                .collect();
+            retain_panic_only_reports(&mut reports);
+
+            match (result.unknown_stats().cloned(), &cache_key) {
+                (Some((constraints, bv_widths)), _) => {
+                    let reason = InconclusiveReason::Timeout(timeout);
+                    let inconclusive = InconclusiveFn::new(def_id, reason, constraints, bv_widths);
+                    self.rcx_mut().inconclusive_fns_mut().push(inconclusive);
+                },
+                // the inconclusive results are not cached, they may be solved with more time
+                (None, Some(key)) => {
+                    let entry = CacheEntry::new(tcx, self.rcx().fn_summaries().get(&def_id).cloned(), &reports);
+                    self.rcx().cache().unwrap().store(key, &entry);
+                },
+                (None, None) => (),
+            }
+
+            set_cross_crate_entry(self.rcx(), def_id, &mut reports);
+            self.rcx_mut().add_time_solve(result.sec_solve());
+            self.rcx_mut().leak_reports_mut().extend(reports);
+        }
     }
 }
 
@@ -81,34 +191,57 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         body: &'tcx Body<'tcx>,
         sw: &Stopwatch,
     ) {
-        let topo:Vec<usize> = self.graph().get_topo().iter().map(|id| *id).collect();
-        for bidx in topo
-        {
-            let data = &body.basic_blocks()[BasicBlock::from(bidx)];
-            self.visit_block_data(ctx, goal, solver, data, sw, bidx);
+        let sccs = self.graph().get_scc().clone();
+        for scc in sccs.iter() {
+            // the blocks in a loop are visited LOOP_UNROLL_BOUND times, where the state of the
+            // back edges in the last round flows into the loop entry in the next round
+            let rounds = if self.graph().is_scc_cyclic(scc) { LOOP_UNROLL_BOUND } else { 1 };
+            for round in 0..rounds {
+                self.set_round(round);
+                for bidx in scc.iter() {
+                    let data = &body.basic_blocks()[BasicBlock::from(*bidx)];
+                    self.visit_block_data(ctx, goal, solver, data, sw, *bidx);
+                    self.visited_mut()[*bidx] = true;
+                }
+            }
+            self.set_round(0);
         }
 
     }
 
+    // The name of the local is bound to the round of the loop, so that the bv in each round is fresh.
+    pub(crate) fn new_local_name(&self, local: usize, bidx: usize, sidx: usize) -> String {
+        let s = new_local_name(local, bidx, sidx);
+        if self.round() == 0 {
+            s
+        } else {
+            s.add("_r").add(&self.round().to_string())
+        }
+    }
+
     pub(crate) fn visit_block_data(
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         data: &'tcx BasicBlockData<'tcx>,
         sw: &Stopwatch,
         bidx: usize,
     ) {
+        // the merge at the entry of the block is located at the first statement
+        self.set_loc((bidx, Some(0)));
         self.preprocess_for_basic_block(ctx, goal, solver, sw, bidx);
 
         for (sidx, stmt) in data.statements.iter().enumerate() {
+            self.set_loc((bidx, Some(sidx)));
             self.visit_statement(ctx, goal, solver, data, stmt, bidx, sidx);
         }
 
+        self.set_loc((bidx, None));
         self.visit_terminator(ctx, goal, solver, data.terminator(), sw, bidx);
 
         self.reprocess_for_basic_block(bidx);
@@ -119,13 +252,18 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         sw: &Stopwatch,
         bidx: usize
     ) {
 
+        if self.is_path_sensitive() {
+            self.preprocess_path_for_basic_block(ctx, goal, solver, bidx);
+        }
+
         // For node 0 there is no pre node existed!
-        if bidx == 0 {
+        // If node 0 is the entry of a loop, it will be merged with the back edges in the next rounds.
+        if bidx == 0 && self.round() == 0 {
             let mut icx_slice = IcxSliceFroBlock::new_for_block_0(self.body().local_decls.len());
 
             for arg_idx in 0..self.body().arg_count {
@@ -147,7 +285,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                 }
                 let int = rustbv_to_int(&ownership_layout_to_rustbv(default_layout.layout()));
 
-                let name = new_local_name(idx, 0, 0).add("_arg_init");
+                let name = self.new_local_name(idx, 0, 0).add("_arg_init");
                 let len = default_layout.layout().len();
 
                 let new_bv = ast::BV::new_const(ctx, name, len as u32);
@@ -155,8 +293,8 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
 
                 let constraint_init_arg = new_bv._eq(&init_const);
 
-                goal.assert(&constraint_init_arg);
-                solver.assert(&constraint_init_arg);
+                let arg_span = self.body().local_decls[Local::from_usize(idx)].source_info.span;
+                self.assert_constraint_at(ctx, goal, solver, &constraint_init_arg, TraceKind::ArgInit, arg_span);
 
                 icx_slice.len_mut()[idx] = len;
                 icx_slice.var_mut()[idx] = IntroVar::Init(new_bv);
@@ -168,19 +306,32 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             return;
         }
 
-        let pre = &self.graph.pre[bidx];
+        // only the visited pre nodes are merged, the back edges are not visited in the first round
+        let pre:Vec<usize> = self.graph.pre[bidx]
+            .iter()
+            .filter(|idx| self.visited()[**idx])
+            .copied()
+            .collect();
 
         if pre.len() > 1 {
             // collect all pre nodes and generate their icx slice into a vector
             let mut v_pre_collect:Vec<IcxSliceFroBlock> = Vec::default();
-            for idx in pre {
-                v_pre_collect.push(IcxSliceFroBlock::new_out(self.icx_mut(), *idx));
+            for idx in pre.iter() {
+                let pre_out = self.pre_out_slice(*idx, bidx);
+                v_pre_collect.push(pre_out);
             }
 
             // the result icx slice for updating the icx
             let mut ans_icx_slice = v_pre_collect[0].clone();
             let var_len = v_pre_collect[0].len().len();
 
+            // the conditions of taking each pre node into this block, used to select the merged state
+            let conds: Vec<ast::Bool> = if self.is_path_sensitive() {
+                pre.iter().map(|idx| self.edge_cond(ctx, *idx, bidx)).collect()
+            } else {
+                Vec::default()
+            };
+
             // for all variables
             for var_idx in 0..var_len {
 
@@ -190,6 +341,8 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                 let mut ty = TyWithIndex::default();
                 let mut len = 0;
 
+                let mut branches: Vec<(usize, ast::BV)> = Vec::default();
+
                 let mut unsupported = false;
                 // for one variable in all pre basic blocks
                 for idx in 0..v_pre_collect.len() {
@@ -227,6 +380,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                     // use bv and to generate new bv
                     let bv_and = using_for_and_bv.unwrap().bvand(&var_bv);
                     using_for_and_bv = Some(bv_and);
+                    branches.push((idx, var_bv));
                     ans_icx_slice.taint_merge(&v_pre_collect[idx], var_idx);
                 }
 
@@ -235,12 +389,23 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                     continue;
                 }
 
-                let name = new_local_name(var_idx, bidx, 0).add("_phi");
+                // in the path-sensitive grain, the phi takes the state of the first pre node whose
+                // condition holds instead of the and of all pre nodes
+                let merged_bv = if conds.is_empty() {
+                    using_for_and_bv.unwrap()
+                } else {
+                    let (_, last_bv) = branches.pop().unwrap();
+                    branches
+                        .iter()
+                        .rev()
+                        .fold(last_bv, |acc, (idx, bv)| conds[*idx].ite(bv, &acc))
+                };
+
+                let name = self.new_local_name(var_idx, bidx, 0).add("_phi");
                 let phi_bv = ast::BV::new_const(ctx, name, len as u32);
-                let constraint_phi = phi_bv._eq(&using_for_and_bv.unwrap());
+                let constraint_phi = phi_bv._eq(&merged_bv);
 
-                goal.assert(&constraint_phi);
-                solver.assert(&constraint_phi);
+                self.assert_constraint(ctx, goal, solver, &constraint_phi, TraceKind::Merge);
 
                 ans_icx_slice.var_mut()[var_idx] = IntroVar::Init(phi_bv);
 
@@ -248,8 +413,12 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             }
         } else {
             if pre.len() == 0 { rlc_error!("The pre node is empty, check the logic is safe to launch."); }
-            self.icx_mut().derive_from_pre_node(pre[0], bidx);
-            self.icx_slice = IcxSliceFroBlock::new_in(self.icx_mut(), bidx);
+            if self.is_unwind_edge(pre[0], bidx) {
+                self.icx_slice = self.pre_out_slice(pre[0], bidx);
+            } else {
+                self.icx_mut().derive_from_pre_node(pre[0], bidx);
+                self.icx_slice = IcxSliceFroBlock::new_in(self.icx_mut(), bidx);
+            }
         }
 
         // println!("{:?} in {}", self.icx_slice(), bidx);
@@ -264,11 +433,249 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         self.icx_mut().derive_from_icx_slice(icx_slice, bidx);
     }
 
+    // Assert the constraint to the goal and the script. In the script, the constraint is tracked by a
+    // label that maps to a trace step, thus the unsat core tells which steps are responsible for a leak.
+    pub(crate) fn assert_constraint(
+        &mut self,
+        ctx: &'ctx z3::Context,
+        goal: &'ctx z3::Goal<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
+        constraint: &ast::Bool<'ctx>,
+        kind: TraceKind,
+    ) {
+        let span = self.current_span();
+        self.assert_constraint_at(ctx, goal, solver, constraint, kind, span);
+    }
+
+    pub(crate) fn assert_constraint_at(
+        &mut self,
+        ctx: &'ctx z3::Context,
+        goal: &'ctx z3::Goal<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
+        constraint: &ast::Bool<'ctx>,
+        kind: TraceKind,
+        span: Span,
+    ) {
+        goal.assert(constraint);
+
+        solver.assert_and_track(constraint, trace_label(self.trace_steps().len()));
+
+        let step = TraceStep::new(self.loc().0, kind, span);
+        self.trace_steps_mut().push(step);
+    }
+
+    // The span of the statement (or the terminator) being visited.
+    pub(crate) fn current_span(&self) -> Span {
+        let (bidx, sidx) = self.loc();
+        let data = &self.body().basic_blocks()[BasicBlock::from(bidx)];
+        match sidx {
+            Some(sidx) if sidx < data.statements.len() => data.statements[sidx].source_info.span,
+            _ => data.terminator().source_info.span,
+        }
+    }
+
+    // The edge from a normal block to a cleanup block is the unwind edge of its terminator.
+    pub(crate) fn is_unwind_edge(&self, from: usize, to: usize) -> bool {
+        let bbs = self.body().basic_blocks();
+        !bbs[BasicBlock::from(from)].is_cleanup
+            && bbs[BasicBlock::from(to)].is_cleanup
+            && self.unwind_out().contains_key(&from)
+    }
+
+    // The out state of the pre node along the edge to the given block.
+    pub(crate) fn pre_out_slice(&mut self, from: usize, to: usize) -> IcxSliceFroBlock<'tcx, 'ctx> {
+        if self.is_unwind_edge(from, to) {
+            return self.unwind_out()[&from].clone();
+        }
+        IcxSliceFroBlock::new_out(self.icx_mut(), from)
+    }
+
+    pub(crate) fn is_path_sensitive(&self) -> bool {
+        self.rcx().config().grain().is_path_sensitive()
+    }
+
+    // The name of the block is bound to the round of the loop like the locals.
+    pub(crate) fn new_block_name(&self, bidx: usize) -> String {
+        if self.round() == 0 {
+            bidx.to_string()
+        } else {
+            bidx.to_string().add("_r").add(&self.round().to_string())
+        }
+    }
+
+    // The path condition of the block holds if the execution reaches the block, i.e., it is the
+    // disjunction of the conditions of all visited pre nodes. The entry is always reached.
+    pub(crate) fn preprocess_path_for_basic_block(
+        &mut self,
+        ctx: &'ctx z3::Context,
+        goal: &'ctx z3::Goal<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
+        bidx: usize
+    ) {
+        if bidx == 0 {
+            self.paths_mut()[bidx] = Some(ast::Bool::from_bool(ctx, true));
+            return;
+        }
+
+        let pre:Vec<usize> = self.graph.pre[bidx]
+            .iter()
+            .filter(|idx| self.visited()[**idx])
+            .copied()
+            .collect();
+        let conds: Vec<ast::Bool> = pre.iter().map(|idx| self.edge_cond(ctx, *idx, bidx)).collect();
+        let conds_ref: Vec<&ast::Bool> = conds.iter().collect();
+
+        let path = ast::Bool::new_const(ctx, self.new_block_name(bidx).add("_path"));
+        let constraint_path = path._eq(&ast::Bool::or(ctx, &conds_ref));
+
+        // only the guarded edges are shown in the trace, the gotos are not interesting
+        if pre.iter().any(|idx| self.edge_guards().contains_key(&(*idx, bidx))) {
+            self.assert_constraint(ctx, goal, solver, &constraint_path, TraceKind::Branch);
+        } else {
+            goal.assert(&constraint_path);
+            solver.assert(&constraint_path);
+        }
+
+        self.paths_mut()[bidx] = Some(path);
+    }
+
+    // The condition of taking the edge: the source block is reached and the guard of the edge holds.
+    pub(crate) fn edge_cond(&self, ctx: &'ctx z3::Context, from: usize, to: usize) -> ast::Bool<'ctx> {
+        let path = self.paths()[from]
+            .clone()
+            .unwrap_or_else(|| ast::Bool::from_bool(ctx, true));
+        match self.edge_guards().get(&(from, to)) {
+            Some(guard) => ast::Bool::and(ctx, &[&path, guard]),
+            None => path,
+        }
+    }
+
+    // Encode the guards of the edges leaving the block. The operand of SwitchInt is an integer, which
+    // is shared by all reads of the same discriminant if the enum is not changed between them, and
+    // the unwind edges are guarded by a fresh boolean of the terminator.
+    pub(crate) fn encode_edge_guards(
+        &mut self,
+        ctx: &'ctx z3::Context,
+        term: &'tcx Terminator<'tcx>,
+        bidx: usize,
+    ) {
+        let switch = match &term.kind {
+            TerminatorKind::SwitchInt { discr, .. } => self.switch_operand_int(ctx, discr, bidx),
+            _ => None,
+        };
+        let unwind = ast::Bool::new_const(ctx, self.new_block_name(bidx).add("_unwind"));
+
+        let graph = self.graph;
+        for to in graph.get_edges()[bidx].iter() {
+            let guard = match graph.get_guards().get(&(bidx, *to)) {
+                Some(guard) => guard,
+                None => {
+                    self.edge_guards_mut().remove(&(bidx, *to));
+                    continue;
+                },
+            };
+            let encoded = match (guard, &switch) {
+                (EdgeGuard::Values(values), Some(int)) => {
+                    let eqs: Option<Vec<ast::Bool>> = values
+                        .iter()
+                        .map(|value| int_from_u128(ctx, *value).map(|value| int._eq(&value)))
+                        .collect();
+                    eqs.map(|eqs| ast::Bool::or(ctx, &eqs.iter().collect::<Vec<_>>()))
+                },
+                (EdgeGuard::Otherwise(values), Some(int)) => {
+                    // the values out of the range of z3 integers are never excluded
+                    let nes: Vec<ast::Bool> = values
+                        .iter()
+                        .filter_map(|value| int_from_u128(ctx, *value).map(|value| int._eq(&value).not()))
+                        .collect();
+                    Some(ast::Bool::and(ctx, &nes.iter().collect::<Vec<_>>()))
+                },
+                (EdgeGuard::Unwind(true), _) => Some(unwind.clone()),
+                (EdgeGuard::Unwind(false), _) => Some(unwind.not()),
+                _ => None,
+            };
+            match encoded {
+                Some(encoded) => { self.edge_guards_mut().insert((bidx, *to), encoded); },
+                None => { self.edge_guards_mut().remove(&(bidx, *to)); },
+            }
+        }
+    }
+
+    // The integer of the SwitchInt operand, if the operand is the discriminant read in the same block,
+    // the integer of the discriminant is used, otherwise the operand is a fresh integer.
+    pub(crate) fn switch_operand_int(
+        &self,
+        ctx: &'ctx z3::Context,
+        discr: &Operand<'tcx>,
+        bidx: usize,
+    ) -> Option<ast::Int<'ctx>> {
+        let local = match discr {
+            Operand::Copy(place) | Operand::Move(place) if !has_projection(place) => place.local,
+            _ => return None,
+        };
+
+        let data = &self.body().basic_blocks()[BasicBlock::from(bidx)];
+        for stmt in data.statements.iter().rev() {
+            if let StatementKind::Assign(box(ref place, ref rvalue)) = stmt.kind {
+                if place.local != local {
+                    continue;
+                }
+                match rvalue {
+                    Rvalue::Discriminant(dplace) if !has_projection(place) && !has_projection(dplace) => {
+                        return Some(self.discriminant_int(ctx, dplace.local, bidx));
+                    },
+                    _ => break,
+                }
+            }
+        }
+
+        Some(ast::Int::new_const(ctx, self.new_local_name(local.as_usize(), bidx, 0).add("_switch")))
+    }
+
+    // The integer of the discriminant of the enum local. The stable discriminant has one integer in
+    // the whole function, otherwise each read of the discriminant is a fresh integer.
+    pub(crate) fn discriminant_int(&self, ctx: &'ctx z3::Context, local: Local, bidx: usize) -> ast::Int<'ctx> {
+        if self.is_discriminant_stable(local) {
+            ast::Int::new_const(ctx, new_local_name(local.as_usize(), 0, 0).add("_disc"))
+        } else {
+            ast::Int::new_const(ctx, self.new_local_name(local.as_usize(), bidx, 0).add("_disc"))
+        }
+    }
+
+    pub(crate) fn is_discriminant_stable(&self, local: Local) -> bool {
+        self.stable_discriminants()[local.as_usize()]
+    }
+
+    // The discriminant is set to the variant, thus the switches on it only take the arm of the variant.
+    pub(crate) fn assert_discriminant(
+        &mut self,
+        ctx: &'ctx z3::Context,
+        goal: &'ctx z3::Goal<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
+        place: &Place<'tcx>,
+        vidx: VariantIdx,
+        bidx: usize,
+    ) {
+        if has_projection(place) || !self.is_discriminant_stable(place.local) {
+            return;
+        }
+        let ty = self.body().local_decls[place.local].ty;
+        let value = match ty.discriminant_for_variant(self.tcx(), vidx) {
+            Some(discr) => discr.val,
+            None => return,
+        };
+        if let Some(value) = int_from_u128(ctx, value) {
+            let constraint_disc = self.discriminant_int(ctx, place.local, bidx)._eq(&value);
+            goal.assert(&constraint_disc);
+            solver.assert(&constraint_disc);
+        }
+    }
+
     pub(crate) fn visit_statement(
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         data: &'tcx BasicBlockData<'tcx>,
         stmt: &Statement<'tcx>,
         bidx: usize,
@@ -313,10 +720,21 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
 
                 self.visit_assign(ctx, goal, solver, place, rvalue, disc, bidx, sidx);
 
+                if let Rvalue::Aggregate(box AggregateKind::Adt(_, vidx, ..), _) = rvalue {
+                    if self.is_path_sensitive() {
+                        self.assert_discriminant(ctx, goal, solver, place, *vidx, bidx);
+                    }
+                }
+
                 if is_icx_slice_verbose() {
                     println!("IcxSlice in Assign: {} {}: {:?}\n{:?}\n", bidx, sidx, stmt.kind, self.icx_slice());
                 }
             },
+            StatementKind::SetDiscriminant { place, variant_index } => {
+                if self.is_path_sensitive() {
+                    self.assert_discriminant(ctx, goal, solver, place, *variant_index, bidx);
+                }
+            },
             StatementKind::StorageLive(local) => {
                 self.handle_stmt_live(local, bidx);
             },
@@ -331,7 +749,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         term: &'tcx Terminator<'tcx>,
         sw: &Stopwatch,
         bidx: usize,
@@ -339,6 +757,10 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
 
         help_debug_goal_term(ctx, goal, bidx);
 
+        if self.is_path_sensitive() {
+            self.encode_edge_guards(ctx, term, bidx);
+        }
+
         match &term.kind {
             TerminatorKind::Drop { place, .. } => {
                 self.handle_drop(ctx, goal, solver, place, bidx, false);
@@ -349,6 +771,9 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             TerminatorKind::Return => {
                 self.handle_return(ctx, goal, solver, sw, bidx);
             }
+            TerminatorKind::Resume => {
+                self.handle_resume(ctx, goal, solver, sw, bidx);
+            }
             _ => (),
         }
 
@@ -362,13 +787,32 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         lplace: &Place<'tcx>,
         rvalue: &Rvalue<'tcx>,
         mut disc: Disc,
         bidx: usize,
         sidx: usize
     ) {
+        // the layouts are field-insensitive at the low grain, thus the locals accessed through
+        // their fields are not tracked any more
+        if !self.rcx().config().grain().is_field_sensitive() {
+            let rplace = match rvalue {
+                Rvalue::Use(Operand::Copy(rplace) | Operand::Move(rplace))
+                | Rvalue::Cast(_, Operand::Copy(rplace) | Operand::Move(rplace), ..)
+                | Rvalue::Ref(.., rplace)
+                | Rvalue::AddressOf(.., rplace) => Some(rplace),
+                _ => None,
+            };
+            let places: Vec<&Place<'tcx>> = std::iter::once(lplace).chain(rplace).collect();
+            if places.iter().any(|place| extract_projection(place).has_field()) {
+                for place in places {
+                    self.handle_intro_var_unsupported(place.local.as_usize());
+                }
+                return;
+            }
+        }
+
         let lvalue_has_projection  = has_projection(lplace);
 
         match rvalue {
@@ -449,7 +893,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         _kind: AsgnKind,
         lplace: &Place<'tcx>,
         rplace: &Place<'tcx>,
@@ -502,8 +946,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             l_ori_bv = self.icx_slice_mut().var_mut()[lu].extract();
             let l_zero_const = ast::BV::from_u64(ctx, 0, llen as u32);
             let constraint_l_ori_zero = l_ori_bv._safe_eq(&l_zero_const).unwrap();
-            goal.assert(&constraint_l_ori_zero);
-            solver.assert(&constraint_l_ori_zero);
+            self.assert_constraint(ctx, goal, solver, &constraint_l_ori_zero, TraceKind::Overwrite);
             is_ctor = false;
         } else {
             // this branch means that the assignment is the constructor of the lvalue
@@ -534,11 +977,11 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
 
         // produce the name of lvalue and rvalue in this program point
         let l_name = if is_ctor {
-            new_local_name(lu, bidx, sidx).add("_ctor_asgn")
+            self.new_local_name(lu, bidx, sidx).add("_ctor_asgn")
         } else {
-            new_local_name(lu, bidx, sidx)
+            self.new_local_name(lu, bidx, sidx)
         };
-        let r_name = new_local_name(ru, bidx, sidx);
+        let r_name = self.new_local_name(ru, bidx, sidx);
 
         // generate new bit vectors for variables
         let l_new_bv = ast::BV::new_const(ctx, l_name, llen as u32);
@@ -565,8 +1008,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         let args3 = &[&summary_1, &summary_2];
         let constraint_owning_now = ast::Bool::or(ctx, args3);
 
-        goal.assert(&constraint_owning_now);
-        solver.assert(&constraint_owning_now);
+        self.assert_constraint(ctx, goal, solver, &constraint_owning_now, TraceKind::Copy);
 
         // update the intro var value in current basic block (exactly, the statement)
         self.icx_slice_mut().var_mut()[lu] = IntroVar::Init(l_new_bv);
@@ -580,7 +1022,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         _kind: AsgnKind,
         lplace: &Place<'tcx>,
         rplace: &Place<'tcx>,
@@ -633,8 +1075,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             l_ori_bv = self.icx_slice_mut().var_mut()[lu].extract();
             let l_zero_const = ast::BV::from_u64(ctx, 0, llen as u32);
             let constraint_l_ori_zero = l_ori_bv._safe_eq(&l_zero_const).unwrap();
-            goal.assert(&constraint_l_ori_zero);
-            solver.assert(&constraint_l_ori_zero);
+            self.assert_constraint(ctx, goal, solver, &constraint_l_ori_zero, TraceKind::Overwrite);
             is_ctor = false;
         } else {
             // this branch means that the assignment is the constructor of the lvalue
@@ -665,11 +1106,11 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
 
         // produce the name of lvalue and rvalue in this program point
         let l_name = if is_ctor {
-            new_local_name(lu, bidx, sidx).add("_ctor_asgn")
+            self.new_local_name(lu, bidx, sidx).add("_ctor_asgn")
         } else {
-            new_local_name(lu, bidx, sidx)
+            self.new_local_name(lu, bidx, sidx)
         };
-        let r_name = new_local_name(ru, bidx, sidx);
+        let r_name = self.new_local_name(ru, bidx, sidx);
 
         // generate new bit vectors for variables
         let l_new_bv = ast::BV::new_const(ctx, l_name, llen as u32);
@@ -684,23 +1125,25 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         // this is for l'=r
         let l_owning = l_new_bv._safe_eq(&r_ori_bv).unwrap();
 
-        goal.assert(&r_non_owning);
-        goal.assert(&l_owning);
-        solver.assert(&r_non_owning);
-        solver.assert(&l_owning);
+        self.assert_constraint(ctx, goal, solver, &r_non_owning, TraceKind::Move);
+        self.assert_constraint(ctx, goal, solver, &l_owning, TraceKind::Move);
 
         // update the intro var value in current basic block (exactly, the statement)
         self.icx_slice_mut().var_mut()[lu] = IntroVar::Init(l_new_bv);
         self.icx_slice_mut().var_mut()[ru] = IntroVar::Init(r_new_bv);
         self.handle_taint(lu, ru);
 
+        // the ManuallyDrop moved to another local is still manually managed
+        if self.manual_locals().contains(&ru) {
+            self.manual_locals_mut().insert(lu);
+        }
     }
 
     pub(crate) fn handle_copy_from_field(
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         _kind: AsgnKind,
         lplace: &Place<'tcx>,
         rplace: &Place<'tcx>,
@@ -771,8 +1214,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             l_ori_bv = self.icx_slice_mut().var_mut()[lu].extract();
             let l_zero_const = ast::BV::from_u64(ctx, 0, llen as u32);
             let constraint_l_ori_zero = l_ori_bv._safe_eq(&l_zero_const).unwrap();
-            goal.assert(&constraint_l_ori_zero);
-            solver.assert(&constraint_l_ori_zero);
+            self.assert_constraint(ctx, goal, solver, &constraint_l_ori_zero, TraceKind::Overwrite);
             is_ctor = false;
         } else {
             // this branch means that the assignment is the constructor of the lvalue
@@ -804,11 +1246,11 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
 
         // produce the name of lvalue and rvalue in this program point
         let l_name = if is_ctor {
-            new_local_name(lu, bidx, sidx).add("_ctor_asgn")
+            self.new_local_name(lu, bidx, sidx).add("_ctor_asgn")
         } else {
-            new_local_name(lu, bidx, sidx)
+            self.new_local_name(lu, bidx, sidx)
         };
-        let r_name = new_local_name(ru, bidx, sidx);
+        let r_name = self.new_local_name(ru, bidx, sidx);
 
         // generate new bit vectors for variables
         let l_new_bv = ast::BV::new_const(ctx, l_name, llen as u32);
@@ -859,8 +1301,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         let args3 = &[&summary_1, &summary_2];
         let constraint_owning_now = ast::Bool::or(ctx, args3);
 
-        goal.assert(&constraint_owning_now);
-        solver.assert(&constraint_owning_now);
+        self.assert_constraint(ctx, goal, solver, &constraint_owning_now, TraceKind::Copy);
 
         // update the intro var value in current basic block (exactly, the statement)
         self.icx_slice_mut().var_mut()[lu] = IntroVar::Init(l_new_bv);
@@ -873,7 +1314,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         _kind: AsgnKind,
         lplace: &Place<'tcx>,
         rplace: &Place<'tcx>,
@@ -950,8 +1391,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             l_ori_bv = self.icx_slice_mut().var_mut()[lu].extract();
             let l_zero_const = ast::BV::from_u64(ctx, 0, llen as u32);
             let constraint_l_ori_zero = l_ori_bv._safe_eq(&l_zero_const).unwrap();
-            goal.assert(&constraint_l_ori_zero);
-            solver.assert(&constraint_l_ori_zero);
+            self.assert_constraint(ctx, goal, solver, &constraint_l_ori_zero, TraceKind::Overwrite);
             is_ctor = false;
         } else {
             // this branch means that the assignment is the constructor of the lvalue
@@ -983,11 +1423,11 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
 
         // produce the name of lvalue and rvalue in this program point
         let l_name = if is_ctor {
-            new_local_name(lu, bidx, sidx).add("_ctor_asgn")
+            self.new_local_name(lu, bidx, sidx).add("_ctor_asgn")
         } else {
-            new_local_name(lu, bidx, sidx)
+            self.new_local_name(lu, bidx, sidx)
         };
-        let r_name = new_local_name(ru, bidx, sidx);
+        let r_name = self.new_local_name(ru, bidx, sidx);
 
         // generate new bit vectors for variables
         let l_new_bv = ast::BV::new_const(ctx, l_name, llen as u32);
@@ -1024,10 +1464,8 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         let after_op_and = r_ori_bv.bvand(&z3_bv_for_op_and);
         let rpj_non_owning = r_new_bv._safe_eq(&after_op_and).unwrap();
 
-        goal.assert(&l_extend_owning);
-        goal.assert(&rpj_non_owning);
-        solver.assert(&l_extend_owning);
-        solver.assert(&rpj_non_owning);
+        self.assert_constraint(ctx, goal, solver, &l_extend_owning, TraceKind::Move);
+        self.assert_constraint(ctx, goal, solver, &rpj_non_owning, TraceKind::Move);
 
         // update the intro var value in current basic block (exactly, the statement)
         self.icx_slice_mut().var_mut()[lu] = IntroVar::Init(l_new_bv);
@@ -1040,7 +1478,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         _kind: AsgnKind,
         lplace: &Place<'tcx>,
         rplace: &Place<'tcx>,
@@ -1135,18 +1573,16 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             if lu > self.body().arg_count {
                 let l_f_zero_const = ast::BV::from_u64(ctx, 0, 1);
                 let constraint_l_f_ori_zero = extract_from_field._safe_eq(&l_f_zero_const).unwrap();
-                goal.assert(&constraint_l_f_ori_zero);
-                solver.assert(&constraint_l_f_ori_zero);
+                self.assert_constraint(ctx, goal, solver, &constraint_l_f_ori_zero, TraceKind::Overwrite);
             }
         } else {
             // this branch means that the assignment is the constructor of the lvalue (either l and l.f)
             // this constraint promise before the struct is [0;field]
-            let l_ori_name_ctor = new_local_name(lu, bidx, sidx).add("_ctor_asgn");
+            let l_ori_name_ctor = self.new_local_name(lu, bidx, sidx).add("_ctor_asgn");
             let l_ori_bv_ctor = ast::BV::new_const(ctx, l_ori_name_ctor, llen as u32);
             let l_ori_zero = ast::BV::from_u64(ctx, 0, llen as u32);
             let constraint_l_ctor_zero = l_ori_bv_ctor._safe_eq(&l_ori_zero).unwrap();
-            goal.assert(&constraint_l_ctor_zero);
-            solver.assert(&constraint_l_ctor_zero);
+            self.assert_constraint(ctx, goal, solver, &constraint_l_ctor_zero, TraceKind::Init);
             l_ori_bv = l_ori_zero;
             self.icx_slice_mut().ty_mut()[lu] = TyWithIndex::new(l_local_ty, disc);
             self.icx_slice_mut().layout_mut()[lu] = default_ownership.layout().clone();
@@ -1157,8 +1593,8 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         // self.icx_slice_mut().len_mut()[lu] = llen;
 
         // produce the name of lvalue and rvalue in this program point
-        let l_name = new_local_name(lu, bidx, sidx);
-        let r_name = new_local_name(ru, bidx, sidx);
+        let l_name = self.new_local_name(lu, bidx, sidx);
+        let r_name = self.new_local_name(ru, bidx, sidx);
 
         // generate new bit vectors for variables
         let l_new_bv = ast::BV::new_const(ctx, l_name, llen as u32);
@@ -1213,8 +1649,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         let args3 = &[&summary_1, &summary_2];
         let constraint_owning_now = ast::Bool::or(ctx, args3);
 
-        goal.assert(&constraint_owning_now);
-        solver.assert(&constraint_owning_now);
+        self.assert_constraint(ctx, goal, solver, &constraint_owning_now, TraceKind::Copy);
 
         // update the intro var value in current basic block (exactly, the statement)
         self.icx_slice_mut().var_mut()[lu] = IntroVar::Init(l_new_bv);
@@ -1227,7 +1662,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         _kind: AsgnKind,
         lplace: &Place<'tcx>,
         rplace: &Place<'tcx>,
@@ -1323,18 +1758,16 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             if lu > self.body().arg_count {
                 let l_f_zero_const = ast::BV::from_u64(ctx, 0, 1);
                 let constraint_l_f_ori_zero = extract_from_field._safe_eq(&l_f_zero_const).unwrap();
-                goal.assert(&constraint_l_f_ori_zero);
-                solver.assert(&constraint_l_f_ori_zero);
+                self.assert_constraint(ctx, goal, solver, &constraint_l_f_ori_zero, TraceKind::Overwrite);
             }
         } else {
             // this branch means that the assignment is the constructor of the lvalue (either l and l.f)
             // this constraint promise before the struct is [0;field]
-            let l_ori_name_ctor = new_local_name(lu, bidx, sidx).add("_ctor_asgn");
+            let l_ori_name_ctor = self.new_local_name(lu, bidx, sidx).add("_ctor_asgn");
             let l_ori_bv_ctor = ast::BV::new_const(ctx, l_ori_name_ctor, llen as u32);
             let l_ori_zero = ast::BV::from_u64(ctx, 0, llen as u32);
             let constraint_l_ctor_zero = l_ori_bv_ctor._safe_eq(&l_ori_zero).unwrap();
-            goal.assert(&constraint_l_ctor_zero);
-            solver.assert(&constraint_l_ctor_zero);
+            self.assert_constraint(ctx, goal, solver, &constraint_l_ctor_zero, TraceKind::Init);
             l_ori_bv = l_ori_zero;
             self.icx_slice_mut().ty_mut()[lu] = TyWithIndex::new(l_local_ty, disc);
             self.icx_slice_mut().layout_mut()[lu] = default_ownership.layout_mut().clone();
@@ -1345,8 +1778,8 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         // self.icx_slice_mut().len_mut()[lu] = llen;
 
         // produce the name of lvalue and rvalue in this program point
-        let l_name = new_local_name(lu, bidx, sidx);
-        let r_name = new_local_name(ru, bidx, sidx);
+        let l_name = self.new_local_name(lu, bidx, sidx);
+        let r_name = self.new_local_name(ru, bidx, sidx);
 
         // generate new bit vectors for variables
         let l_new_bv = ast::BV::new_const(ctx, l_name, llen as u32);
@@ -1378,10 +1811,8 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         }
         let lpj_shrink_owning = l_new_bv._safe_eq(&final_bv).unwrap();
 
-        goal.assert(&r_non_owning);
-        goal.assert(&lpj_shrink_owning);
-        solver.assert(&r_non_owning);
-        solver.assert(&lpj_shrink_owning);
+        self.assert_constraint(ctx, goal, solver, &r_non_owning, TraceKind::Move);
+        self.assert_constraint(ctx, goal, solver, &lpj_shrink_owning, TraceKind::Move);
 
         // update the intro var value in current basic block (exactly, the statement)
         self.icx_slice_mut().var_mut()[lu] = IntroVar::Init(l_new_bv);
@@ -1394,7 +1825,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         _kind: AsgnKind,
         lplace: &Place<'tcx>,
         rplace: &Place<'tcx>,
@@ -1492,26 +1923,24 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             if lu > self.body().arg_count {
                 let l_f_zero_const = ast::BV::from_u64(ctx, 0, 1);
                 let constraint_l_f_ori_zero = extract_from_field._safe_eq(&l_f_zero_const).unwrap();
-                goal.assert(&constraint_l_f_ori_zero);
-                solver.assert(&constraint_l_f_ori_zero);
+                self.assert_constraint(ctx, goal, solver, &constraint_l_f_ori_zero, TraceKind::Overwrite);
             }
         } else {
             // this branch means that the assignment is the constructor of the lvalue (either l and l.f)
             // this constraint promise before the struct is [0;field]
-            let l_ori_name_ctor = new_local_name(lu, bidx, sidx).add("_ctor_asgn");
+            let l_ori_name_ctor = self.new_local_name(lu, bidx, sidx).add("_ctor_asgn");
             let l_ori_bv_ctor = ast::BV::new_const(ctx, l_ori_name_ctor, llen as u32);
             let l_ori_zero = ast::BV::from_u64(ctx, 0, llen as u32);
             let constraint_l_ctor_zero = l_ori_bv_ctor._safe_eq(&l_ori_zero).unwrap();
-            goal.assert(&constraint_l_ctor_zero);
-            solver.assert(&constraint_l_ctor_zero);
+            self.assert_constraint(ctx, goal, solver, &constraint_l_ctor_zero, TraceKind::Init);
             l_ori_bv = l_ori_zero;
             self.icx_slice_mut().ty_mut()[lu] = TyWithIndex::new(l_local_ty, disc);
             self.icx_slice_mut().layout_mut()[lu] = default_ownership.layout().clone();
         }
 
         // produce the name of lvalue and rvalue in this program point
-        let l_name = new_local_name(lu, bidx, sidx);
-        let r_name = new_local_name(ru, bidx, sidx);
+        let l_name = self.new_local_name(lu, bidx, sidx);
+        let r_name = self.new_local_name(ru, bidx, sidx);
 
         // generate new bit vectors for variables
         let l_new_bv = ast::BV::new_const(ctx, l_name, llen as u32);
@@ -1569,8 +1998,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         let args3 = &[&summary_1, &summary_2];
         let constraint_owning_now = ast::Bool::or(ctx, args3);
 
-        goal.assert(&constraint_owning_now);
-        solver.assert(&constraint_owning_now);
+        self.assert_constraint(ctx, goal, solver, &constraint_owning_now, TraceKind::Copy);
 
         // update the intro var value in current basic block (exactly, the statement)
         self.icx_slice_mut().var_mut()[lu] = IntroVar::Init(l_new_bv);
@@ -1583,7 +2011,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         _kind: AsgnKind,
         lplace: &Place<'tcx>,
         rplace: &Place<'tcx>,
@@ -1680,26 +2108,24 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             if lu > self.body().arg_count {
                 let l_f_zero_const = ast::BV::from_u64(ctx, 0, 1);
                 let constraint_l_f_ori_zero = extract_from_field._safe_eq(&l_f_zero_const).unwrap();
-                goal.assert(&constraint_l_f_ori_zero);
-                solver.assert(&constraint_l_f_ori_zero);
+                self.assert_constraint(ctx, goal, solver, &constraint_l_f_ori_zero, TraceKind::Overwrite);
             }
         } else {
             // this branch means that the assignment is the constructor of the lvalue (either l and l.f)
             // this constraint promise before the struct is [0;field]
-            let l_ori_name_ctor = new_local_name(lu, bidx, sidx).add("_ctor_asgn");
+            let l_ori_name_ctor = self.new_local_name(lu, bidx, sidx).add("_ctor_asgn");
             let l_ori_bv_ctor = ast::BV::new_const(ctx, l_ori_name_ctor, llen as u32);
             let l_ori_zero = ast::BV::from_u64(ctx, 0, llen as u32);
             let constraint_l_ctor_zero = l_ori_bv_ctor._safe_eq(&l_ori_zero).unwrap();
-            goal.assert(&constraint_l_ctor_zero);
-            solver.assert(&constraint_l_ctor_zero);
+            self.assert_constraint(ctx, goal, solver, &constraint_l_ctor_zero, TraceKind::Init);
             l_ori_bv = l_ori_zero;
             self.icx_slice_mut().ty_mut()[lu] = TyWithIndex::new(l_local_ty, disc);
             self.icx_slice_mut().layout_mut()[lu] = default_ownership.layout().clone();
         }
 
         // produce the name of lvalue and rvalue in this program point
-        let l_name = new_local_name(lu, bidx, sidx);
-        let r_name = new_local_name(ru, bidx, sidx);
+        let l_name = self.new_local_name(lu, bidx, sidx);
+        let r_name = self.new_local_name(ru, bidx, sidx);
 
         // generate new bit vectors for variables
         let l_new_bv = ast::BV::new_const(ctx, l_name, llen as u32);
@@ -1736,10 +2162,8 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         }
         let lpj_owning = l_new_bv._safe_eq(&final_bv).unwrap();
 
-        goal.assert(&rpj_non_owning);
-        goal.assert(&lpj_owning);
-        solver.assert(&rpj_non_owning);
-        solver.assert(&lpj_owning);
+        self.assert_constraint(ctx, goal, solver, &rpj_non_owning, TraceKind::Move);
+        self.assert_constraint(ctx, goal, solver, &lpj_owning, TraceKind::Move);
 
         // update the intro var value in current basic block (exactly, the statement)
         self.icx_slice_mut().var_mut()[lu] = IntroVar::Init(l_new_bv);
@@ -1776,8 +2200,30 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         args: &Vec<Operand<'tcx>>,
         dest: &Place<'tcx>,
+        summary: Option<&OwnershipSummary>,
     ) -> bool {
 
+        // if the callee has a summary, the source is the arg whose ownership escapes into a pointer
+        if let Some(summary) = summary {
+            let l_place_ty = dest.ty(&self.body().local_decls, self.tcx());
+            if !is_place_containing_ptr(&l_place_ty.ty) {
+                return false;
+            }
+
+            for (aidx, arg) in args.iter().enumerate() {
+                if !summary.is_escaped(aidx) { continue; }
+                if let Operand::Move(aplace) = arg {
+                    let a_place_ty = aplace.ty(&self.body().local_decls, self.tcx());
+                    let default_layout = self.extract_default_ty_layout(a_place_ty.ty, a_place_ty.variant_index);
+                    if default_layout.is_owned() {
+                        self.taint_flag = true;
+                        return true;
+                    }
+                }
+            }
+            return false;
+        }
+
         if args.len() != 1 { return false; }
 
         let l_place_ty = dest.ty(&self.body().local_decls, self.tcx());
@@ -1805,12 +2251,56 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         args: &Vec<Operand<'tcx>>,
         dest: &Place<'tcx>,
+        summary: Option<&OwnershipSummary>,
+        effect: AllocEffect,
+        dealloc_params: &[usize],
     ) -> (bool, Vec<usize>) {
 
         let mut ans:(bool, Vec<usize>) = (false, Vec::new());
 
         if args.len() == 0 { return ans; }
 
+        // if the callee has a summary, the tainted pointer is recovered if the ownership it carries
+        // is consumed in the callee or returned to the caller
+        if let Some(summary) = summary {
+            for (aidx, arg) in args.iter().enumerate() {
+                if !summary.is_consumed(aidx) && !summary.is_returned(aidx) { continue; }
+                match arg {
+                    Operand::Move(aplace) | Operand::Copy(aplace) => {
+                        let au:usize = aplace.local.as_usize();
+                        if self.icx_slice().taint()[au].is_tainted() {
+                            ans.0 = true;
+                            ans.1.push(au);
+                        }
+                    },
+                    _ => (),
+                }
+            }
+            return ans;
+        }
+
+        // the callee proven never to deallocate over MIR cannot take the ownership from the pointer
+        if !effect.may_dealloc() {
+            return ans;
+        }
+
+        // the tainted raw pointers passed to the params reaching a deallocation in the callee are released,
+        // the other args are left to the layout of the return value
+        for (aidx, arg) in args.iter().enumerate() {
+            if !dealloc_params.contains(&aidx) { continue; }
+            match arg {
+                Operand::Move(aplace) | Operand::Copy(aplace) => {
+                    let au:usize = aplace.local.as_usize();
+                    let a_ty = aplace.ty(&self.body().local_decls, self.tcx()).ty;
+                    if aplace.projection.is_empty() && a_ty.is_unsafe_ptr() && self.icx_slice().taint()[au].is_tainted() {
+                        ans.0 = true;
+                        ans.1.push(au);
+                    }
+                },
+                _ => (),
+            }
+        }
+
         let l_place_ty = dest.ty(&self.body().local_decls, self.tcx());
         let default_layout = self.extract_default_ty_layout(l_place_ty.ty, l_place_ty.variant_index);
         if !default_layout.get_requirement() || default_layout.is_empty() {
@@ -1823,7 +2313,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                 Operand::Move(aplace) => {
                     let au:usize = aplace.local.as_usize();
                     let taint = &self.icx_slice().taint()[au];
-                    if taint.is_tainted() && taint.contains(&ty_with_idx) {
+                    if taint.is_tainted() && taint.contains(&ty_with_idx) && !ans.1.contains(&au) {
                         ans.0 = true;
                         ans.1.push(au);
                     }
@@ -1831,7 +2321,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                 Operand::Copy(aplace) => {
                     let au:usize = aplace.local.as_usize();
                     let taint = &self.icx_slice().taint()[au];
-                    if taint.is_tainted() && taint.contains(&ty_with_idx) {
+                    if taint.is_tainted() && taint.contains(&ty_with_idx) && !ans.1.contains(&au) {
                         ans.0 = true;
                         ans.1.push(au);
                     }
@@ -1842,55 +2332,191 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         ans
     }
 
+    pub(crate) fn check_known_source(&mut self, known: KnownFn) -> bool {
+        if known.is_source() {
+            self.taint_flag = true;
+        }
+        known.is_source()
+    }
+
+    pub(crate) fn check_known_recovery(
+        &mut self,
+        known: KnownFn,
+        args: &[Operand<'tcx>],
+    ) -> (bool, Vec<usize>) {
+        // the ownership carried by the pointer in the first arg is taken by the return value
+        if known.is_recovery() {
+            match args.first() {
+                Some(Operand::Move(aplace)) | Some(Operand::Copy(aplace)) => {
+                    return (true, vec![aplace.local.as_usize()]);
+                },
+                _ => (),
+            }
+        }
+        (false, Vec::new())
+    }
+
+    pub(crate) fn check_model_source(&mut self, model: &FnModel, dest: &Place<'tcx>) -> bool {
+        let l_place_ty = dest.ty(&self.body().local_decls, self.tcx());
+        if !model.is_owned_return() || !l_place_ty.ty.is_unsafe_ptr() {
+            return false;
+        }
+        self.taint_flag = true;
+        true
+    }
+
+    // The raw pointers passed to the params of the model with the given effect.
+    pub(crate) fn check_model_params(
+        &mut self,
+        model: &FnModel,
+        args: &[Operand<'tcx>],
+        param: ParamModel,
+    ) -> (bool, Vec<usize>) {
+        let mut ans:(bool, Vec<usize>) = (false, Vec::new());
+        for (aidx, arg) in args.iter().enumerate() {
+            if model.param(aidx) != param { continue; }
+            match arg {
+                Operand::Move(aplace) | Operand::Copy(aplace) => {
+                    let a_ty = aplace.ty(&self.body().local_decls, self.tcx()).ty;
+                    if aplace.projection.is_empty() && a_ty.is_unsafe_ptr() {
+                        ans.0 = true;
+                        ans.1.push(aplace.local.as_usize());
+                    }
+                },
+                _ => (),
+            }
+        }
+        ans
+    }
+
+    // Find the local that the reference (or pointer) points to, the moves and reborrows like
+    // `&mut (*r)` are resolved transitively.
+    pub(crate) fn ref_target(&self, local: Local) -> Option<Local> {
+        let mut cur = local;
+        for _ in 0..self.body().local_decls.len() {
+            match self.ref_assigns().get(&cur) {
+                Some((rplace, true)) if rplace.projection.is_empty() => return Some(rplace.local),
+                Some((rplace, true)) if rplace.projection.len() == 1 && rplace.projection[0] == ProjectionElem::Deref => {
+                    cur = rplace.local;
+                },
+                Some((rplace, false)) if rplace.projection.is_empty() => {
+                    cur = rplace.local;
+                },
+                _ => return None,
+            }
+        }
+        None
+    }
+
     pub(crate) fn handle_call(
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         func: &Operand<'tcx>,
         args: &Vec<Operand<'tcx>>,
         dest: &Place<'tcx>,
         bidx: usize,
     ) {
 
-        match func {
-            Operand::Constant(constant) => {
-                match constant.literal.ty().kind() {
-                    ty::FnDef(id, ..) => {
-                        match id.index.as_usize() {
-                            2171 => {
-                                // this for calling std::mem::drop(TY)
-                                match args[0] {
-                                    Operand::Move(aplace) => {
-                                        let a_place_ty = dest.ty(&self.body().local_decls, self.tcx());
-                                        let a_ty = a_place_ty.ty;
-                                        if a_ty.is_adt() {
-                                            self.handle_drop(ctx, goal, solver, &aplace, bidx, false);
-                                            return;
-                                        }
-                                    }, _ => (),
-                                }
-
-                            }, _ => (),
+        let known = callee_def_id(func).and_then(|callee| KnownFn::resolve(self.tcx(), callee));
+        match known {
+            Some(KnownFn::MemDrop) => {
+                // this for calling std::mem::drop(TY)
+                if let Operand::Move(aplace) = args[0] {
+                    let a_ty = aplace.ty(&self.body().local_decls, self.tcx()).ty;
+                    if a_ty.is_adt() {
+                        self.handle_drop(ctx, goal, solver, &aplace, bidx, false);
+                        return;
+                    }
+                }
+            },
+            Some(KnownFn::ManuallyDropNew) => {
+                // the owning bits of the arg are moved into the ManuallyDrop instead of being dropped, they
+                // are manually managed and must be released by ManuallyDrop::drop, into_inner or take
+                self.taint_flag = true;
+                if let Operand::Move(aplace) = args[0] {
+                    if self.handle_manually_drop(ctx, goal, solver, &aplace, dest, bidx) {
+                        return;
+                    }
+                }
+            },
+            Some(KnownFn::ManuallyDropIntoInner) => {
+                // this for calling ManuallyDrop::into_inner(ManuallyDrop<T>), the owning bits are moved out
+                if let Operand::Move(aplace) = args[0] {
+                    if self.handle_manually_drop(ctx, goal, solver, &aplace, dest, bidx) {
+                        return;
+                    }
+                }
+            },
+            Some(KnownFn::ManuallyDropTake) => {
+                // this for calling ManuallyDrop::take(&mut ManuallyDrop<T>), the referent is left without ownership
+                match args[0] {
+                    Operand::Move(rplace) | Operand::Copy(rplace) => {
+                        if let Some(target) = self.ref_target(rplace.local) {
+                            if self.handle_manually_drop(ctx, goal, solver, &Place::from(target), dest, bidx) {
+                                return;
+                            }
+                        }
+                    },
+                    _ => (),
+                }
+            },
+            Some(KnownFn::ManuallyDropDrop) => {
+                // this for calling ManuallyDrop::drop(&mut ManuallyDrop<T>), release the referent
+                match args[0] {
+                    Operand::Move(rplace) | Operand::Copy(rplace) => {
+                        if let Some(target) = self.ref_target(rplace.local) {
+                            self.handle_drop(ctx, goal, solver, &Place::from(target), bidx, false);
+                            return;
                         }
-                    }, _ => (),
+                    },
+                    _ => (),
                 }
-            }, _ => (),
+            },
+            _ => (),
         }
 
         // for return value
         let llocal = dest.local;
         let lu:usize = llocal.as_usize();
 
+        // the summary of the callee defined in current crate, it refines the following heuristics
+        let summary = callee_def_id(func)
+            .and_then(|callee| self.rcx().fn_summaries().get(&callee))
+            .cloned();
+        // the may-deallocate effect of the callee, the callees out of the reachability analysis may do both
+        let effect = callee_def_id(func).map_or_else(AllocEffect::unknown, |callee| self.rcx().alloc_effect_of(callee));
+        // the params of the callee whose pointers reach a deallocation in it
+        let dealloc_params = callee_def_id(func)
+            .and_then(|callee| self.rcx().dealloc_params().get(&callee))
+            .cloned()
+            .unwrap_or_default();
+        // the model of the callee (e.g., ffi), it overrides the summary and the heuristics
+        let model = callee_def_id(func).and_then(|callee| self.rcx().fn_model_of(callee));
+
         // the source flag is for fn(self) -> */&
         // we will tag the lvalue as tainted and change the default ctor to modified one
-        let source_flag = self.check_fn_source(args, dest);
+        let source_flag = match (known, &model) {
+            (Some(known), _) => self.check_known_source(known),
+            (None, Some(model)) => self.check_model_source(model, dest),
+            (None, None) => self.check_fn_source(args, dest, summary.as_ref()),
+        };
         // the recovery flag is for fn(*) -> Self
         // the return value should have the same layout as tainted one
         // we will take the ownership of the args if the arg is a pointer
-        let recovery_flag = self.check_fn_recovery(args, dest);
+        let recovery_flag = match (known, &model) {
+            (Some(known), _) => self.check_known_recovery(known, args),
+            (None, Some(model)) => self.check_model_params(model, args, ParamModel::Take),
+            (None, None) => self.check_fn_recovery(args, dest, summary.as_ref(), effect, &dealloc_params),
+        };
+        // the pointers borrowed by the modeled callee keep their ownership through the call
+        let borrowed = match (known, &model) {
+            (None, Some(model)) => self.check_model_params(model, args, ParamModel::Borrow).1,
+            _ => Vec::default(),
+        };
 
-        for arg in args {
+        for (aidx, arg) in args.iter().enumerate() {
             match arg {
                 Operand::Move(aplace) => {
 
@@ -1915,7 +2541,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                     let a_ori_bv = self.icx_slice_mut().var_mut()[au].extract();
                     let alen = self.icx_slice().len()[au];
 
-                    if source_flag {
+                    if source_flag && model.is_none() && summary.as_ref().map_or(true, |summary| summary.is_escaped(aidx)) {
                         self.icx_slice_mut().taint_mut()[lu].insert(
                             TyWithIndex::new(
                                 a_place_ty.ty,
@@ -1934,20 +2560,20 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                                 }
 
                                 // if the aplace is a pointer (move ptr => still hold)
-                                // the exact constraint is a=0, a'=a
+                                // the exact constraint is a=0, a'=a, or a'=a if the callee borrows it
                                 // this is for a=0
-                                let a_zero_const = ast::BV::from_u64(ctx, 0, alen as u32);
-                                let a_ori_non_owing = a_ori_bv._safe_eq(&a_zero_const).unwrap();
+                                if !borrowed.contains(&au) {
+                                    let a_zero_const = ast::BV::from_u64(ctx, 0, alen as u32);
+                                    let a_ori_non_owing = a_ori_bv._safe_eq(&a_zero_const).unwrap();
+                                    self.assert_constraint(ctx, goal, solver, &a_ori_non_owing, TraceKind::ParamPass);
+                                }
 
                                 // this is for a'=a
-                                let a_name = new_local_name(au, bidx, 0).add("_param_pass");
+                                let a_name = self.new_local_name(au, bidx, 0).add("_param_pass");
                                 let a_new_bv = ast::BV::new_const(ctx, a_name, alen as u32);
                                 let update_a = a_new_bv._safe_eq(&a_ori_bv).unwrap();
 
-                                goal.assert(&a_ori_non_owing);
-                                goal.assert(&update_a);
-                                solver.assert(&a_ori_non_owing);
-                                solver.assert(&update_a);
+                                self.assert_constraint(ctx, goal, solver, &update_a, TraceKind::ParamPass);
 
                                 self.icx_slice_mut().var_mut()[au] = IntroVar::Init(a_new_bv);
                             } else {
@@ -1965,12 +2591,11 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                                 // if the aplace in field is a pointer (move a.f (ptr) => still hold)
                                 // the exact constraint is a'=a
                                 // this is for a'=a
-                                let a_name = new_local_name(au, bidx, 0).add("_param_pass");
+                                let a_name = self.new_local_name(au, bidx, 0).add("_param_pass");
                                 let a_new_bv = ast::BV::new_const(ctx, a_name, alen as u32);
                                 let update_a = a_new_bv._safe_eq(&a_ori_bv).unwrap();
 
-                                goal.assert(&update_a);
-                                solver.assert(&update_a);
+                                self.assert_constraint(ctx, goal, solver, &update_a, TraceKind::ParamPass);
                             } else {
                                 // if the aplace is a instance (move i.f => i.f=0)
                                 self.handle_drop(ctx, goal, solver, aplace, bidx, false);
@@ -2016,20 +2641,20 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                                 }
 
                                 // if the aplace is a pointer (ptr => still hold)
-                                // the exact constraint is a=0, a'=a
+                                // the exact constraint is a=0, a'=a, or a'=a if the callee borrows it
                                 // this is for a=0
-                                let a_zero_const = ast::BV::from_u64(ctx, 0, alen as u32);
-                                let a_ori_non_owing = a_ori_bv._safe_eq(&a_zero_const).unwrap();
+                                if !borrowed.contains(&au) {
+                                    let a_zero_const = ast::BV::from_u64(ctx, 0, alen as u32);
+                                    let a_ori_non_owing = a_ori_bv._safe_eq(&a_zero_const).unwrap();
+                                    self.assert_constraint(ctx, goal, solver, &a_ori_non_owing, TraceKind::ParamPass);
+                                }
 
                                 // this is for a'=a
-                                let a_name = new_local_name(au, bidx, 0).add("_param_pass");
+                                let a_name = self.new_local_name(au, bidx, 0).add("_param_pass");
                                 let a_new_bv = ast::BV::new_const(ctx, a_name, alen as u32);
                                 let update_a = a_new_bv._safe_eq(&a_ori_bv).unwrap();
 
-                                goal.assert(&a_ori_non_owing);
-                                goal.assert(&update_a);
-                                solver.assert(&a_ori_non_owing);
-                                solver.assert(&update_a);
+                                self.assert_constraint(ctx, goal, solver, &update_a, TraceKind::ParamPass);
 
                                 self.icx_slice_mut().var_mut()[au] = IntroVar::Init(a_new_bv);
 
@@ -2044,22 +2669,20 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                                     }
                                 }
 
-                                let a_name = new_local_name(au, bidx, 0).add("_param_pass");
+                                let a_name = self.new_local_name(au, bidx, 0).add("_param_pass");
                                 let a_new_bv = ast::BV::new_const(ctx, a_name, alen as u32);
                                 let update_a = a_new_bv._safe_eq(&a_ori_bv).unwrap();
 
-                                goal.assert(&update_a);
-                                solver.assert(&update_a);
+                                self.assert_constraint(ctx, goal, solver, &update_a, TraceKind::ParamPass);
                             }
                         },
                         1 => {
                             // this indicates that the operand is move without projection
-                            let a_name = new_local_name(au, bidx, 0).add("_param_pass");
+                            let a_name = self.new_local_name(au, bidx, 0).add("_param_pass");
                             let a_new_bv = ast::BV::new_const(ctx, a_name, alen as u32);
                             let update_a = a_new_bv._safe_eq(&a_ori_bv).unwrap();
 
-                            goal.assert(&update_a);
-                            solver.assert(&update_a);
+                            self.assert_constraint(ctx, goal, solver, &update_a, TraceKind::ParamPass);
                         },
                         _ => {
                             self.handle_intro_var_unsupported(au);
@@ -2071,6 +2694,18 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             }
         }
 
+        // the pointer returned by the modeled callee is a fresh allocation owned by the lvalue
+        if source_flag && model.is_some() {
+            let l_place_ty = dest.ty(&self.body().local_decls, self.tcx());
+            self.icx_slice_mut().taint_mut()[lu].insert(TyWithIndex::new(l_place_ty.ty, l_place_ty.variant_index));
+        }
+
+        // if the call unwinds, the args are moved but the return value is never assigned
+        if self.rcx().config().effective_unwind_mode() == UnwindMode::Enabled {
+            let unwind_slice = self.icx_slice().clone();
+            self.unwind_out_mut().insert(bidx, unwind_slice);
+        }
+
         // establish constraints for return value
         if self.icx_slice().var()[lu].is_unsupported() {
             self.handle_intro_var_unsupported(lu);
@@ -2115,8 +2750,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                     l_ori_bv = self.icx_slice_mut().var_mut()[lu].extract();
                     let l_zero_const = ast::BV::from_u64(ctx, 0, llen as u32);
                     let constraint_l_ori_zero = l_ori_bv._safe_eq(&l_zero_const).unwrap();
-                    goal.assert(&constraint_l_ori_zero);
-                    solver.assert(&constraint_l_ori_zero);
+                    self.assert_constraint(ctx, goal, solver, &constraint_l_ori_zero, TraceKind::Overwrite);
                     is_ctor = false;
                 } else {
                     // this branch means that the assignment is the constructor of the lvalue
@@ -2142,9 +2776,9 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                 llen = return_value_layout.layout().len();
 
                 let l_name = if is_ctor {
-                    new_local_name(lu, bidx, 0).add("_ctor_fn")
+                    self.new_local_name(lu, bidx, 0).add("_ctor_fn")
                 } else {
-                    new_local_name(lu, bidx, 0).add("_cover_fn")
+                    self.new_local_name(lu, bidx, 0).add("_cover_fn")
                 };
 
                 let l_layout_bv = ast::BV::from_u64(ctx, int_for_gen, llen as u32);
@@ -2152,8 +2786,8 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
 
                 let constraint_new_owning = l_new_bv._safe_eq(&l_layout_bv).unwrap();
 
-                goal.assert(&constraint_new_owning);
-                solver.assert(&constraint_new_owning);
+                let kind = if source_flag && model.is_none() { TraceKind::IntoRaw } else { TraceKind::CallReturn };
+                self.assert_constraint(ctx, goal, solver, &constraint_new_owning, kind);
 
                 self.icx_slice_mut().len_mut()[lu] =llen;
                 self.icx_slice_mut().var_mut()[lu] = IntroVar::Init(l_new_bv);
@@ -2179,23 +2813,21 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                     let l_f_zero_const = ast::BV::from_u64(ctx, 0, 1);
                     let constraint_l_f_ori_zero = extract_from_field._safe_eq(&l_f_zero_const).unwrap();
 
-                    goal.assert(&constraint_l_f_ori_zero);
-                    solver.assert(&constraint_l_f_ori_zero);
+                    self.assert_constraint(ctx, goal, solver, &constraint_l_f_ori_zero, TraceKind::Overwrite);
                 } else {
-                    let l_ori_name_ctor = new_local_name(lu, bidx, 0).add("_ctor_fn");
+                    let l_ori_name_ctor = self.new_local_name(lu, bidx, 0).add("_ctor_fn");
                     let l_ori_bv_ctor = ast::BV::new_const(ctx, l_ori_name_ctor, llen as u32);
                     let l_ori_zero = ast::BV::from_u64(ctx, 0, llen as u32);
                     let constraint_l_ctor_zero = l_ori_bv_ctor._safe_eq(&l_ori_zero).unwrap();
 
-                    goal.assert(&constraint_l_ctor_zero);
-                    solver.assert(&constraint_l_ctor_zero);
+                    self.assert_constraint(ctx, goal, solver, &constraint_l_ctor_zero, TraceKind::Init);
 
                     l_ori_bv = l_ori_zero;
                     self.icx_slice_mut().ty_mut()[lu] = TyWithIndex::new(l_local_ty, None);
                     self.icx_slice_mut().layout_mut()[lu] = return_value_layout.layout().clone();
                 }
 
-                let l_name = new_local_name(lu, bidx, 0);
+                let l_name = self.new_local_name(lu, bidx, 0);
                 let l_new_bv = ast::BV::new_const(ctx, l_name, llen as u32);
 
                 let update_field = if source_flag {
@@ -2221,8 +2853,8 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                 }
                 let update_filed_using_func = l_new_bv._safe_eq(&final_bv).unwrap();
 
-                goal.assert(&update_filed_using_func);
-                solver.assert(&update_filed_using_func);
+                let kind = if source_flag && model.is_none() { TraceKind::IntoRaw } else { TraceKind::CallReturn };
+                self.assert_constraint(ctx, goal, solver, &update_filed_using_func, kind);
 
                 self.icx_slice_mut().len_mut()[lu] = return_value_layout.layout().len();
                 self.icx_slice_mut().var_mut()[lu] = IntroVar::Init(l_new_bv);
@@ -2234,11 +2866,74 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         }
     }
 
+    // Move the owning bits of the value into or out of a ManuallyDrop, i.e., l'=r and r'=0. ManuallyDrop<T> is
+    // transparent, thus the lvalue takes the layout of the rvalue, and it is manually managed if it is the
+    // ManuallyDrop. Return false if the places are not locals or the rvalue is not tracked, then the call is
+    // handled as other calls.
+    pub(crate) fn handle_manually_drop(
+        &mut self,
+        ctx: &'ctx z3::Context,
+        goal: &'ctx z3::Goal<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
+        rplace: &Place<'tcx>,
+        dest: &Place<'tcx>,
+        bidx: usize,
+    ) -> bool {
+        if !rplace.projection.is_empty() || !dest.projection.is_empty() {
+            return false;
+        }
+
+        let lu:usize = dest.local.as_usize();
+        let ru:usize = rplace.local.as_usize();
+        if !self.icx_slice().var()[ru].is_init() || self.icx_slice().len()[ru] == 0 || self.icx_slice().var()[lu].is_unsupported() {
+            return false;
+        }
+
+        let l_adt = self.body().local_decls[dest.local].ty.ty_adt_def();
+        let is_wrap = l_adt.map_or(false, |adt_def| Some(adt_def.did()) == self.tcx().lang_items().manually_drop());
+        let kind = if is_wrap { TraceKind::Wrap } else { TraceKind::Unwrap };
+
+        let rlen = self.icx_slice().len()[ru];
+        let r_ori_bv = self.icx_slice_mut().var_mut()[ru].extract();
+
+        // the old value of the lvalue must not hold the ownership, e.g., in the next round of a loop
+        if self.icx_slice().var()[lu].is_init() {
+            let llen = self.icx_slice().len()[lu];
+            let l_ori_bv = self.icx_slice_mut().var_mut()[lu].extract();
+            let l_zero_const = ast::BV::from_u64(ctx, 0, llen as u32);
+            let constraint_l_ori_zero = l_ori_bv._safe_eq(&l_zero_const).unwrap();
+            self.assert_constraint(ctx, goal, solver, &constraint_l_ori_zero, TraceKind::Overwrite);
+        }
+        self.icx_slice_mut().ty_mut()[lu] = self.icx_slice().ty()[ru].clone();
+        self.icx_slice_mut().layout_mut()[lu] = self.icx_slice().layout()[ru].clone();
+        self.icx_slice_mut().len_mut()[lu] = rlen;
+
+        let l_name = self.new_local_name(lu, bidx, 0).add("_manual");
+        let r_name = self.new_local_name(ru, bidx, 0).add("_manual");
+        let l_new_bv = ast::BV::new_const(ctx, l_name, rlen as u32);
+        let r_new_bv = ast::BV::new_const(ctx, r_name, rlen as u32);
+
+        let r_zero_const = ast::BV::from_u64(ctx, 0, rlen as u32);
+        let r_non_owning = r_new_bv._safe_eq(&r_zero_const).unwrap();
+        let l_owning = l_new_bv._safe_eq(&r_ori_bv).unwrap();
+
+        self.assert_constraint(ctx, goal, solver, &r_non_owning, kind);
+        self.assert_constraint(ctx, goal, solver, &l_owning, kind);
+
+        self.icx_slice_mut().var_mut()[lu] = IntroVar::Init(l_new_bv);
+        self.icx_slice_mut().var_mut()[ru] = IntroVar::Init(r_new_bv);
+        self.handle_taint(lu, ru);
+        if is_wrap {
+            self.manual_locals_mut().insert(lu);
+        }
+        true
+    }
+
     pub(crate) fn handle_return(
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         sw: &Stopwatch,
         bidx: usize,
     ) {
@@ -2253,7 +2948,38 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
             false,
         );
 
-        // when whole function return => we need to check every variable is freed
+        self.check_exit(ctx, goal, solver, sw, bidx, LeakPath::Return);
+    }
+
+    pub(crate) fn handle_resume(
+        &mut self,
+        ctx: &'ctx z3::Context,
+        goal: &'ctx z3::Goal<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
+        sw: &Stopwatch,
+        bidx: usize,
+    ) {
+        // the return value is never assigned when unwinding, thus we only check the locals
+        self.check_exit(ctx, goal, solver, sw, bidx, LeakPath::Unwind);
+    }
+
+    pub(crate) fn check_exit(
+        &mut self,
+        ctx: &'ctx z3::Context,
+        goal: &'ctx z3::Goal<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
+        sw: &Stopwatch,
+        bidx: usize,
+        path: LeakPath,
+    ) {
+        // in the path-sensitive grain, the exit is checked on the paths reaching it only
+        let path_cond = if self.is_path_sensitive() { self.paths()[bidx].clone() } else { None };
+
+        // when whole function return => we need to check every variable is freed, the script keeps
+        // each constraint both untracked and tracked by the exit label for extracting the unsat core
+        let exit_label = ast::Bool::new_const(ctx, EXIT_LABEL);
+        let mut returns: Vec<(usize, String, String)> = Vec::default();
+        let mut bv_widths: Vec<usize> = Vec::default();
         for (iidx, var) in self.icx_slice().var.iter().enumerate() {
             let len = self.icx_slice().len()[iidx];
             if len == 0 { continue; }
@@ -2263,7 +2989,10 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
 
                 let var_ori_bv = var.extract();
 
-                let return_name = new_local_name(iidx, bidx, 0).add("_return");
+                let return_name = match path {
+                    LeakPath::Return => self.new_local_name(iidx, bidx, 0).add("_return"),
+                    LeakPath::Unwind => self.new_local_name(iidx, bidx, 0).add("_resume"),
+                };
                 let var_return_bv = ast::BV::new_const(ctx, return_name, len as u32);
 
                 let zero_const = ast::BV::from_u64(ctx, 0, len as u32);
@@ -2272,41 +3001,24 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                 let var_freed = var_return_bv._safe_eq(&zero_const).unwrap();
 
                 let args = &[&var_update, &var_freed];
-                let constraint_return = ast::Bool::and(ctx, args);
+                let constraint_return = match &path_cond {
+                    Some(cond) => cond.implies(&ast::Bool::and(ctx, args)),
+                    None => ast::Bool::and(ctx, args),
+                };
 
                 goal.assert(&constraint_return);
-                solver.assert(&constraint_return);
+                let constraint_tracked = exit_label.implies(&constraint_return);
+                returns.push((iidx, solver.to_smt2(&constraint_return), solver.to_smt2(&constraint_tracked)));
+                bv_widths.push(len);
             }
         }
 
-        let sec_build = sw.elapsed_ms();
-
-        let result = solver.check();
-        let model = solver.get_model();
-
-        let sec_solve = sw.elapsed_ms() - sec_build;
+        let path_cond = path_cond.map(|cond| solver.to_smt2(&cond));
+        let query = ExitQuery::new(bidx, path, path_cond, returns, self.taint_flag, (self.trace_steps().len(), bv_widths));
+        solver.check_exit(query);
 
+        let sec_build = sw.elapsed_ms();
         self.add_time_build(sec_build);
-        self.add_time_solve(sec_solve);
-
-        // if is_z3_goal_verbose() {
-        //     let g = format!("{}", goal);
-        //     println!("{}\n", g.color(Color::LightGray).bold());
-        //     if model.is_some() {
-        //         println!("{}", format!("{}", model.unwrap()).color(Color::LightCyan).bold());
-        //     }
-        // }
-
-        // println!("{}", self.body().local_decls.display());
-        // println!("{}", self.body().basic_blocks().display());
-        // let g = format!("{}", goal);
-        // println!("{}\n", g.color(Color::LightGray).bold());
-
-
-        // if result == z3::SatResult::Unsat && self.taint_flag {
-        //     println!("{} {:?} {:?}", format!("{:?}", result).color(Color::LightCyan).bold(), self.did(), self.body().span);
-        //
-        // }
 
     }
 
@@ -2314,7 +3026,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         &mut self,
         ctx: &'ctx z3::Context,
         goal: &'ctx z3::Goal<'ctx>,
-        solver: &'ctx z3::Solver<'ctx>,
+        solver: &'ctx ConstraintScript<'ctx>,
         dest: &Place<'tcx>,
         bidx: usize,
         recovery: bool,
@@ -2335,7 +3047,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         let ori_bv = self.icx_slice().var()[u].extract();
 
         let f = extract_projection(dest);
-        if f.is_unsupported() {
+        if f.is_unsupported() || (f.has_field() && !self.rcx().config().grain().is_field_sensitive()) {
             self.handle_intro_var_unsupported(u);
             return;
         }
@@ -2346,7 +3058,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                 // reverse the ownership layout and using and operator
                 if recovery {
                     // recovery for pointer, clear all
-                    let name = new_local_name(u, bidx, 0).add("_drop_recovery");
+                    let name = self.new_local_name(u, bidx, 0).add("_drop_recovery");
                     let new_bv = ast::BV::new_const(ctx, name, len as u32);
                     let zero_bv = ast::BV::from_u64(ctx, 0, len as u32);
 
@@ -2354,13 +3066,12 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
 
                     let constraint_recovery = new_bv._eq(&and_bv);
 
-                    goal.assert(&constraint_recovery);
-                    solver.assert(&constraint_recovery);
+                    self.assert_constraint(ctx, goal, solver, &constraint_recovery, TraceKind::Recover);
 
                     self.icx_slice_mut().var_mut()[u] = IntroVar::Init(new_bv);
                 } else {
                     // is not recovery for pointer, just normal drop
-                    let name = new_local_name(u, bidx, 0).add("_drop_all");
+                    let name = self.new_local_name(u, bidx, 0).add("_drop_all");
                     let new_bv = ast::BV::new_const(ctx, name, len as u32);
                     let int_for_rust_bv = rustbv_to_int(&rust_bv);
                     let int_bv_const = ast::BV::from_u64(ctx, int_for_rust_bv, len as u32);
@@ -2369,8 +3080,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
 
                     let constraint_reverse = new_bv._eq(&and_bv);
 
-                    goal.assert(&constraint_reverse);
-                    solver.assert(&constraint_reverse);
+                    self.assert_constraint(ctx, goal, solver, &constraint_reverse, TraceKind::Drop);
 
                     self.icx_slice_mut().var_mut()[u] = IntroVar::Init(new_bv);
                 }
@@ -2382,9 +3092,9 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                 if index_needed >= rust_bv.len() { return; }
 
                 let name = if recovery {
-                    new_local_name(u, bidx, 0).add("_drop_f_recovery")
+                    self.new_local_name(u, bidx, 0).add("_drop_f_recovery")
                 } else {
-                    new_local_name(u, bidx, 0).add("_drop_f")
+                    self.new_local_name(u, bidx, 0).add("_drop_f")
                 };
                 let new_bv = ast::BV::new_const(ctx, name, len as u32);
 
@@ -2393,8 +3103,8 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
                     // the default ownership is false (non-owning) somehow, we just reverse it before
                     let constraint_update = new_bv._eq(&ori_bv);
 
-                    goal.assert(&constraint_update);
-                    solver.assert(&constraint_update);
+                    let kind = if recovery { TraceKind::Recover } else { TraceKind::Drop };
+                    self.assert_constraint(ctx, goal, solver, &constraint_update, kind);
 
                     self.icx_slice_mut().var_mut()[u] = IntroVar::Init(new_bv);
                 } else {
@@ -2413,8 +3123,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
 
                     let constraint_free_f = new_bv._safe_eq(&final_bv).unwrap();
 
-                    goal.assert(&constraint_free_f);
-                    solver.assert(&constraint_free_f);
+                    self.assert_constraint(ctx, goal, solver, &constraint_free_f, TraceKind::Drop);
 
                     self.icx_slice_mut().var_mut()[u] = IntroVar::Init(new_bv);
                 }
@@ -2461,86 +3170,7 @@ impl<'tcx, 'ctx, 'a> IntroFlowAnalysis<'tcx, 'ctx, 'a> {
         ty: Ty<'tcx>,
         variant: Option<VariantIdx>
     ) -> OwnershipLayoutResult {
-        match ty.kind() {
-            TyKind::Array( .. ) => {
-                let mut res = OwnershipLayoutResult::new();
-                let mut default_ownership = DefaultOwnership::new(self.tcx(), self.owner());
-
-                ty.visit_with(&mut default_ownership);
-                res.update_from_default_ownership_visitor(&mut default_ownership);
-
-                res
-            },
-            TyKind::Tuple( tuple_ty_list ) => {
-                let mut res = OwnershipLayoutResult::new();
-
-                for tuple_ty in tuple_ty_list.iter() {
-                    let mut default_ownership = DefaultOwnership::new(self.tcx(), self.owner());
-
-                    tuple_ty.visit_with(&mut default_ownership);
-                    res.update_from_default_ownership_visitor(&mut default_ownership);
-                }
-
-                res
-            },
-            TyKind::Adt( adtdef, substs ) => {
-                // check the ty is or is not an enum and the variant of this enum is or is not given
-                if adtdef.is_enum() && variant.is_none() {
-                    return OwnershipLayoutResult::new();
-                }
-
-                let mut res = OwnershipLayoutResult::new();
-
-                // check the ty if it is a struct or union
-                if adtdef.is_struct() || adtdef.is_union() {
-                    for field in adtdef.all_fields() {
-                        let field_ty = field.ty(self.tcx(), substs);
-
-                        let mut default_ownership = DefaultOwnership::new(self.tcx(), self.owner());
-
-                        field_ty.visit_with(&mut default_ownership);
-                        res.update_from_default_ownership_visitor(&mut default_ownership);
-                    }
-                }
-                // check the ty which is an enum with a exact variant idx
-                else if adtdef.is_enum() {
-                    let vidx = variant.unwrap();
-
-                    for field in &adtdef.variants()[vidx].fields {
-                        let field_ty = field.ty(self.tcx(), substs);
-
-                        let mut default_ownership = DefaultOwnership::new(self.tcx(), self.owner());
-
-                        field_ty.visit_with(&mut default_ownership);
-                        res.update_from_default_ownership_visitor(&mut default_ownership);
-                    }
-                }
-                res
-            },
-            TyKind::Param( .. ) => {
-                let mut res = OwnershipLayoutResult::new();
-                res.set_requirement(true);
-                res.set_param(true);
-                res.set_owned(true);
-                res.layout_mut().push(RawTypeOwner::Owned);
-                res
-            },
-            TyKind::RawPtr( .. ) => {
-                let mut res = OwnershipLayoutResult::new();
-                res.set_requirement(true);
-                res.layout_mut().push(RawTypeOwner::Unowned);
-                res
-            },
-            TyKind::Ref( .. ) => {
-                let mut res = OwnershipLayoutResult::new();
-                res.set_requirement(true);
-                res.layout_mut().push(RawTypeOwner::Unowned);
-                res
-            },
-            _ => {
-                OwnershipLayoutResult::new()
-            },
-        }
+        extract_default_ty_layout(self.tcx(), self.owner(), ty, variant)
     }
 
     pub(crate) fn generate_ptr_layout(
@@ -2617,7 +3247,95 @@ fn new_local_name(local: usize, bidx: usize, sidx: usize) -> String {
     s
 }
 
-fn is_place_containing_ptr(ty: &Ty) -> bool {
+pub(crate) fn extract_default_ty_layout<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    owner: &AdtOwner,
+    ty: Ty<'tcx>,
+    variant: Option<VariantIdx>
+) -> OwnershipLayoutResult {
+    match ty.kind() {
+        TyKind::Array( .. ) => {
+            let mut res = OwnershipLayoutResult::new();
+            let mut default_ownership = DefaultOwnership::new(tcx, owner);
+
+            ty.visit_with(&mut default_ownership);
+            res.update_from_default_ownership_visitor(&mut default_ownership);
+
+            res
+        },
+        TyKind::Tuple( tuple_ty_list ) => {
+            let mut res = OwnershipLayoutResult::new();
+
+            for tuple_ty in tuple_ty_list.iter() {
+                let mut default_ownership = DefaultOwnership::new(tcx, owner);
+
+                tuple_ty.visit_with(&mut default_ownership);
+                res.update_from_default_ownership_visitor(&mut default_ownership);
+            }
+
+            res
+        },
+        TyKind::Adt( adtdef, substs ) => {
+            // check the ty is or is not an enum and the variant of this enum is or is not given
+            if adtdef.is_enum() && variant.is_none() {
+                return OwnershipLayoutResult::new();
+            }
+
+            let mut res = OwnershipLayoutResult::new();
+
+            // check the ty if it is a struct or union
+            if adtdef.is_struct() || adtdef.is_union() {
+                for field in adtdef.all_fields() {
+                    let field_ty = field.ty(tcx, substs);
+
+                    let mut default_ownership = DefaultOwnership::new(tcx, owner);
+
+                    field_ty.visit_with(&mut default_ownership);
+                    res.update_from_default_ownership_visitor(&mut default_ownership);
+                }
+            }
+            // check the ty which is an enum with a exact variant idx
+            else if adtdef.is_enum() {
+                let vidx = variant.unwrap();
+
+                for field in &adtdef.variants()[vidx].fields {
+                    let field_ty = field.ty(tcx, substs);
+
+                    let mut default_ownership = DefaultOwnership::new(tcx, owner);
+
+                    field_ty.visit_with(&mut default_ownership);
+                    res.update_from_default_ownership_visitor(&mut default_ownership);
+                }
+            }
+            res
+        },
+        TyKind::Param( .. ) => {
+            let mut res = OwnershipLayoutResult::new();
+            res.set_requirement(true);
+            res.set_param(true);
+            res.set_owned(true);
+            res.layout_mut().push(RawTypeOwner::Owned);
+            res
+        },
+        TyKind::RawPtr( .. ) => {
+            let mut res = OwnershipLayoutResult::new();
+            res.set_requirement(true);
+            res.layout_mut().push(RawTypeOwner::Unowned);
+            res
+        },
+        TyKind::Ref( .. ) => {
+            let mut res = OwnershipLayoutResult::new();
+            res.set_requirement(true);
+            res.layout_mut().push(RawTypeOwner::Unowned);
+            res
+        },
+        _ => {
+            OwnershipLayoutResult::new()
+        },
+    }
+}
+
+pub(crate) fn is_place_containing_ptr(ty: &Ty) -> bool {
     match ty.kind() {
         TyKind::Tuple( tuple_ty_list ) => {
             for tuple_ty in tuple_ty_list.iter() {
@@ -2687,6 +3405,71 @@ impl<'tcx> ProjectionSupport<'tcx> {
 
 }
 
+// Build the reports of one exit that is not proven free of leaks. The cores of the leaking locals are
+// mapped back to the trace steps, and the exit itself is the last step of each trace. The leaking locals
+// holding a ManuallyDrop are never released by hand, they are reported apart from other leaks.
+fn exit_leak_reports(
+    rcx: &RlcGlobalCtxt,
+    did: DefId,
+    trace_steps: &[TraceStep],
+    manual_locals: &HashSet<usize>,
+    exit: &ExitResult,
+) -> Vec<LeakReport> {
+    let body = mir_body(rcx.tcx(), did);
+    let fn_span = body.span;
+    let return_span = body.basic_blocks()[BasicBlock::from(exit.bidx())].terminator().source_info.span;
+    let grain = rcx.config().grain();
+
+    if exit.leaking().is_empty() {
+        let mut report = LeakReport::new(did, fn_span, None, None, return_span, exit.verdict(), exit.path());
+        report.set_grain(grain);
+        return vec![report];
+    }
+
+    let mut reports = Vec::default();
+    for (iidx, core) in exit.leaking() {
+        let local_span = body.local_decls[Local::from_usize(*iidx)].source_info.span;
+        let mut report = LeakReport::new(did, fn_span, Some(*iidx), Some(local_span), return_span, exit.verdict(), exit.path());
+        for idx in core {
+            let step = trace_steps[*idx];
+            if report.trace().last() != Some(&step) {
+                report.trace_mut().push(step);
+            }
+        }
+        report.trace_mut().push(TraceStep::new(exit.bidx(), TraceKind::Exit(exit.path()), return_span));
+        report.set_grain(grain);
+        report.set_manually_managed(manual_locals.contains(iidx));
+        reports.push(report);
+    }
+    reports
+}
+
+// The reports of a function of the dependencies point to the local call site it is reached from, the
+// entry is not cached since it depends on the local crate.
+fn set_cross_crate_entry(rcx: &RlcGlobalCtxt, did: DefId, reports: &mut [LeakReport]) {
+    if let Some(entry) = rcx.cross_crate_fns().get(&did) {
+        reports.iter_mut().for_each(|report| report.set_entry(*entry));
+    }
+}
+
+// The leaks on the unwinding paths are kept only if the same local does not leak on the
+// normal return, thus each unwind report indicates a leak that only happens on panic.
+fn retain_panic_only_reports(reports: &mut Vec<LeakReport>) {
+    let returned: Vec<Option<usize>> = reports
+        .iter()
+        .filter(|report| report.path() == LeakPath::Return)
+        .map(|report| report.local())
+        .collect();
+    reports.retain(|report| {
+        report.path() == LeakPath::Return || !returned.contains(&report.local())
+    });
+}
+
+// The z3 integer of the value of SwitchInt or the discriminant, None if it is out of u64.
+fn int_from_u128(ctx: &z3::Context, value: u128) -> Option<ast::Int> {
+    u64::try_from(value).ok().map(|value| ast::Int::from_u64(ctx, value))
+}
+
 fn has_projection(place: &Place) -> bool {
     return if place.projection.len() > 0 { true } else { false }
 }
@@ -2797,11 +3580,174 @@ fn help_debug_goal_term<'tcx, 'ctx>(
     goal.assert(&dbg_bool);
 }
 
+// Whether the discriminant of each local is the same wherever it is read, i.e., the local is written in
+// at most one block out of loops (or never written for the args), and it is never borrowed mutably. The
+// body is scanned once when the visitor is created.
+pub(crate) fn stable_discriminants(body: &Body, graph: &Graph) -> Vec<bool> {
+    let v_len = body.local_decls.len();
+    let mut writes: Vec<HashSet<usize>> = vec![HashSet::default() ; v_len];
+    let mut borrowed = vec![false ; v_len];
+
+    for (bidx, data) in body.basic_blocks().iter_enumerated() {
+        let mut write = |place: &Place| {
+            if !place.is_indirect() {
+                writes[place.local.as_usize()].insert(bidx.as_usize());
+            }
+        };
+        for stmt in data.statements.iter() {
+            match &stmt.kind {
+                StatementKind::Assign(box(place, rvalue)) => {
+                    write(place);
+                    match rvalue {
+                        Rvalue::Ref(_, BorrowKind::Mut { .. }, rplace)
+                        | Rvalue::AddressOf(_, rplace) if !rplace.is_indirect() => {
+                            borrowed[rplace.local.as_usize()] = true;
+                        },
+                        _ => (),
+                    }
+                },
+                StatementKind::SetDiscriminant { place, .. }
+                | StatementKind::Deinit(place) => write(place),
+                _ => (),
+            }
+        }
+        match &data.terminator().kind {
+            TerminatorKind::Call { destination: place, .. }
+            | TerminatorKind::DropAndReplace { place, .. }
+            | TerminatorKind::Yield { resume_arg: place, .. } => write(place),
+            _ => (),
+        }
+    }
+
+    (0..v_len)
+        .map(|local| {
+            let is_arg = local >= 1 && local <= body.arg_count;
+            !borrowed[local] && match writes[local].len() {
+                0 => true,
+                1 => !is_arg && !graph.is_in_loop(*writes[local].iter().next().unwrap()),
+                _ => false,
+            }
+        })
+        .collect()
+}
+
+// The place that each local is assigned from by a borrow (true) or a move (false), the last assignment
+// of the body wins. The body is scanned once when the visitor is created, and `ref_target` follows them.
+pub(crate) fn ref_assigns<'tcx>(body: &Body<'tcx>) -> HashMap<Local, (Place<'tcx>, bool)> {
+    let mut assigns = HashMap::default();
+    for data in body.basic_blocks().iter() {
+        for stmt in data.statements.iter() {
+            if let StatementKind::Assign(box(place, rvalue)) = &stmt.kind {
+                if !place.projection.is_empty() { continue; }
+                match rvalue {
+                    Rvalue::Ref(_, _, rplace)
+                    | Rvalue::AddressOf(_, rplace) => { assigns.insert(place.local, (*rplace, true)); },
+                    Rvalue::Use(Operand::Move(rplace))
+                    | Rvalue::Use(Operand::Copy(rplace)) => { assigns.insert(place.local, (*rplace, false)); },
+                    _ => (),
+                }
+            }
+        }
+    }
+    assigns
+}
+
 type Disc = Option<VariantIdx>;
 
+// The function in the order of the analysis, merged after the pool finishes.
+enum SubmittedFn {
+    // the reports taken from the cache
+    Cached(DefId, Vec<LeakReport>),
+    // reached after the budget is exhausted, thus neither visited nor solved
+    OverBudget(DefId),
+    // submitted to the solver pool with its cache key, the trace steps and the manually managed locals
+    Solving(DefId, Option<String>, Vec<TraceStep>, HashSet<usize>),
+}
+
 #[inline(always)]
 fn debug_icx_slice(icxs: &IcxSliceFroBlock) {
     for index in 0..icxs.len().len() {
         println!("index:{} {:?} {:?} {:?}", index, icxs.len[index], icxs.var[index], icxs.ty[index]);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{with_tcx_of, RlcConfig};
+    use crate::analysis::flow_analysis::NodeOrder;
+    use rustc_middle::mir::VarDebugInfoContents;
+
+    // Run the test on the function `f` of the source with its locals by names, the graph of its body is
+    // in the global context as the flow analysis orders it.
+    fn with_fn_f<R: Send>(
+        name: &str,
+        source: &str,
+        test: impl for<'tcx> FnOnce(&RlcGlobalCtxt<'tcx>, DefId, HashMap<String, Local>) -> R + Send,
+    ) -> R {
+        with_tcx_of(name, source, |tcx| {
+            let did = tcx.hir()
+                .body_owners()
+                .map(|did| did.to_def_id())
+                .find(|did| tcx.def_path_str(*did) == "f")
+                .expect("the crate has a function f");
+            let body = mir_body(tcx, did);
+            let mut path = NodeOrder::new(body);
+            path.collect_edges(UnwindMode::Disabled);
+            path.topo_order();
+            let mut rcx = RlcGlobalCtxt::new(tcx, RlcConfig::default());
+            rcx.mir_graph_mut().insert(did, path.graph().clone());
+            let locals: HashMap<String, Local> = body.var_debug_info
+                .iter()
+                .filter_map(|info| match &info.value {
+                    VarDebugInfoContents::Place(place) => Some((info.name.to_string(), place.local)),
+                    _ => None,
+                })
+                .collect();
+            test(&rcx, did, locals)
+        })
+    }
+
+    #[test]
+    fn stable_discriminants_of_locals() {
+        let source = "pub fn f(a: Option<u8>, c: bool) {\n\
+                          let x = Some(1u8);\n\
+                          let mut y = None;\n\
+                          if c { y = Some(2u8); }\n\
+                          let mut z = Some(3u8);\n\
+                          let r = &mut z;\n\
+                          for i in 0..2u8 { let v = Some(i); drop(v); }\n\
+                          drop((a, x, y, r));\n\
+                      }\n";
+        with_fn_f("stable_discriminants", source, |rcx, did, locals| {
+            let stable = stable_discriminants(mir_body(rcx.tcx(), did), &rcx.mir_graph()[&did]);
+            let is_stable = |name: &str| stable[locals[name].as_usize()];
+            // the arg is never written, and the local is written once out of loops
+            assert!(is_stable("a"));
+            assert!(is_stable("x"));
+            // written in two blocks, borrowed mutably, and written in a loop
+            assert!(!is_stable("y"));
+            assert!(!is_stable("z"));
+            assert!(!is_stable("v"));
+        })
+    }
+
+    #[test]
+    fn ref_target_through_reborrows_and_moves() {
+        let source = "pub fn f() {\n\
+                          let mut m = std::mem::ManuallyDrop::new(Box::new(1u8));\n\
+                          let r = &mut m;\n\
+                          let rr = &mut *r;\n\
+                          let moved = rr;\n\
+                          unsafe { std::mem::ManuallyDrop::drop(moved) };\n\
+                      }\n";
+        with_fn_f("ref_target", source, |rcx, did, locals| {
+            let mut unique = Unique::new();
+            let intro_visitor = IntroFlowAnalysis::new(rcx, did, &mut unique);
+            assert_eq!(intro_visitor.ref_target(locals["r"]), Some(locals["m"]));
+            assert_eq!(intro_visitor.ref_target(locals["moved"]), Some(locals["m"]));
+            // the value returned by a call is not a reference to any local
+            assert_eq!(intro_visitor.ref_target(locals["m"]), None);
+        })
+    }
+}
@@ -0,0 +1,296 @@
+use rustc_middle::ty::TyCtxt;
+use rustc_span::def_id::DefId;
+use rustc_span::symbol::sym;
+
+use std::fmt::{Display, Formatter};
+
+// KnownFn is the registry of well-known functions that have a fixed ownership semantic.
+// The functions are resolved through diagnostic items and lang items first, and through the
+// def path as a fallback, thus the result does not depend on the DefIndex of the toolchain.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum KnownFn {
+    // std::mem::drop(T): release the arg
+    MemDrop,
+    // std::mem::forget(T): the arg is consumed without being released
+    MemForget,
+    // Box::into_raw(Box<T>) -> *mut T
+    BoxIntoRaw,
+    // Box::from_raw(*mut T) -> Box<T>
+    BoxFromRaw,
+    // Box::leak(Box<T>) -> &mut T
+    BoxLeak,
+    // ManuallyDrop::new(T) -> ManuallyDrop<T>
+    ManuallyDropNew,
+    // ManuallyDrop::drop(&mut ManuallyDrop<T>)
+    ManuallyDropDrop,
+    // ManuallyDrop::into_inner(ManuallyDrop<T>) -> T
+    ManuallyDropIntoInner,
+    // ManuallyDrop::take(&mut ManuallyDrop<T>) -> T
+    ManuallyDropTake,
+    // Arc::into_raw(Arc<T>) -> *const T
+    ArcIntoRaw,
+    // Arc::from_raw(*const T) -> Arc<T>
+    ArcFromRaw,
+    // CString::into_raw(CString) -> *mut c_char
+    CStringIntoRaw,
+    // CString::from_raw(*mut c_char) -> CString
+    CStringFromRaw,
+    // Vec::from_raw_parts(*mut T, usize, usize) -> Vec<T>
+    VecFromRawParts,
+    // String::from_raw_parts(*mut u8, usize, usize) -> String
+    StringFromRawParts,
+    // Vec::into_raw_parts(Vec<T>) -> (*mut T, usize, usize)
+    VecIntoRawParts,
+    // String::into_raw_parts(String) -> (*mut u8, usize, usize)
+    StringIntoRawParts,
+    // the accessors of the raw parts, they keep the ownership in the value
+    // Vec::as_ptr(&Vec<T>) -> *const T
+    VecAsPtr,
+    // Vec::as_mut_ptr(&mut Vec<T>) -> *mut T
+    VecAsMutPtr,
+    // str::as_ptr(&str) -> *const u8, the pointer of a String through Deref
+    StrAsPtr,
+    // str::as_mut_ptr(&mut str) -> *mut u8
+    StrAsMutPtr,
+    // Vec::len(&Vec<T>) -> usize
+    VecLen,
+    // String::len(&String) -> usize
+    StringLen,
+    // Vec::capacity(&Vec<T>) -> usize
+    VecCapacity,
+    // String::capacity(&String) -> usize
+    StringCapacity,
+    // Vec::shrink_to_fit(&mut Vec<T>): the capacity becomes the length
+    VecShrinkToFit,
+    // String::shrink_to_fit(&mut String)
+    StringShrinkToFit,
+}
+
+impl KnownFn {
+    pub fn resolve(tcx: TyCtxt<'_>, did: DefId) -> Option<Self> {
+        if tcx.is_diagnostic_item(sym::mem_drop, did) {
+            return Some(KnownFn::MemDrop);
+        }
+        if tcx.is_diagnostic_item(sym::mem_forget, did) {
+            return Some(KnownFn::MemForget);
+        }
+
+        // the inherent methods are resolved by the adt of the impl block and the name of the method
+        if let Some(impl_did) = tcx.impl_of_method(did) {
+            if tcx.type_of(impl_did).is_str() {
+                match tcx.item_name(did).as_str() {
+                    "as_ptr" => return Some(KnownFn::StrAsPtr),
+                    "as_mut_ptr" => return Some(KnownFn::StrAsMutPtr),
+                    _ => (),
+                }
+            }
+            if let Some(adt_def) = tcx.type_of(impl_did).ty_adt_def() {
+                let adt_did = adt_def.did();
+                let name = tcx.item_name(did);
+                let known = if Some(adt_did) == tcx.lang_items().owned_box() {
+                    match name.as_str() {
+                        "into_raw" => Some(KnownFn::BoxIntoRaw),
+                        "from_raw" => Some(KnownFn::BoxFromRaw),
+                        "leak" => Some(KnownFn::BoxLeak),
+                        _ => None,
+                    }
+                } else if Some(adt_did) == tcx.lang_items().manually_drop() {
+                    match name.as_str() {
+                        "new" => Some(KnownFn::ManuallyDropNew),
+                        "drop" => Some(KnownFn::ManuallyDropDrop),
+                        "into_inner" => Some(KnownFn::ManuallyDropIntoInner),
+                        "take" => Some(KnownFn::ManuallyDropTake),
+                        _ => None,
+                    }
+                } else if tcx.is_diagnostic_item(sym::Arc, adt_did) {
+                    match name.as_str() {
+                        "into_raw" => Some(KnownFn::ArcIntoRaw),
+                        "from_raw" => Some(KnownFn::ArcFromRaw),
+                        _ => None,
+                    }
+                } else if tcx.is_diagnostic_item(sym::cstring_type, adt_did) {
+                    match name.as_str() {
+                        "into_raw" => Some(KnownFn::CStringIntoRaw),
+                        "from_raw" => Some(KnownFn::CStringFromRaw),
+                        _ => None,
+                    }
+                } else if tcx.is_diagnostic_item(sym::Vec, adt_did) {
+                    match name.as_str() {
+                        "from_raw_parts" => Some(KnownFn::VecFromRawParts),
+                        "into_raw_parts" => Some(KnownFn::VecIntoRawParts),
+                        "as_ptr" => Some(KnownFn::VecAsPtr),
+                        "as_mut_ptr" => Some(KnownFn::VecAsMutPtr),
+                        "len" => Some(KnownFn::VecLen),
+                        "capacity" => Some(KnownFn::VecCapacity),
+                        "shrink_to_fit" => Some(KnownFn::VecShrinkToFit),
+                        _ => None,
+                    }
+                } else if tcx.is_diagnostic_item(sym::String, adt_did) {
+                    match name.as_str() {
+                        "from_raw_parts" => Some(KnownFn::StringFromRawParts),
+                        "into_raw_parts" => Some(KnownFn::StringIntoRawParts),
+                        "len" => Some(KnownFn::StringLen),
+                        "capacity" => Some(KnownFn::StringCapacity),
+                        "shrink_to_fit" => Some(KnownFn::StringShrinkToFit),
+                        _ => None,
+                    }
+                } else {
+                    None
+                };
+                if known.is_some() {
+                    return known;
+                }
+            }
+        }
+
+        Self::resolve_by_def_path(&tcx.def_path_str(did))
+    }
+
+    // The fallback for the items without diagnostic items, e.g., built with a `no_std` sysroot.
+    // The generic args in the path are erased before matching, e.g., `Box::<T, A>::into_raw`.
+    fn resolve_by_def_path(path: &str) -> Option<Self> {
+        let mut erased = String::with_capacity(path.len());
+        let mut depth = 0;
+        for c in path.chars() {
+            match c {
+                '<' => depth += 1,
+                '>' => depth -= 1,
+                _ if depth == 0 => erased.push(c),
+                _ => (),
+            }
+        }
+        let erased = erased.replace("::::", "::");
+
+        let krate = erased.split("::").next().unwrap_or_default();
+        if !matches!(krate, "std" | "core" | "alloc") {
+            return None;
+        }
+        let tail = &erased[krate.len()..];
+        match tail {
+            "::mem::drop" => Some(KnownFn::MemDrop),
+            "::mem::forget" => Some(KnownFn::MemForget),
+            "::boxed::Box::into_raw" => Some(KnownFn::BoxIntoRaw),
+            "::boxed::Box::from_raw" => Some(KnownFn::BoxFromRaw),
+            "::boxed::Box::leak" => Some(KnownFn::BoxLeak),
+            "::mem::ManuallyDrop::new" => Some(KnownFn::ManuallyDropNew),
+            "::mem::ManuallyDrop::drop" => Some(KnownFn::ManuallyDropDrop),
+            "::mem::ManuallyDrop::into_inner" => Some(KnownFn::ManuallyDropIntoInner),
+            "::mem::ManuallyDrop::take" => Some(KnownFn::ManuallyDropTake),
+            "::sync::Arc::into_raw" => Some(KnownFn::ArcIntoRaw),
+            "::sync::Arc::from_raw" => Some(KnownFn::ArcFromRaw),
+            "::ffi::CString::into_raw" => Some(KnownFn::CStringIntoRaw),
+            "::ffi::CString::from_raw" => Some(KnownFn::CStringFromRaw),
+            "::vec::Vec::from_raw_parts" => Some(KnownFn::VecFromRawParts),
+            "::string::String::from_raw_parts" => Some(KnownFn::StringFromRawParts),
+            "::vec::Vec::into_raw_parts" => Some(KnownFn::VecIntoRawParts),
+            "::string::String::into_raw_parts" => Some(KnownFn::StringIntoRawParts),
+            "::vec::Vec::as_ptr" => Some(KnownFn::VecAsPtr),
+            "::vec::Vec::as_mut_ptr" => Some(KnownFn::VecAsMutPtr),
+            "::str::as_ptr" => Some(KnownFn::StrAsPtr),
+            "::str::as_mut_ptr" => Some(KnownFn::StrAsMutPtr),
+            "::vec::Vec::len" => Some(KnownFn::VecLen),
+            "::string::String::len" => Some(KnownFn::StringLen),
+            "::vec::Vec::capacity" => Some(KnownFn::VecCapacity),
+            "::string::String::capacity" => Some(KnownFn::StringCapacity),
+            "::vec::Vec::shrink_to_fit" => Some(KnownFn::VecShrinkToFit),
+            "::string::String::shrink_to_fit" => Some(KnownFn::StringShrinkToFit),
+            _ => None,
+        }
+    }
+
+    // The functions that turn the owning arg into a pointer, the ownership is kept by the pointer.
+    pub fn is_source(&self) -> bool {
+        matches!(self, KnownFn::BoxIntoRaw | KnownFn::BoxLeak | KnownFn::ArcIntoRaw | KnownFn::CStringIntoRaw
+            | KnownFn::VecIntoRawParts | KnownFn::StringIntoRawParts)
+    }
+
+    // The functions that take the ownership carried by the pointer in the first arg.
+    pub fn is_recovery(&self) -> bool {
+        matches!(self, KnownFn::BoxFromRaw | KnownFn::ArcFromRaw | KnownFn::CStringFromRaw | KnownFn::VecFromRawParts
+            | KnownFn::StringFromRawParts)
+    }
+
+    // The recovery that takes back the pointer escaped by the function, e.g., `Box::from_raw` for
+    // `Box::into_raw`, the pointer recovered by another one is freed with a wrong allocator or layout.
+    pub fn paired_recovery(&self) -> Option<Self> {
+        match self {
+            KnownFn::BoxIntoRaw => Some(KnownFn::BoxFromRaw),
+            KnownFn::ArcIntoRaw => Some(KnownFn::ArcFromRaw),
+            KnownFn::CStringIntoRaw => Some(KnownFn::CStringFromRaw),
+            _ => None,
+        }
+    }
+}
+
+impl Display for KnownFn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                KnownFn::MemDrop => "std::mem::drop",
+                KnownFn::MemForget => "std::mem::forget",
+                KnownFn::BoxIntoRaw => "Box::into_raw",
+                KnownFn::BoxFromRaw => "Box::from_raw",
+                KnownFn::BoxLeak => "Box::leak",
+                KnownFn::ManuallyDropNew => "ManuallyDrop::new",
+                KnownFn::ManuallyDropDrop => "ManuallyDrop::drop",
+                KnownFn::ManuallyDropIntoInner => "ManuallyDrop::into_inner",
+                KnownFn::ManuallyDropTake => "ManuallyDrop::take",
+                KnownFn::ArcIntoRaw => "Arc::into_raw",
+                KnownFn::ArcFromRaw => "Arc::from_raw",
+                KnownFn::CStringIntoRaw => "CString::into_raw",
+                KnownFn::CStringFromRaw => "CString::from_raw",
+                KnownFn::VecFromRawParts => "Vec::from_raw_parts",
+                KnownFn::StringFromRawParts => "String::from_raw_parts",
+                KnownFn::VecIntoRawParts => "Vec::into_raw_parts",
+                KnownFn::StringIntoRawParts => "String::into_raw_parts",
+                KnownFn::VecAsPtr => "Vec::as_ptr",
+                KnownFn::VecAsMutPtr => "Vec::as_mut_ptr",
+                KnownFn::StrAsPtr => "str::as_ptr",
+                KnownFn::StrAsMutPtr => "str::as_mut_ptr",
+                KnownFn::VecLen => "Vec::len",
+                KnownFn::StringLen => "String::len",
+                KnownFn::VecCapacity => "Vec::capacity",
+                KnownFn::StringCapacity => "String::capacity",
+                KnownFn::VecShrinkToFit => "Vec::shrink_to_fit",
+                KnownFn::StringShrinkToFit => "String::shrink_to_fit",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_by_def_path_plain() {
+        assert_eq!(KnownFn::resolve_by_def_path("std::mem::drop"), Some(KnownFn::MemDrop));
+        assert_eq!(KnownFn::resolve_by_def_path("core::mem::forget"), Some(KnownFn::MemForget));
+        assert_eq!(KnownFn::resolve_by_def_path("alloc::ffi::CString::into_raw"), Some(KnownFn::CStringIntoRaw));
+    }
+
+    #[test]
+    fn resolve_by_def_path_erases_generics() {
+        assert_eq!(KnownFn::resolve_by_def_path("std::boxed::Box::<T, A>::into_raw"), Some(KnownFn::BoxIntoRaw));
+        assert_eq!(KnownFn::resolve_by_def_path("alloc::sync::Arc::<T>::from_raw"), Some(KnownFn::ArcFromRaw));
+        assert_eq!(KnownFn::resolve_by_def_path("core::mem::ManuallyDrop::<T>::new"), Some(KnownFn::ManuallyDropNew));
+        // the nested generic args are erased as a whole
+        assert_eq!(
+            KnownFn::resolve_by_def_path("alloc::vec::Vec::<std::boxed::Box<u8>, A>::from_raw_parts"),
+            Some(KnownFn::VecFromRawParts),
+        );
+        // the impl segment of a primitive leaves `::::` behind, e.g., `<impl str>`
+        assert_eq!(KnownFn::resolve_by_def_path("core::str::<impl str>::as_ptr"), Some(KnownFn::StrAsPtr));
+    }
+
+    #[test]
+    fn resolve_by_def_path_rejects_others() {
+        // only the items of the standard crates are known
+        assert_eq!(KnownFn::resolve_by_def_path("mycrate::mem::drop"), None);
+        // the whole path must match, not a prefix of it
+        assert_eq!(KnownFn::resolve_by_def_path("std::mem::drop_in_place"), None);
+        assert_eq!(KnownFn::resolve_by_def_path("std::boxed::Box::<T>::into_raw_non_null"), None);
+    }
+}
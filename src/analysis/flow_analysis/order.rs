@@ -1,32 +1,31 @@
 use rustc_middle::mir::TerminatorKind;
 
 use crate::analysis::RcxMut;
-use crate::analysis::flow_analysis::{FlowAnalysis, NodeOrder};
+use crate::analysis::flow_analysis::{EdgeGuard, Edges, FlowAnalysis, Graph, NodeOrder, Scc, UnwindMode};
 use crate::analysis::type_analysis::type_visitor::mir_body;
 
 use std::collections::BinaryHeap;
+use std::cmp::Reverse;
 use stopwatch::Stopwatch;
 
 impl<'tcx, 'a> FlowAnalysis<'tcx, 'a>{
     pub fn order(&mut self) {
         // Get the Global TyCtxt from rustc
-        // Grasp all mir Keys defined in current crate
+        // Grasp all mir Keys defined in current crate and the functions of the dependencies
 
         let mut sw = Stopwatch::start_new();
 
         let tcx = self.tcx();
-        let mir_keys = tcx.mir_keys(());
+        let unwind = self.rcx().config().effective_unwind_mode();
 
-        for each_mir in mir_keys {
-            // Get the defid of current crate and get mir Body through this id
-            let def_id = each_mir.to_def_id();
+        for def_id in self.rcx().analyzed_fns() {
+            // Get the mir Body through the defid
             let body = mir_body(tcx, def_id);
 
             let mut path = NodeOrder::new(body);
-            let mut lev:Vec<usize> = vec![0 ; body.basic_blocks().len()];
 
-            path.collect_edges(&mut lev);
-            path.topo_order(&mut lev);
+            path.collect_edges(unwind);
+            path.topo_order();
             self.rcx_mut().mir_graph_mut().insert(def_id, path.graph_mut().clone());
         }
 
@@ -37,8 +36,9 @@ impl<'tcx, 'a> FlowAnalysis<'tcx, 'a>{
 
 impl<'tcx> NodeOrder<'tcx> {
 
-    /// !Note: this function does not collect the edges that belongs to unwind paths.
-    pub(crate) fn collect_edges(&mut self, lev: &mut Vec<usize>) {
+    /// !Note: this function does not collect the edges that belongs to unwind paths,
+    /// unless the panic-path mode is enabled, which adds the cleanup targets of Call, Drop and Assert.
+    pub(crate) fn collect_edges(&mut self, unwind: UnwindMode) {
         let bbs = self.body().basic_blocks();
         for (block, data) in bbs.iter().enumerate() {
             let mut result:Vec<usize> = vec![];
@@ -50,6 +50,12 @@ impl<'tcx> NodeOrder<'tcx> {
                         for bb in targets.all_targets() {
                             result.push(bb.as_usize());
                         }
+                        // the values jumping to the same target are united into one guard
+                        let values: Vec<u128> = targets.iter().map(|(value, _)| value).collect();
+                        for (value, bb) in targets.iter() {
+                            self.add_guard(block, bb.as_usize(), EdgeGuard::Values(vec![value]));
+                        }
+                        self.add_guard(block, targets.otherwise().as_usize(), EdgeGuard::Otherwise(values));
                     },
                 TerminatorKind::Resume =>
                     (),
@@ -59,23 +65,33 @@ impl<'tcx> NodeOrder<'tcx> {
                     (),
                 TerminatorKind::Unreachable =>
                     (),
-                TerminatorKind::Drop { target, .. } =>
-                    result.push(target.as_usize()),
+                TerminatorKind::Drop { target, unwind: cleanup, .. } => {
+                    result.push(target.as_usize());
+                    if let (UnwindMode::Enabled, Some(c)) = (unwind, cleanup) {
+                        result.push(c.as_usize());
+                        self.add_unwind_guards(block, target.as_usize(), c.as_usize());
+                    }
+                },
                 TerminatorKind::DropAndReplace { .. } =>
                     (),
-                TerminatorKind::Assert { target, .. } =>
-                    result.push(target.as_usize()),
+                TerminatorKind::Assert { target, cleanup, .. } => {
+                    result.push(target.as_usize());
+                    if let (UnwindMode::Enabled, Some(c)) = (unwind, cleanup) {
+                        result.push(c.as_usize());
+                        self.add_unwind_guards(block, target.as_usize(), c.as_usize());
+                    }
+                },
                 TerminatorKind::Yield { .. } =>
                     (),
                 TerminatorKind::GeneratorDrop =>
                     (),
-                TerminatorKind::FalseEdge { .. } =>
-                    (),
-                TerminatorKind::FalseUnwind { .. } =>
-                    (),
+                TerminatorKind::FalseEdge { real_target, .. } =>
+                    result.push(real_target.as_usize()),
+                TerminatorKind::FalseUnwind { real_target, .. } =>
+                    result.push(real_target.as_usize()),
                 TerminatorKind::InlineAsm { .. } =>
                     (),
-                TerminatorKind::Call { target, .. } => {
+                TerminatorKind::Call { target, cleanup, .. } => {
                     // We check the destination due to following case.
                     // Terminator { source_info: SourceInfo { span: src/main.rs:100:9: 100:35 (#7), scope: scope[0] },
                     // kind: core::panicking::panic(const "assertion failed: index <= self.len") -> bb24 },
@@ -84,11 +100,17 @@ impl<'tcx> NodeOrder<'tcx> {
                         Some(t) => { result.push(t.as_usize()) },
                         None => (),
                     }
+                    if let (UnwindMode::Enabled, Some(c)) = (unwind, cleanup) {
+                        if !result.contains(&c.as_usize()) {
+                            result.push(c.as_usize());
+                            if let Some(t) = target {
+                                self.add_unwind_guards(block, t.as_usize(), c.as_usize());
+                            }
+                        }
+                    }
                 }
             }
-            // Update the lev for generating topo order.
             for index in result.iter() {
-                lev[*index] = lev[*index] + 1;
                 self.graph_mut().get_pre_mut()[*index].push(block);
             }
             self.graph_mut().get_edges_mut()[block] = result;
@@ -96,20 +118,281 @@ impl<'tcx> NodeOrder<'tcx> {
         }
     }
 
-    pub(crate) fn topo_order(&mut self, lev: &mut Vec<usize>) {
-        let mut q:BinaryHeap<usize> = BinaryHeap::new();
-        q.push(0);
+    pub(crate) fn add_guard(&mut self, from: usize, to: usize, guard: EdgeGuard) {
+        let guards = self.graph_mut().get_guards_mut();
+        match guards.get(&(from, to)) {
+            Some(old) => match old.union(&guard) {
+                Some(united) => { guards.insert((from, to), united); },
+                None => { guards.remove(&(from, to)); },
+            },
+            None => { guards.insert((from, to), guard); },
+        }
+    }
+
+    // The normal target and the unwind target of one terminator are exclusive.
+    pub(crate) fn add_unwind_guards(&mut self, from: usize, target: usize, cleanup: usize) {
+        if target == cleanup {
+            return;
+        }
+        self.add_guard(from, target, EdgeGuard::Unwind(false));
+        self.add_guard(from, cleanup, EdgeGuard::Unwind(true));
+    }
+
+    pub(crate) fn topo_order(&mut self) {
+        self.graph_mut().topo_order();
+    }
+}
+
+impl Graph {
+    // The topo order is generated on the condensation of the cfg, where each strongly connected
+    // component (scc) is shrunk into one node. Therefore, the loop body will be visited as a whole
+    // after all its predecessors, and the blocks in one scc are ordered by the dfs from its entry.
+    pub(crate) fn topo_order(&mut self) {
+        let sccs = tarjan_scc(&self.e, &[0]);
+
+        // map each reachable block to the scc it belongs to
+        let len = self.e.len();
+        let mut belong:Vec<Option<usize>> = vec![None ; len];
+        for (sidx, scc) in sccs.iter().enumerate() {
+            for node in scc.iter() {
+                belong[*node] = Some(sidx);
+            }
+        }
+
+        // the lev of one scc is the number of edges from other reachable sccs
+        let mut lev:Vec<usize> = vec![0 ; sccs.len()];
+        for (from, targets) in self.e.iter().enumerate() {
+            let from_scc = match belong[from] {
+                Some(sidx) => sidx,
+                None => continue,
+            };
+            for to in targets.iter() {
+                let to_scc = belong[*to].unwrap();
+                if from_scc != to_scc {
+                    lev[to_scc] += 1;
+                }
+            }
+        }
+
+        let mut q:BinaryHeap<(usize, usize)> = BinaryHeap::new();
+        let root = belong[0].unwrap();
+        q.push((0, root));
         while !q.is_empty() {
-            let top = q.pop().unwrap();
-            self.graph_mut().get_topo_mut().push(top);
-            for cnt in 0..self.graph().e[top].len() {
-                let next = self.graph().e[top][cnt];
-                lev[next] = lev[next] - 1;
-                if lev[next] == 0 {
-                    q.push(next);
+            let (_, top) = q.pop().unwrap();
+            let ordered = self.order_in_scc(&sccs[top], &belong, top);
+            for node in ordered.iter() {
+                self.topo.push(*node);
+                for cnt in 0..self.e[*node].len() {
+                    let next = belong[self.e[*node][cnt]].unwrap();
+                    if next == top { continue; }
+                    lev[next] -= 1;
+                    if lev[next] == 0 {
+                        let entry = self.scc_entry(&sccs[next], &belong, next);
+                        q.push((entry, next));
+                    }
+                }
+            }
+            self.scc.push(ordered);
+        }
+    }
+
+    // The entry of an scc is the block that can be reached from outside of this scc.
+    // For irreducible loops with several entries, the smallest index is taken.
+    fn scc_entry(&self, scc: &Scc, belong: &[Option<usize>], sidx: usize) -> usize {
+        let mut entries:Vec<usize> = scc
+            .iter()
+            .filter(|node| **node == 0 || self.pre[**node].iter().any(|p| belong[*p].is_some() && belong[*p] != Some(sidx)))
+            .copied()
+            .collect();
+        entries.sort();
+        match entries.first() {
+            Some(entry) => *entry,
+            None => scc[0],
+        }
+    }
+
+    // Order the blocks in one scc by the reverse post order of the dfs that starts from the entry
+    // and does not leave the scc.
+    fn order_in_scc(&self, scc: &Scc, belong: &[Option<usize>], sidx: usize) -> Scc {
+        if scc.len() == 1 {
+            return scc.clone();
+        }
+
+        let entry = self.scc_entry(scc, belong, sidx);
+        let mut post:Scc = Vec::with_capacity(scc.len());
+        let mut visited:Vec<bool> = vec![false ; belong.len()];
+        let mut stack:Vec<(usize, usize)> = vec![(entry, 0)];
+        visited[entry] = true;
+        while let Some((node, cnt)) = stack.pop() {
+            if cnt < self.e[node].len() {
+                stack.push((node, cnt + 1));
+                let next = self.e[node][cnt];
+                if belong[next] == Some(sidx) && !visited[next] {
+                    visited[next] = true;
+                    stack.push((next, 0));
                 }
+            } else {
+                post.push(node);
+            }
+        }
+        // the blocks not reached in the dfs (from the other entries) are appended in the end
+        for node in scc.iter() {
+            if !visited[*node] {
+                post.insert(0, *node);
             }
         }
+        post.reverse();
+        post
     }
+}
+
+// Tarjan's algorithm for strongly connected components over the nodes reachable from the roots.
+// The result is in reverse topo order, i.e., an scc is generated before all its predecessors.
+// This is implemented with an explicit stack to avoid overflow on large bodies.
+pub(crate) fn tarjan_scc(edges: &Edges, roots: &[usize]) -> Vec<Scc> {
+    let len = edges.len();
+    let mut sccs:Vec<Scc> = Vec::new();
 
-}
\ No newline at end of file
+    let mut index = 0;
+    let mut dfn:Vec<Option<usize>> = vec![None ; len];
+    let mut low:Vec<usize> = vec![0 ; len];
+    let mut on_stack:Vec<bool> = vec![false ; len];
+    let mut stack:Vec<usize> = Vec::new();
+    let mut call:Vec<(usize, usize)> = Vec::new();
+
+    for root in roots.iter() {
+        if *root >= len || dfn[*root].is_some() { continue; }
+        call.push((*root, 0));
+        while let Some((node, cnt)) = call.pop() {
+            if cnt == 0 {
+                dfn[node] = Some(index);
+                low[node] = index;
+                index += 1;
+                stack.push(node);
+                on_stack[node] = true;
+            }
+
+            if cnt < edges[node].len() {
+                call.push((node, cnt + 1));
+                let next = edges[node][cnt];
+                match dfn[next] {
+                    None => call.push((next, 0)),
+                    Some(d) => if on_stack[next] { low[node] = low[node].min(d); },
+                }
+                continue;
+            }
+
+            // all successors are visited, propagate the low link to the parent
+            if let Some((parent, _)) = call.last() {
+                low[*parent] = low[*parent].min(low[node]);
+            }
+
+            if Some(low[node]) == dfn[node] {
+                let mut scc:Scc = Vec::new();
+                loop {
+                    let top = stack.pop().unwrap();
+                    on_stack[top] = false;
+                    scc.push(top);
+                    if top == node { break; }
+                }
+                scc.sort_by_key(|n| Reverse(*n));
+                sccs.push(scc);
+            }
+        }
+    }
+    sccs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph_of(len: usize, edges: &[(usize, usize)]) -> Graph {
+        let mut graph = Graph::new(len);
+        for (from, to) in edges.iter() {
+            graph.e[*from].push(*to);
+            graph.pre[*to].push(*from);
+        }
+        graph
+    }
+
+    #[test]
+    fn tarjan_scc_self_loop() {
+        let graph = graph_of(3, &[(0, 1), (1, 1), (1, 2)]);
+        assert_eq!(tarjan_scc(&graph.e, &[0]), vec![vec![2], vec![1], vec![0]]);
+    }
+
+    #[test]
+    fn tarjan_scc_nested_loops() {
+        // the inner loop 2 <-> 3 is nested in the outer loop 1 -> 2 -> 3 -> 1
+        let graph = graph_of(5, &[(0, 1), (1, 2), (2, 3), (3, 2), (3, 1), (3, 4)]);
+        assert_eq!(tarjan_scc(&graph.e, &[0]), vec![vec![4], vec![3, 2, 1], vec![0]]);
+    }
+
+    #[test]
+    fn tarjan_scc_skips_unreachable() {
+        let graph = graph_of(4, &[(0, 1), (2, 1), (2, 3), (3, 2)]);
+        assert_eq!(tarjan_scc(&graph.e, &[0]), vec![vec![1], vec![0]]);
+    }
+
+    #[test]
+    fn topo_order_self_loop() {
+        let mut graph = graph_of(3, &[(0, 1), (1, 1), (1, 2)]);
+        graph.topo_order();
+        assert_eq!(graph.topo, vec![0, 1, 2]);
+        assert_eq!(graph.scc, vec![vec![0], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn topo_order_nested_loops() {
+        let mut graph = graph_of(5, &[(0, 1), (1, 2), (2, 3), (3, 2), (3, 1), (3, 4)]);
+        graph.topo_order();
+        assert_eq!(graph.topo, vec![0, 1, 2, 3, 4]);
+        assert_eq!(graph.scc, vec![vec![0], vec![1, 2, 3], vec![4]]);
+    }
+
+    #[test]
+    fn topo_order_skips_unreachable() {
+        // the unreachable block 2 jumps into the reachable block 1, it does not hold back block 1
+        let mut graph = graph_of(4, &[(0, 1), (2, 1), (1, 3)]);
+        graph.topo_order();
+        assert_eq!(graph.topo, vec![0, 1, 3]);
+    }
+
+    #[test]
+    fn topo_order_loop_entered_at_larger_index() {
+        let mut graph = graph_of(5, &[(0, 3), (3, 2), (2, 3), (2, 4)]);
+        graph.topo_order();
+        assert_eq!(graph.topo, vec![0, 3, 2, 4]);
+    }
+
+    #[test]
+    fn scc_entry_of_irreducible_loop() {
+        // the loop 1 <-> 2 is entered from 0 at both blocks, the smallest index is taken
+        let graph = graph_of(4, &[(0, 1), (0, 2), (1, 2), (2, 1), (2, 3)]);
+        let sccs = tarjan_scc(&graph.e, &[0]);
+        let mut belong = vec![None; 4];
+        for (sidx, scc) in sccs.iter().enumerate() {
+            for node in scc.iter() {
+                belong[*node] = Some(sidx);
+            }
+        }
+        let sidx = belong[1].unwrap();
+        assert_eq!(graph.scc_entry(&sccs[sidx], &belong, sidx), 1);
+    }
+
+    #[test]
+    fn scc_entry_ignores_unreachable_predecessors() {
+        // block 3 is unreachable, thus block 2 is not an entry of the loop 1 <-> 2
+        let graph = graph_of(4, &[(0, 1), (1, 2), (2, 1), (3, 2)]);
+        let sccs = tarjan_scc(&graph.e, &[0]);
+        let mut belong = vec![None; 4];
+        for (sidx, scc) in sccs.iter().enumerate() {
+            for node in scc.iter() {
+                belong[*node] = Some(sidx);
+            }
+        }
+        let sidx = belong[2].unwrap();
+        assert_eq!(graph.scc_entry(&sccs[sidx], &belong, sidx), 1);
+    }
+}
@@ -0,0 +1,430 @@
+use z3::ast;
+
+use crate::components::report::{LeakPath, LeakVerdict};
+
+use std::cell::RefCell;
+use std::cmp::min;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use stopwatch::Stopwatch;
+
+// The label tracking the exit constraint of the leaking local when its unsat core is extracted.
+pub const EXIT_LABEL: &str = "trace_exit";
+
+// The label `trace_{idx}` tracks the constraint of the idx-th trace step.
+pub fn trace_label(idx: usize) -> String {
+    format!("trace_{}", idx)
+}
+
+// ScriptStep is one step of the solver script of a function. The constraints are kept in smt-lib2
+// together with the declarations of their constants, thus the script can be sent to a worker and
+// replayed in the z3 context of that thread.
+#[derive(Debug, Clone)]
+pub enum ScriptStep {
+    Assert(String),
+    // the label and the constraint `label => constraint`, the label is checked as an assumption
+    Track(String, String),
+    Exit(ExitQuery),
+}
+
+// ExitQuery checks whether all locals are freed at one exit of the function.
+#[derive(Debug, Clone)]
+pub struct ExitQuery {
+    bidx: usize,
+    path: LeakPath,
+    // the path condition of the exit block, None if the grain is not path-sensitive
+    path_cond: Option<String>,
+    // the local, its return constraint and the same constraint tracked by the exit label
+    returns: Vec<(usize, String, String)>,
+    tainted: bool,
+    // the count of the tracked constraints and the widths of the checked bit-vectors
+    stats: (usize, Vec<usize>),
+}
+
+impl ExitQuery {
+    pub fn new(
+        bidx: usize,
+        path: LeakPath,
+        path_cond: Option<String>,
+        returns: Vec<(usize, String, String)>,
+        tainted: bool,
+        stats: (usize, Vec<usize>),
+    ) -> Self {
+        Self {
+            bidx,
+            path,
+            path_cond,
+            returns,
+            tainted,
+            stats,
+        }
+    }
+}
+
+// ConstraintScript takes the place of the solver in the intro visitor: the constraints are recorded
+// rather than solved, since neither the tcx nor the z3 context can be shared with other threads.
+pub struct ConstraintScript<'ctx> {
+    ctx: &'ctx z3::Context,
+    // the scratch solver prints one constraint with its declarations at a time
+    printer: z3::Solver<'ctx>,
+    steps: RefCell<Vec<ScriptStep>>,
+}
+
+impl<'ctx> ConstraintScript<'ctx> {
+    pub fn new(ctx: &'ctx z3::Context) -> Self {
+        Self {
+            ctx,
+            printer: z3::Solver::new(ctx),
+            steps: RefCell::new(Vec::default()),
+        }
+    }
+
+    pub fn to_smt2(&self, constraint: &ast::Bool<'ctx>) -> String {
+        self.printer.push();
+        self.printer.assert(constraint);
+        let text = self.printer.to_string();
+        self.printer.pop(1);
+        text
+    }
+
+    pub fn assert(&self, constraint: &ast::Bool<'ctx>) {
+        let text = self.to_smt2(constraint);
+        self.steps.borrow_mut().push(ScriptStep::Assert(text));
+    }
+
+    pub fn assert_and_track(&self, constraint: &ast::Bool<'ctx>, label: String) {
+        let tracked = ast::Bool::new_const(self.ctx, label.as_str()).implies(constraint);
+        let text = self.to_smt2(&tracked);
+        self.steps.borrow_mut().push(ScriptStep::Track(label, text));
+    }
+
+    pub fn check_exit(&self, query: ExitQuery) {
+        self.steps.borrow_mut().push(ScriptStep::Exit(query));
+    }
+
+    pub fn take_steps(&self) -> Vec<ScriptStep> {
+        self.steps.take()
+    }
+}
+
+// ScriptJob is the script of one function and the timeout of the solver for it.
+#[derive(Debug, Clone)]
+pub struct ScriptJob {
+    steps: Vec<ScriptStep>,
+    timeout: u64,
+}
+
+impl ScriptJob {
+    pub fn new(steps: Vec<ScriptStep>, timeout: u64) -> Self {
+        Self { steps, timeout }
+    }
+}
+
+// ExitResult is one exit that is not proven free of leaks, and the unsat cores (the indexes of the
+// trace steps) of the locals that still hold owning bits at the exit.
+#[derive(Debug, Clone)]
+pub struct ExitResult {
+    bidx: usize,
+    path: LeakPath,
+    verdict: LeakVerdict,
+    leaking: Vec<(usize, Vec<usize>)>,
+}
+
+impl ExitResult {
+    pub fn bidx(&self) -> usize {
+        self.bidx
+    }
+
+    pub fn path(&self) -> LeakPath {
+        self.path
+    }
+
+    pub fn verdict(&self) -> LeakVerdict {
+        self.verdict
+    }
+
+    pub fn leaking(&self) -> &Vec<(usize, Vec<usize>)> {
+        &self.leaking
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScriptResult {
+    exits: Vec<ExitResult>,
+    // the stats of the first exit that the solver returned unknown for, None if every exit is solved
+    unknown_stats: Option<(usize, Vec<usize>)>,
+    // the timeout that the function is solved with, None if the budget is exhausted before solving
+    timeout: Option<u64>,
+    sec_solve: i64,
+    // the solver panicked or the worker was lost before the script is solved
+    failed: bool,
+}
+
+impl ScriptResult {
+    fn failed() -> Self {
+        Self { failed: true, ..Self::default() }
+    }
+
+    pub fn exits(&self) -> &Vec<ExitResult> {
+        &self.exits
+    }
+
+    pub fn unknown_stats(&self) -> Option<&(usize, Vec<usize>)> {
+        self.unknown_stats.as_ref()
+    }
+
+    pub fn timeout(&self) -> Option<u64> {
+        self.timeout
+    }
+
+    pub fn sec_solve(&self) -> i64 {
+        self.sec_solve
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+}
+
+// Replay the script in a fresh z3 context. The timeout of the function is bounded by the rest of
+// the budget when the job is picked up, and the job is skipped once the deadline has passed.
+pub fn solve_script(job: ScriptJob, deadline: Option<Instant>) -> ScriptResult {
+    let sw = Stopwatch::start_new();
+
+    let mut timeout = job.timeout;
+    if let Some(deadline) = deadline {
+        let now = Instant::now();
+        if now >= deadline {
+            return ScriptResult::default();
+        }
+        timeout = min(timeout, (deadline - now).as_millis() as u64);
+    }
+
+    let mut cfg = z3::Config::new();
+    cfg.set_model_generation(true);
+    cfg.set_timeout_msec(timeout);
+    let ctx = z3::Context::new(&cfg);
+    let solver = z3::Solver::new(&ctx);
+
+    let mut result = ScriptResult {
+        timeout: Some(timeout),
+        ..ScriptResult::default()
+    };
+    let mut labels = Vec::default();
+    for step in job.steps.iter() {
+        match step {
+            ScriptStep::Assert(text) => solver.from_string(text.as_str()),
+            ScriptStep::Track(label, text) => {
+                solver.from_string(text.as_str());
+                labels.push(ast::Bool::new_const(&ctx, label.as_str()));
+            },
+            ScriptStep::Exit(query) => solve_exit(&ctx, &solver, &labels, query, &mut result),
+        }
+    }
+
+    result.sec_solve = sw.elapsed_ms();
+    result
+}
+
+fn solve_exit<'ctx>(
+    ctx: &'ctx z3::Context,
+    solver: &z3::Solver<'ctx>,
+    labels: &[ast::Bool<'ctx>],
+    query: &ExitQuery,
+    result: &mut ScriptResult,
+) {
+    if let Some(cond) = &query.path_cond {
+        // no feasible path reaches the exit, e.g., the arm of a variant that is never constructed
+        solver.push();
+        solver.from_string(cond.as_str());
+        let reachable = solver.check_assumptions(labels);
+        solver.pop(1);
+        if reachable == z3::SatResult::Unsat && solver.check_assumptions(labels) != z3::SatResult::Unsat {
+            return;
+        }
+        solver.push();
+        solver.from_string(cond.as_str());
+    }
+
+    // the return constraints are checked in a new scope, thus we can still tell which local
+    // holds the owning bits if the whole function turns out to be unsatisfiable
+    solver.push();
+    for (_, constraint_return, _) in query.returns.iter() {
+        solver.from_string(constraint_return.as_str());
+    }
+    let sat = solver.check_assumptions(labels);
+    solver.pop(1);
+
+    // the unknown result is recorded even if the function is untainted, it means that the
+    // function is not fully checked rather than free of leaks
+    if sat == z3::SatResult::Unknown && result.unknown_stats.is_none() {
+        result.unknown_stats = Some(query.stats.clone());
+    }
+
+    if sat != z3::SatResult::Sat && query.tainted {
+        let (verdict, leaking) = if sat == z3::SatResult::Unsat {
+            let leaking = locate_leaking_locals(solver, labels, &query.returns)
+                .into_iter()
+                .map(|(iidx, tracked)| (iidx, unsat_core(ctx, solver, labels, tracked)))
+                .collect();
+            (LeakVerdict::Unsat, leaking)
+        } else {
+            (LeakVerdict::Unknown, Vec::default())
+        };
+        result.exits.push(ExitResult { bidx: query.bidx, path: query.path, verdict, leaking });
+    }
+
+    if query.path_cond.is_some() {
+        solver.pop(1);
+    }
+
+    // the constraints of one exit are kept only if they hold, otherwise the other exits
+    // (e.g., the resume blocks) could not be checked any more
+    if sat == z3::SatResult::Sat {
+        for (_, constraint_return, _) in query.returns.iter() {
+            solver.from_string(constraint_return.as_str());
+        }
+    }
+}
+
+// Check the return constraint of each local separately to find which locals still hold
+// owning bits at the exit. If the constraints before the exit are already conflicting,
+// the leak cannot be attributed to any local and the result is empty.
+fn locate_leaking_locals<'q>(
+    solver: &z3::Solver,
+    labels: &[ast::Bool],
+    returns: &'q [(usize, String, String)],
+) -> Vec<(usize, &'q str)> {
+    let mut leaking = Vec::default();
+    if solver.check_assumptions(labels) == z3::SatResult::Unsat {
+        return leaking;
+    }
+
+    for (iidx, constraint_return, tracked) in returns.iter() {
+        solver.push();
+        solver.from_string(constraint_return.as_str());
+        if solver.check_assumptions(labels) == z3::SatResult::Unsat {
+            leaking.push((*iidx, tracked.as_str()));
+        }
+        solver.pop(1);
+    }
+    leaking
+}
+
+// The exit constraint of the leaking local is tracked together with the constraints of the trace
+// steps, and the labels in the core are sorted in the order they are asserted (i.e., the visiting order).
+fn unsat_core<'ctx>(
+    ctx: &'ctx z3::Context,
+    solver: &z3::Solver<'ctx>,
+    labels: &[ast::Bool<'ctx>],
+    tracked: &str,
+) -> Vec<usize> {
+    let mut assumptions = labels.to_vec();
+    assumptions.push(ast::Bool::new_const(ctx, EXIT_LABEL));
+
+    let mut core = Vec::default();
+    solver.push();
+    solver.from_string(tracked);
+    if solver.check_assumptions(&assumptions) == z3::SatResult::Unsat {
+        core = solver
+            .get_unsat_core()
+            .iter()
+            .filter_map(|label| label.to_string().strip_prefix("trace_").and_then(|idx| idx.parse().ok()))
+            .collect();
+        core.sort_unstable();
+    }
+    solver.pop(1);
+    core
+}
+
+// SolverPool solves the scripts of the functions with a number of workers, each of which owns its
+// z3 contexts. With a single worker the scripts are solved in place once they are submitted.
+pub struct SolverPool {
+    deadline: Option<Instant>,
+    jobs: Option<mpsc::Sender<(usize, ScriptJob)>>,
+    results: mpsc::Receiver<(usize, ScriptResult)>,
+    results_tx: mpsc::Sender<(usize, ScriptResult)>,
+    workers: Vec<JoinHandle<()>>,
+    submitted: usize,
+}
+
+impl SolverPool {
+    pub fn new(workers: usize, deadline: Option<Instant>) -> Self {
+        let (results_tx, results) = mpsc::channel();
+        let mut pool = Self {
+            deadline,
+            jobs: None,
+            results,
+            results_tx,
+            workers: Vec::default(),
+            submitted: 0,
+        };
+
+        if workers > 1 {
+            let (jobs_tx, jobs) = mpsc::channel::<(usize, ScriptJob)>();
+            let jobs = Arc::new(Mutex::new(jobs));
+            for _ in 0..workers {
+                let jobs = Arc::clone(&jobs);
+                let results_tx = pool.results_tx.clone();
+                let worker = thread::spawn(move || loop {
+                    // the lock is never held while solving, thus a poisoned lock still guards the receiver
+                    let job = jobs.lock().unwrap_or_else(PoisonError::into_inner).recv();
+                    let sent = match job {
+                        Ok((idx, job)) => results_tx.send((idx, solve_job(job, deadline))),
+                        Err(_) => break,
+                    };
+                    if sent.is_err() {
+                        break;
+                    }
+                });
+                pool.workers.push(worker);
+            }
+            pool.jobs = Some(jobs_tx);
+        }
+
+        pool
+    }
+
+    pub fn submit(&mut self, job: ScriptJob) {
+        let idx = self.submitted;
+        self.submitted += 1;
+        // the job is solved in place if all workers have exited
+        let job = match &self.jobs {
+            Some(jobs) => match jobs.send((idx, job)) {
+                Ok(()) => return,
+                Err(mpsc::SendError((_, job))) => job,
+            },
+            None => job,
+        };
+        let _ = self.results_tx.send((idx, solve_job(job, self.deadline)));
+    }
+
+    // Wait for all workers and return the results in the order of submission, thus the merged
+    // results never depend on which worker finishes first. The scripts lost with a worker are failed.
+    pub fn finish(self) -> Vec<ScriptResult> {
+        let Self { jobs, results, results_tx, workers, submitted, .. } = self;
+        drop(jobs);
+        drop(results_tx);
+
+        let mut ordered: Vec<Option<ScriptResult>> = vec![None; submitted];
+        for (idx, result) in results.iter() {
+            ordered[idx] = Some(result);
+        }
+        for worker in workers {
+            let _ = worker.join();
+        }
+
+        ordered
+            .into_iter()
+            .map(|result| result.unwrap_or_else(ScriptResult::failed))
+            .collect()
+    }
+}
+
+// Solve the script and catch the panics of the solver, e.g., a malformed script or an error of z3,
+// the function is then failed instead of aborting the whole analysis.
+fn solve_job(job: ScriptJob, deadline: Option<Instant>) -> ScriptResult {
+    panic::catch_unwind(AssertUnwindSafe(|| solve_script(job, deadline))).unwrap_or_else(|_| ScriptResult::failed())
+}
@@ -1,4 +1,10 @@
 pub mod connect;
+pub mod cross_crate;
+pub mod drop_check;
+pub mod proxy_type;
+pub mod raw_pairing;
+pub mod raw_parts;
+pub mod reachability;
 pub mod type_visitor;
 pub mod ownership;
 
@@ -24,6 +30,9 @@ pub type Unique = HashSet<DefId>;
 pub type OwnershipLayout = Vec<RawTypeOwner>;
 pub type RustBV = Vec<bool>;
 
+// The env var set by cargo-rlc when the llvm phase has emitted the call graph into the workspace.
+pub static RLC_LLVM_CROSS_CHECK_ENV: &str = "RLC_LLVM_CROSS_CHECK";
+
 // Type Analysis is the first step and it will perform a simple-inter-procedural analysis
 // for current crate and collect types after monomorphism as well as extracting 'adt-def'.
 // The struct TypeAnalysis implements mir::Visitor to simulate as the type collector.
@@ -83,10 +92,26 @@ impl<'tcx, 'a> TypeAnalysis<'tcx, 'a> {
 
         let mut sw = Stopwatch::start_new();
 
-        // Get the analysis result from rlc phase llvm
-        // self.connect();
+        // Get the may-allocate and may-deallocate functions over MIR
+        self.reachability();
+        // Cross-check the result with the llvm phase if it has been run by cargo-rlc
+        if is_llvm_cross_check() {
+            self.connect();
+        }
+        // Find the functions of the dependencies to analyze at the Ultra grain
+        self.cross_crate();
         // Get related adt types through visiting mir local
         self.visitor();
+        // Find the raw pointer fields receiving owned allocations in the crate
+        let owned_fields = self.owned_raw_fields();
+        // Report the adts holding them without Drop impl
+        self.proxy_type(&owned_fields);
+        // Verify that the Drop impls release them if the drop check is enabled
+        self.drop_check(&owned_fields);
+        // Check the capacities of the buffers rebuilt by from_raw_parts
+        self.raw_parts();
+        // Pair the raw pointers escaped by into_raw with the recoveries of the crate
+        self.raw_pairing();
 
         //rlc_info!("AdtDef Sum:{:?}", self.adt_owner().len());
         //rlc_info!("Tymap Sum:{:?}", self.ty_map().len());
@@ -397,12 +422,24 @@ impl<'tcx> FindPtr<'tcx> {
     }
 }
 
-#[derive(Debug, Copy, Clone, Hash)]
+#[derive(Debug, Copy, Clone, Hash, Serialize, Deserialize)]
 pub enum AdtOwnerDisplay {
     Verbose,
     Disabled,
 }
 
+// The drop-check mode: if it is enabled, the bodies of `Drop::drop` of the local adts are verified to
+// release the owned raw pointer fields of `self` on all paths.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum DropCheckMode {
+    Enabled,
+    Disabled,
+}
+
+pub fn is_llvm_cross_check() -> bool {
+    env::var_os(RLC_LLVM_CROSS_CHECK_ENV).is_some()
+}
+
 pub fn is_display_verbose() -> bool {
     match env::var_os("ADT_DISPLAY") {
         Some(_)  => true,
@@ -1,6 +1,12 @@
-use crate::RLC_LLVM_IR;
+use rustc_hir::def::DefKind;
+use rustc_middle::ty::Instance;
+
+use crate::rlc_info;
+use crate::analysis::RcxMut;
 use crate::analysis::type_analysis::TypeAnalysis;
+use crate::analysis::type_analysis::reachability::AllocEffect;
 use crate::components::fs::{rlc_can_read_dir, rlc_read, rlc_demangle, rlc_create_file};
+use crate::components::workspace::RlcWorkspace;
 
 use std::io::{BufRead, BufReader, Write};
 use std::collections::{HashMap, HashSet};
@@ -33,9 +39,14 @@ impl CallGraph {
 
 impl<'tcx, 'a> TypeAnalysis<'tcx, 'a> {
     pub fn connect(&mut self) {
-        if rlc_can_read_dir(RLC_LLVM_IR, "Cannot read LLVM IR files") {
+        // the llvm ir is only emitted into the workspace given by cargo-rlc
+        let workspace = match RlcWorkspace::from_env() {
+            Some(workspace) => workspace,
+            None => return,
+        };
+        if rlc_can_read_dir(workspace.llvm_ir(), "Cannot read LLVM IR files") {
             let mut call_graph = CallGraph::default();
-            for entry in WalkDir::new(RLC_LLVM_IR) {
+            for entry in WalkDir::new(workspace.llvm_ir()) {
                 let entry_path = entry.unwrap().into_path();
                 if entry_path
                     .iter()
@@ -69,8 +80,49 @@ impl<'tcx, 'a> TypeAnalysis<'tcx, 'a> {
                 }
             }
             let json_value = json!(call_graph);
-            let mut file = rlc_create_file("/tmp/rlc/cg.json", "failed to create call graph (json)");
+            let mut file = rlc_create_file(workspace.call_graph(), "failed to create call graph (json)");
             file.write(json_value.to_string().as_bytes());
+            self.cross_check(&call_graph);
+        }
+    }
+
+    // The functions in the call graph are the ones reaching `__rust_dealloc` in the llvm ir. A local
+    // function that deallocates in the llvm ir but not over MIR is a miss of the reachability analysis,
+    // thus it is marked as deallocating for the flow analysis. The converse is expected since the
+    // unresolved calls in MIR are taken as deallocating.
+    fn cross_check(&mut self, call_graph: &CallGraph) {
+        let tcx = self.tcx();
+        let mut agreed = 0;
+        let mut only_mir = 0;
+        let mut only_llvm = Vec::default();
+        for each_mir in tcx.mir_keys(()) {
+            let did = each_mir.to_def_id();
+            if !matches!(tcx.def_kind(did), DefKind::Fn | DefKind::AssocFn)
+                || tcx.generics_of(did).requires_monomorphization(tcx) {
+                continue;
+            }
+            let symbol = rlc_demangle(tcx.symbol_name(Instance::mono(tcx, did)).name);
+            let llvm = call_graph.graph().contains_key(&symbol);
+            let mir = self.rcx().alloc_effect_of(did).may_dealloc();
+            match (llvm, mir) {
+                (true, false) => {
+                    let effect = self.rcx().alloc_effect_of(did).union(&AllocEffect::new(false, true));
+                    self.rcx_mut().alloc_effects_mut().insert(did, effect);
+                    only_llvm.push(tcx.def_path_str(did));
+                },
+                (false, true) => only_mir += 1,
+                _ => agreed += 1,
+            }
+        }
+
+        rlc_info!(
+            "LLVM Cross-Check: {} function(s) agree, {} may deallocate only over MIR, {} only in LLVM IR",
+            agreed,
+            only_mir,
+            only_llvm.len()
+        );
+        for path in only_llvm {
+            rlc_info!("    {}", path);
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,85 @@
+use rustc_hir::def::DefKind;
+use rustc_middle::mir::TerminatorKind;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use rustc_span::def_id::DefId;
+
+use crate::rlc_info;
+use crate::analysis::RcxMut;
+use crate::analysis::flow_analysis::inter_visitor::callee_def_id;
+use crate::analysis::type_analysis::TypeAnalysis;
+use crate::analysis::type_analysis::type_visitor::mir_body;
+
+use std::collections::{HashMap, VecDeque};
+
+// The functions of the dependencies found beyond the limit are not analyzed, they are black boxes
+// like the functions without MIR.
+pub const CROSS_CRATE_FN_LIMIT: usize = 2_000;
+
+// The functions of the dependencies that are analyzed with the local crate, each one is mapped to
+// the local function and the call site through which it is first reached.
+pub type CrossCrateFns = HashMap<DefId, (DefId, Span)>;
+
+impl<'tcx, 'a> TypeAnalysis<'tcx, 'a> {
+    // Find the functions of the dependencies reachable from the local crate through the direct calls,
+    // their MIR is decoded from the metadata (cargo-rlc builds all crates with `-Zalways-encode-mir`).
+    // The crates of the sysroot (std, core, alloc ...) are not followed, their functions are modeled
+    // by the known functions and the summaries of the callers.
+    pub fn cross_crate(&mut self) {
+        if !self.rcx().config().grain().is_cross_crate() {
+            return;
+        }
+
+        let tcx = self.tcx();
+        let mut fns = CrossCrateFns::default();
+        let mut queue = VecDeque::default();
+        for each_mir in tcx.mir_keys(()) {
+            queue.push_back((each_mir.to_def_id(), None));
+        }
+
+        while let Some((did, entry)) = queue.pop_front() {
+            let body = mir_body(tcx, did);
+            for data in body.basic_blocks().iter() {
+                let callee = match &data.terminator().kind {
+                    TerminatorKind::Call { func, .. } => match callee_def_id(func) {
+                        Some(callee) => callee,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                if fns.contains_key(&callee) || !is_dependency_fn(tcx, callee) {
+                    continue;
+                }
+                if fns.len() >= CROSS_CRATE_FN_LIMIT {
+                    rlc_info!("Cross-Crate: the limit of {} functions is reached", CROSS_CRATE_FN_LIMIT);
+                    queue.clear();
+                    break;
+                }
+                // the callees of a dependency function share the entry of their caller
+                let entry = entry.unwrap_or((did, data.terminator().source_info.span));
+                fns.insert(callee, entry);
+                queue.push_back((callee, Some(entry)));
+            }
+        }
+
+        rlc_info!("Cross-Crate: {} function(s) of the dependencies are analyzed", fns.len());
+        *self.rcx_mut().cross_crate_fns_mut() = fns;
+    }
+}
+
+// The function is defined in a dependency outside of the sysroot and its MIR is encoded.
+fn is_dependency_fn(tcx: TyCtxt, did: DefId) -> bool {
+    if did.is_local() || tcx.is_foreign_item(did) {
+        return false;
+    }
+    if !matches!(tcx.def_kind(did), DefKind::Fn | DefKind::AssocFn | DefKind::Closure) {
+        return false;
+    }
+    // the crates of the sysroot are found by rustc itself, they come with no path out of the sysroot
+    let sysroot = &tcx.sess.sysroot;
+    let source = tcx.used_crate_source(did.krate);
+    if !source.paths().any(|path| !path.as_os_str().is_empty() && !path.starts_with(sysroot)) {
+        return false;
+    }
+    tcx.is_mir_available(did)
+}
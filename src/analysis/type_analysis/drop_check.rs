@@ -0,0 +1,452 @@
+use rustc_middle::mir::{BasicBlock, Body, Local, Operand, Place, ProjectionElem, Rvalue, StatementKind, TerminatorKind,
+                        UnOp};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use rustc_span::def_id::DefId;
+use rustc_target::abi::VariantIdx;
+
+use crate::rlc_info;
+use crate::analysis::RcxMut;
+use crate::analysis::flow_analysis::inter_visitor::callee_def_id;
+use crate::analysis::flow_analysis::known_fn::KnownFn;
+use crate::analysis::type_analysis::{AdtOwner, DropCheckMode, TypeAnalysis};
+use crate::analysis::type_analysis::ownership::RawTypeOwner;
+use crate::analysis::type_analysis::proxy_type::{operand_local, OwnedFields};
+use crate::analysis::type_analysis::type_visitor::mir_body;
+use crate::components::context::RlcGlobalCtxt;
+use crate::components::report::DropCheckReport;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+// The path of an owned raw pointer field from `*self`, one (variant, field) step for each adt on the
+// way, e.g., `((*self) as Bytes).0.ptr` is [(Bytes, 0), (0, 0)].
+type FieldPath = Vec<(VariantIdx, usize)>;
+// The owning bits of self at the entry of `Drop::drop`, each one is released on its own.
+type OwnedBits = Vec<(FieldPath, DefId)>;
+
+// The structs nested in the fields of self deeper than the limit are not followed.
+pub const DROP_CHECK_DEPTH: usize = 4;
+
+impl<'tcx, 'a> TypeAnalysis<'tcx, 'a> {
+    // Verify the Drop impls of the local adts: the owned raw pointer fields of `self` are the owning bits
+    // at the entry of `Drop::drop`, and each bit must be released on every path to the return, e.g.,
+    // passed to `Box::from_raw` or to a function freeing it. The bits of one variant of an enum are only
+    // checked on the paths where `self` may be of this variant.
+    pub fn drop_check(&mut self, owned_fields: &OwnedFields) {
+        if self.rcx().config().drop_check_mode() == DropCheckMode::Disabled {
+            return;
+        }
+
+        let tcx = self.tcx();
+        let mut adts: Vec<DefId> = self
+            .adt_owner()
+            .keys()
+            .filter(|did| did.is_local() && tcx.adt_destructor(**did).is_some())
+            .filter(|did| self.rcx().is_def_analyzed(**did))
+            .copied()
+            .collect();
+        adts.sort_by_key(|did| tcx.def_path_hash(*did));
+
+        let mut reports = Vec::default();
+        for adt in adts {
+            let mut bits = OwnedBits::default();
+            owned_bits(tcx, self.adt_owner(), owned_fields, adt, &FieldPath::default(), &mut bits);
+            if bits.is_empty() {
+                continue;
+            }
+
+            let drop_fn = tcx.adt_destructor(adt).unwrap().did;
+            let body = mir_body(tcx, drop_fn);
+            let mut drop_flow = DropFlow::new(self.rcx(), body, adt);
+            drop_flow.resolve_aliases();
+
+            let adt_def = tcx.adt_def(adt);
+            for (path, field, spans) in drop_flow.unreleased(&bits) {
+                let variant = if adt_def.is_enum() { Some(adt_def.variant(path[0].0).def_id) } else { None };
+                let field_path = field_path_to_string(tcx, adt, &path);
+                reports.push(DropCheckReport::new(adt, drop_fn, field, field_path, variant, spans));
+            }
+        }
+
+        rlc_info!("Drop Check: {} owned field(s) are not released by the Drop impls", reports.len());
+        *self.rcx_mut().drop_check_reports_mut() = reports;
+    }
+}
+
+// Collect the owning bits of the adt: the raw pointer fields that receive owned allocations in the crate
+// or belong to a phantom unit, and the ones in the structs held by the fields if these structs have no
+// Drop impl of their own, e.g., `Data::Bytes(BytesPtr { ptr, .. })` owns `((*self) as Bytes).0.ptr`.
+fn owned_bits(
+    tcx: TyCtxt,
+    adt_owner: &AdtOwner,
+    owned_fields: &OwnedFields,
+    adt: DefId,
+    prefix: &FieldPath,
+    bits: &mut OwnedBits,
+) {
+    let adt_def = tcx.adt_def(adt);
+    let phantom_unit = is_phantom_unit(tcx, adt_owner, adt);
+    for (variant, variant_def) in adt_def.variants().iter_enumerated() {
+        for (idx, field_def) in variant_def.fields.iter().enumerate() {
+            let mut path = prefix.clone();
+            path.push((variant, idx));
+            let field_ty = tcx.type_of(field_def.did);
+            if field_ty.is_unsafe_ptr() {
+                if phantom_unit || owned_fields.contains_key(&(adt, variant, idx)) {
+                    bits.push((path, field_def.did));
+                }
+                continue;
+            }
+            match field_ty.ty_adt_def() {
+                Some(field_adt) if field_adt.is_struct()
+                    && field_adt.did().is_local()
+                    && tcx.adt_destructor(field_adt.did()).is_none()
+                    && path.len() < DROP_CHECK_DEPTH => {
+                    owned_bits(tcx, adt_owner, owned_fields, field_adt.did(), &path, bits);
+                },
+                _ => (),
+            }
+        }
+    }
+}
+
+// The struct holds PhantomData<T> with a pointer to T, it is owned in its ownership layout.
+fn is_phantom_unit(tcx: TyCtxt, adt_owner: &AdtOwner, adt: DefId) -> bool {
+    let adt_def = tcx.adt_def(adt);
+    if !adt_def.is_struct() {
+        return false;
+    }
+    let has_phantom = adt_def
+        .all_fields()
+        .any(|field| tcx.type_of(field.did).ty_adt_def().map_or(false, |def| def.is_phantom_data()));
+    has_phantom && adt_owner.get(&adt).map_or(false, |layout| layout[0].0 == RawTypeOwner::Owned)
+}
+
+// e.g., self.0.ptr
+fn field_path_to_string(tcx: TyCtxt, adt: DefId, path: &FieldPath) -> String {
+    let mut s = "self".to_string();
+    let mut adt_def = tcx.adt_def(adt);
+    for (variant, idx) in path {
+        let field_def = &adt_def.variant(*variant).fields[*idx];
+        s += &format!(".{}", field_def.name);
+        if let Some(next) = tcx.type_of(field_def.did).ty_adt_def() {
+            adt_def = next;
+        }
+    }
+    s
+}
+
+// DropFlow walks the body of `Drop::drop` for the owning bits of self, the places reading the fields of
+// self are resolved through the references and the copies of the pointers, flow-insensitively.
+struct DropFlow<'tcx, 'a> {
+    rcx: &'a RlcGlobalCtxt<'tcx>,
+    body: &'tcx Body<'tcx>,
+    adt: DefId,
+    // the references to self or to its fields
+    ref_paths: HashMap<Local, FieldPath>,
+    // the raw pointers read from the fields of self
+    ptr_paths: HashMap<Local, FieldPath>,
+    // the discriminants of self
+    discrs: HashSet<Local>,
+    // the results of `is_null` on the pointers read from the fields of self, and whether the result is
+    // true (or false if negated) when the pointer is null
+    null_checks: HashMap<Local, (FieldPath, bool)>,
+}
+
+impl<'tcx, 'a> DropFlow<'tcx, 'a> {
+    fn new(rcx: &'a RlcGlobalCtxt<'tcx>, body: &'tcx Body<'tcx>, adt: DefId) -> Self {
+        Self {
+            rcx,
+            body,
+            adt,
+            ref_paths: HashMap::default(),
+            ptr_paths: HashMap::default(),
+            discrs: HashSet::default(),
+            null_checks: HashMap::default(),
+        }
+    }
+
+    fn tcx(&self) -> TyCtxt<'tcx> {
+        self.rcx.tcx()
+    }
+
+    // The path of the place from `*self`, the place must go through the deref of self or of one
+    // reference to its fields, e.g., `(((*_1) as Bytes).0).0` is [(Bytes, 0), (0, 0)].
+    fn resolve(&self, place: &Place<'tcx>) -> Option<FieldPath> {
+        let (first, rest) = place.projection.split_first()?;
+        if *first != ProjectionElem::Deref {
+            return None;
+        }
+        let mut path = if place.local.as_usize() == 1 {
+            FieldPath::default()
+        } else {
+            self.ref_paths.get(&place.local)?.clone()
+        };
+        let mut variant = None;
+        for elem in rest {
+            match elem {
+                ProjectionElem::Downcast(_, idx) => variant = Some(*idx),
+                ProjectionElem::Field(field, _) => {
+                    path.push((variant.take().unwrap_or_else(|| VariantIdx::from_u32(0)), field.index()));
+                },
+                _ => return None,
+            }
+        }
+        Some(path)
+    }
+
+    // The path of the pointer passed in the operand.
+    fn operand_path(&self, op: &Operand<'tcx>) -> Option<FieldPath> {
+        match op {
+            Operand::Move(place) | Operand::Copy(place) if place.projection.is_empty() => {
+                self.ptr_paths.get(&place.local).cloned()
+            },
+            Operand::Move(place) | Operand::Copy(place) => self.resolve(place),
+            _ => None,
+        }
+    }
+
+    // Resolve the references, the pointers and the discriminants of self until the fixed point.
+    fn resolve_aliases(&mut self) {
+        let tcx = self.tcx();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for data in self.body.basic_blocks().iter() {
+                for stmt in data.statements.iter() {
+                    let (place, rvalue) = match &stmt.kind {
+                        StatementKind::Assign(box (place, rvalue)) if place.projection.is_empty() => (place, rvalue),
+                        _ => continue,
+                    };
+                    let local = place.local;
+                    let is_ptr = self.body.local_decls[local].ty.is_unsafe_ptr();
+                    match rvalue {
+                        Rvalue::Ref(_, _, borrowed) | Rvalue::AddressOf(_, borrowed) => {
+                            if let Some(path) = self.resolve(borrowed) {
+                                changed |= self.ref_paths.insert(local, path).is_none();
+                            }
+                        },
+                        Rvalue::Use(op) | Rvalue::Cast(_, op, _) => {
+                            let from = match op {
+                                Operand::Move(from) | Operand::Copy(from) => from,
+                                _ => continue,
+                            };
+                            if from.projection.is_empty() {
+                                if let Some(path) = self.ref_paths.get(&from.local).cloned() {
+                                    changed |= self.ref_paths.insert(local, path).is_none();
+                                }
+                                if let Some(path) = self.ptr_paths.get(&from.local).cloned() {
+                                    changed |= self.ptr_paths.insert(local, path).is_none();
+                                }
+                            } else if is_ptr {
+                                if let Some(path) = self.resolve(from) {
+                                    changed |= self.ptr_paths.insert(local, path).is_none();
+                                }
+                            }
+                        },
+                        Rvalue::UnaryOp(UnOp::Not, op) => {
+                            let negated = operand_local(op)
+                                .and_then(|from| self.null_checks.get(&from))
+                                .map(|(path, when)| (path.clone(), !when));
+                            if let Some(null_check) = negated {
+                                changed |= self.null_checks.insert(local, null_check).is_none();
+                            }
+                        },
+                        Rvalue::Discriminant(discr) => {
+                            if self.resolve(discr).map_or(false, |path| path.is_empty()) {
+                                changed |= self.discrs.insert(local);
+                            }
+                        },
+                        _ => (),
+                    }
+                }
+
+                let (func, args, destination) = match &data.terminator().kind {
+                    TerminatorKind::Call { func, args, destination, .. } if destination.projection.is_empty() => (func, args, destination),
+                    _ => continue,
+                };
+                let callee = match callee_def_id(func) {
+                    Some(callee) => callee,
+                    None => continue,
+                };
+                let local = destination.local;
+                if tcx.item_name(callee).as_str() == "is_null" {
+                    if let Some(path) = args.first().and_then(|arg| self.operand_path(arg)) {
+                        changed |= self.null_checks.insert(local, (path, true)).is_none();
+                    }
+                } else if self.body.local_decls[local].ty.is_unsafe_ptr() {
+                    // the pointer taken out of the field, e.g., `mem::replace(&mut self.ptr, null_mut())`
+                    let taken = args
+                        .iter()
+                        .filter_map(operand_local)
+                        .filter_map(|arg| self.ref_paths.get(&arg))
+                        .find(|path| self.is_ptr_path(path))
+                        .cloned();
+                    if let Some(path) = taken {
+                        changed |= self.ptr_paths.insert(local, path).is_none();
+                    }
+                }
+            }
+        }
+    }
+
+    // The path ends at a raw pointer field.
+    fn is_ptr_path(&self, path: &FieldPath) -> bool {
+        let tcx = self.tcx();
+        let mut adt_def = tcx.adt_def(self.adt);
+        for (depth, (variant, idx)) in path.iter().enumerate() {
+            let field_def = match adt_def.variant(*variant).fields.get(*idx) {
+                Some(field_def) => field_def,
+                None => return false,
+            };
+            let field_ty = tcx.type_of(field_def.did);
+            if depth + 1 == path.len() {
+                return field_ty.is_unsafe_ptr();
+            }
+            adt_def = match field_ty.ty_adt_def() {
+                Some(next) => next,
+                None => return false,
+            };
+        }
+        false
+    }
+
+    // The callee releases the pointer passed in the arg: a known recovery function, the param taken by
+    // the model, or a function that deallocates on its resolved paths.
+    fn is_released_by(&self, callee: DefId, aidx: usize) -> bool {
+        if let Some(known) = KnownFn::resolve(self.tcx(), callee) {
+            return known.is_recovery() && aidx == 0;
+        }
+        if let Some(model) = self.rcx.fn_model_of(callee) {
+            return model.is_taken(aidx);
+        }
+        let effect = self.rcx.alloc_effect_of(callee);
+        effect.may_dealloc() && !effect.is_opaque()
+    }
+
+    // The bits released by the call in the block.
+    fn released_in(&self, bidx: usize, bits: &OwnedBits) -> Vec<FieldPath> {
+        let mut released = Vec::default();
+        if let TerminatorKind::Call { func, args, .. } = &self.body.basic_blocks()[BasicBlock::from_usize(bidx)].terminator().kind {
+            let callee = match callee_def_id(func) {
+                Some(callee) => callee,
+                None => return released,
+            };
+            for (aidx, arg) in args.iter().enumerate() {
+                if !self.is_released_by(callee, aidx) {
+                    continue;
+                }
+                if let Some(path) = self.operand_path(arg) {
+                    released.push(path);
+                    continue;
+                }
+                // the reference to a field holding the bits, e.g., `free_inner(&mut self.inner)`
+                if let Some(prefix) = operand_local(arg).and_then(|local| self.ref_paths.get(&local)) {
+                    if !self.is_ptr_path(prefix) {
+                        released.extend(bits.iter().filter(|(path, _)| path.starts_with(prefix)).map(|(path, _)| path.clone()));
+                    }
+                }
+            }
+        }
+        released
+    }
+
+    // Walk the normal paths of the body from the entry, the state of each block is the set of the variants
+    // that self may be of and the bits released on all paths reaching it. The bits of the possible
+    // variants that are not released at some return are collected with the spans of these returns.
+    fn unreleased(&self, bits: &OwnedBits) -> Vec<(FieldPath, DefId, Vec<Span>)> {
+        let tcx = self.tcx();
+        let adt_def = tcx.adt_def(self.adt);
+        let all_variants: Vec<VariantIdx> = adt_def.variants().indices().collect();
+        let discr_values: Vec<(VariantIdx, u128)> = if adt_def.is_enum() {
+            adt_def.discriminants(tcx).map(|(idx, discr)| (idx, discr.val)).collect()
+        } else {
+            Vec::default()
+        };
+
+        let mut states: HashMap<(usize, Vec<VariantIdx>), BTreeSet<FieldPath>> = HashMap::default();
+        let mut worklist: VecDeque<(usize, Vec<VariantIdx>)> = VecDeque::default();
+        let mut leaks: BTreeMap<FieldPath, Vec<Span>> = BTreeMap::default();
+        states.insert((0, all_variants.clone()), BTreeSet::default());
+        worklist.push_back((0, all_variants));
+
+        while let Some(node) = worklist.pop_front() {
+            let (bidx, variants) = node.clone();
+            let mut released = states[&node].clone();
+            released.extend(self.released_in(bidx, bits));
+
+            let terminator = self.body.basic_blocks()[BasicBlock::from_usize(bidx)].terminator();
+            let mut succs: Vec<(usize, Vec<VariantIdx>, BTreeSet<FieldPath>)> = Vec::default();
+            match &terminator.kind {
+                TerminatorKind::Return => {
+                    for (path, _) in bits.iter() {
+                        if variants.contains(&path[0].0) && !released.contains(path) {
+                            let spans = leaks.entry(path.clone()).or_default();
+                            if !spans.contains(&terminator.source_info.span) {
+                                spans.push(terminator.source_info.span);
+                            }
+                        }
+                    }
+                },
+                TerminatorKind::SwitchInt { discr, targets, .. } => {
+                    let switch_local = operand_local(discr);
+                    let is_discr = switch_local.map_or(false, |local| self.discrs.contains(&local));
+                    let null_check = switch_local.and_then(|local| self.null_checks.get(&local));
+                    for (value, target) in targets.iter() {
+                        let mut variants = variants.clone();
+                        if is_discr {
+                            variants.retain(|v| discr_values.iter().any(|(idx, val)| idx == v && *val == value));
+                        }
+                        // the pointer is null on the false edge of `!is_null`, there is nothing to release
+                        let mut released = released.clone();
+                        if let Some((path, false)) = null_check {
+                            if value == 0 {
+                                released.insert(path.clone());
+                            }
+                        }
+                        succs.push((target.as_usize(), variants, released));
+                    }
+                    let mut variants = variants.clone();
+                    if is_discr {
+                        variants.retain(|v| !discr_values.iter().any(|(idx, val)| idx == v && targets.iter().any(|(value, _)| value == *val)));
+                    }
+                    // the pointer is null on the otherwise (true) edge of `is_null`
+                    let mut released = released.clone();
+                    if let Some((path, true)) = null_check {
+                        released.insert(path.clone());
+                    }
+                    succs.push((targets.otherwise().as_usize(), variants, released));
+                },
+                _ => {
+                    for succ in terminator.successors() {
+                        succs.push((succ.as_usize(), variants.clone(), released.clone()));
+                    }
+                },
+            }
+
+            for (succ, variants, released) in succs {
+                if variants.is_empty() || self.body.basic_blocks()[BasicBlock::from_usize(succ)].is_cleanup {
+                    continue;
+                }
+                let key = (succ, variants);
+                match states.get_mut(&key) {
+                    Some(state) => {
+                        let merged: BTreeSet<FieldPath> = state.intersection(&released).cloned().collect();
+                        if merged.len() != state.len() {
+                            *state = merged;
+                            worklist.push_back(key);
+                        }
+                    },
+                    None => {
+                        states.insert(key.clone(), released);
+                        worklist.push_back(key);
+                    },
+                }
+            }
+        }
+
+        bits.iter()
+            .filter_map(|(path, field)| leaks.get(path).map(|spans| (path.clone(), *field, spans.clone())))
+            .collect()
+    }
+}
@@ -0,0 +1,294 @@
+use rustc_middle::mir::{AggregateKind, Body, Local, Operand, Place, ProjectionElem, Rvalue, StatementKind,
+                        TerminatorKind};
+use rustc_middle::ty::TyCtxt;
+use rustc_middle::ty::subst::GenericArgKind;
+use rustc_span::def_id::DefId;
+use rustc_target::abi::VariantIdx;
+
+use crate::rlc_info;
+use crate::analysis::RcxMut;
+use crate::analysis::flow_analysis::inter_visitor::callee_def_id;
+use crate::analysis::flow_analysis::known_fn::KnownFn;
+use crate::analysis::type_analysis::TypeAnalysis;
+use crate::analysis::type_analysis::ownership::RawTypeOwner;
+use crate::analysis::type_analysis::type_visitor::mir_body;
+use crate::components::context::RlcGlobalCtxt;
+use crate::components::report::{ProxySite, ProxyTypeReport};
+
+use std::collections::{HashMap, HashSet};
+
+// The raw pointer field of an adt: the adt, the variant and the index of the field in the variant.
+pub(crate) type ProxyField = (DefId, VariantIdx, usize);
+// The raw pointer fields of the local adts receiving owned allocations, with the sites storing them.
+pub(crate) type OwnedFields = HashMap<ProxyField, Vec<ProxySite>>;
+
+impl<'tcx, 'a> TypeAnalysis<'tcx, 'a> {
+    // Find the raw pointer fields of the local adts that receive owned allocations somewhere in the
+    // crate, e.g., `Foo { ptr: Box::into_raw(b) }` stores an owned allocation into `Foo::ptr`.
+    pub fn owned_raw_fields(&mut self) -> OwnedFields {
+        let tcx = self.tcx();
+        let mut fields = OwnedFields::default();
+        for each_mir in tcx.mir_keys(()) {
+            let did = each_mir.to_def_id();
+            let body = mir_body(tcx, did);
+            let owned = owned_raw_locals(self.rcx(), body);
+            if owned.is_empty() {
+                continue;
+            }
+
+            for data in body.basic_blocks().iter() {
+                for stmt in data.statements.iter() {
+                    let (place, rvalue) = match &stmt.kind {
+                        StatementKind::Assign(box (place, rvalue)) => (place, rvalue),
+                        _ => continue,
+                    };
+                    // the stores are the field assignments after the deaggregation and the aggregates
+                    let stores: Vec<(ProxyField, &Operand)> = match rvalue {
+                        Rvalue::Use(op) | Rvalue::Cast(_, op, _) => match proxy_field_of(tcx, body, place) {
+                            Some(field) => vec![(field, op)],
+                            None => continue,
+                        },
+                        Rvalue::Aggregate(box AggregateKind::Adt(adt, variant, _, _, active), ops) => ops
+                            .iter()
+                            .enumerate()
+                            .map(|(idx, op)| ((*adt, *variant, active.unwrap_or(idx)), op))
+                            .collect(),
+                        _ => continue,
+                    };
+
+                    for (field, op) in stores {
+                        if !field.0.is_local() || !is_raw_ptr_field(tcx, field) {
+                            continue;
+                        }
+                        if let Some(source) = operand_local(op).and_then(|local| owned.get(&local)) {
+                            let site = ProxySite::new(did, stmt.source_info.span, source.clone());
+                            fields.entry(field).or_default().push(site);
+                        }
+                    }
+                }
+            }
+        }
+        fields
+    }
+
+    // Find the proxy types: the adts whose raw pointer fields receive owned allocations somewhere in
+    // the crate, while neither the adt nor the adts holding it implement Drop. The drop glue does not
+    // follow raw pointers, thus the allocations are leaked whenever the values of the adt are dropped.
+    pub fn proxy_type(&mut self, owned_fields: &OwnedFields) {
+        let tcx = self.tcx();
+        let candidates = proxy_candidates(self.rcx());
+
+        let mut reports: Vec<ProxyTypeReport> = owned_fields
+            .iter()
+            .filter(|(field, _)| candidates.contains(&field.0))
+            .map(|((adt, variant, idx), sites)| {
+                let field_did = tcx.adt_def(*adt).variant(*variant).fields[*idx].did;
+                // the variant is an owner if it is a phantom unit or it holds other owners
+                let owner = self
+                    .adt_owner()
+                    .get(adt)
+                    .and_then(|layout| layout.get(variant.as_usize()))
+                    .map_or(false, |unit| unit.0 == RawTypeOwner::Owned);
+                ProxyTypeReport::new(*adt, field_did, tcx.def_span(field_did), owner, sites.clone())
+            })
+            .collect();
+        reports.sort_by_key(|report| tcx.def_path_hash(report.field()));
+
+        rlc_info!("Proxy Type: {} raw pointer field(s) receive owned allocations without Drop impl", reports.len());
+        *self.rcx_mut().proxy_type_reports_mut() = reports;
+    }
+}
+
+// The adts in the ownership layouts that may be proxy types: they are defined in the analyzed paths of
+// the local crate, they hold raw pointer fields and have no Drop impl, and no adt with a Drop impl holds them.
+fn proxy_candidates(rcx: &RlcGlobalCtxt) -> HashSet<DefId> {
+    let tcx = rcx.tcx();
+    let droppers: Vec<DefId> = rcx
+        .adt_owner()
+        .keys()
+        .filter(|did| did.is_local() && tcx.adt_destructor(**did).is_some())
+        .copied()
+        .collect();
+
+    rcx.adt_owner()
+        .keys()
+        .filter(|did| did.is_local() && tcx.adt_destructor(**did).is_none())
+        .filter(|did| rcx.is_def_analyzed(**did))
+        .filter(|did| tcx.adt_def(**did).all_fields().any(|field| tcx.type_of(field.did).is_unsafe_ptr()))
+        .filter(|did| !droppers.iter().any(|dropper| holds_adt(tcx, *dropper, **did)))
+        .copied()
+        .collect()
+}
+
+// The fields of the holder contain the adt, e.g., `Data` holds `StringPtr` in `Data::String(StringPtr)`.
+fn holds_adt(tcx: TyCtxt, holder: DefId, adt: DefId) -> bool {
+    tcx.adt_def(holder).all_fields().any(|field| {
+        tcx.type_of(field.did).walk().any(|arg| match arg.unpack() {
+            GenericArgKind::Type(ty) => ty.ty_adt_def().map_or(false, |adt_def| adt_def.did() == adt),
+            _ => false,
+        })
+    })
+}
+
+pub(crate) fn is_raw_ptr_field(tcx: TyCtxt, field: ProxyField) -> bool {
+    let (adt, variant, idx) = field;
+    match tcx.adt_def(adt).variant(variant).fields.get(idx) {
+        Some(field_def) => tcx.type_of(field_def.did).is_unsafe_ptr(),
+        None => false,
+    }
+}
+
+// The field of the adt that the place points to, e.g., `(_1 as Field2).0` is the field 0 of the variant Field2.
+pub(crate) fn proxy_field_of<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, place: &Place<'tcx>) -> Option<ProxyField> {
+    let (last, base) = place.projection.split_last()?;
+    let idx = match last {
+        ProjectionElem::Field(field, _) => field.index(),
+        _ => return None,
+    };
+    let base_ty = Place::ty_from(place.local, base, body, tcx);
+    let adt_def = base_ty.ty.ty_adt_def()?;
+    Some((adt_def.did(), base_ty.variant_index.unwrap_or_else(|| VariantIdx::from_u32(0)), idx))
+}
+
+pub(crate) fn operand_local(op: &Operand) -> Option<Local> {
+    match op {
+        Operand::Move(place) | Operand::Copy(place) if place.projection.is_empty() => Some(place.local),
+        _ => None,
+    }
+}
+
+// The raw pointer locals holding owned allocations in the body, with the functions they come from:
+// the known sources (e.g., `Box::into_raw`), the functions modeled with an owned return, and the
+// `as_ptr` of a value forgotten in the body (e.g., `v.as_ptr()` followed by `mem::forget(v)`).
+fn owned_raw_locals(rcx: &RlcGlobalCtxt, body: &Body) -> HashMap<Local, String> {
+    let tcx = rcx.tcx();
+    let forgotten = forgotten_locals(tcx, body);
+    let mut owned: HashMap<Local, String> = HashMap::default();
+
+    for data in body.basic_blocks().iter() {
+        let (func, args, destination) = match &data.terminator().kind {
+            TerminatorKind::Call { func, args, destination, .. } => (func, args, destination),
+            _ => continue,
+        };
+        let callee = match callee_def_id(func) {
+            Some(callee) => callee,
+            None => continue,
+        };
+        if !destination.projection.is_empty() || !body.local_decls[destination.local].ty.is_unsafe_ptr() {
+            continue;
+        }
+
+        let source = match KnownFn::resolve(tcx, callee) {
+            Some(known) if known.is_source() => Some(known.to_string()),
+            _ => None,
+        };
+        let source = source.or_else(|| {
+            rcx.fn_model_of(callee)
+                .filter(|model| model.is_owned_return())
+                .map(|_| tcx.def_path_str(callee))
+        });
+        let source = source.or_else(|| {
+            if !matches!(tcx.item_name(callee).as_str(), "as_ptr" | "as_mut_ptr") {
+                return None;
+            }
+            let borrowed = args.first().and_then(operand_local).and_then(|local| borrowed_local(tcx, body, local))?;
+            if forgotten.contains(&borrowed) {
+                Some(format!("{} of a forgotten value", tcx.def_path_str(callee)))
+            } else {
+                None
+            }
+        });
+        if let Some(source) = source {
+            owned.insert(destination.local, source);
+        }
+    }
+
+    // the pointers are propagated through the copies, the moves and the casts
+    let mut changed = !owned.is_empty();
+    while changed {
+        changed = false;
+        for data in body.basic_blocks().iter() {
+            for stmt in data.statements.iter() {
+                if let StatementKind::Assign(box (place, Rvalue::Use(op) | Rvalue::Cast(_, op, _))) = &stmt.kind {
+                    if !place.projection.is_empty() || owned.contains_key(&place.local) {
+                        continue;
+                    }
+                    if let Some(source) = operand_local(op).and_then(|local| owned.get(&local)).cloned() {
+                        owned.insert(place.local, source);
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+    owned
+}
+
+// The locals moved into `mem::forget` or `ManuallyDrop::new`, and the locals moved into them.
+pub(crate) fn forgotten_locals(tcx: TyCtxt, body: &Body) -> HashSet<Local> {
+    let mut forgotten = HashSet::default();
+    for data in body.basic_blocks().iter() {
+        if let TerminatorKind::Call { func, args, .. } = &data.terminator().kind {
+            let known = callee_def_id(func).and_then(|callee| KnownFn::resolve(tcx, callee));
+            if matches!(known, Some(KnownFn::MemForget) | Some(KnownFn::ManuallyDropNew)) {
+                if let Some(Operand::Move(place)) = args.first() {
+                    if place.projection.is_empty() {
+                        forgotten.insert(place.local);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut changed = !forgotten.is_empty();
+    while changed {
+        changed = false;
+        for data in body.basic_blocks().iter() {
+            for stmt in data.statements.iter() {
+                if let StatementKind::Assign(box (place, Rvalue::Use(Operand::Move(from)))) = &stmt.kind {
+                    if place.projection.is_empty() && from.projection.is_empty() && forgotten.contains(&place.local) {
+                        changed |= forgotten.insert(from.local);
+                    }
+                }
+            }
+        }
+    }
+    forgotten
+}
+
+// Find the local that the reference is borrowed from, the reborrows and the calls of `deref` are
+// resolved transitively, e.g., `CStr::as_ptr(&*CString::deref(&s))` is borrowed from `s`.
+pub(crate) fn borrowed_local(tcx: TyCtxt, body: &Body, local: Local) -> Option<Local> {
+    let mut cur = local;
+    for _ in 0..body.local_decls.len() {
+        let mut next: Option<Local> = None;
+        for data in body.basic_blocks().iter() {
+            for stmt in data.statements.iter() {
+                let rvalue = match &stmt.kind {
+                    StatementKind::Assign(box (place, rvalue)) if place.projection.is_empty() && place.local == cur => rvalue,
+                    _ => continue,
+                };
+                match rvalue {
+                    Rvalue::Ref(_, _, borrowed) if borrowed.projection.is_empty() => return Some(borrowed.local),
+                    Rvalue::Ref(_, _, borrowed) if borrowed.projection.as_ref() == [ProjectionElem::Deref] => {
+                        next = Some(borrowed.local)
+                    },
+                    Rvalue::Use(op) => next = operand_local(op),
+                    _ => return None,
+                }
+            }
+            if let TerminatorKind::Call { func, args, destination, .. } = &data.terminator().kind {
+                if destination.projection.is_empty() && destination.local == cur {
+                    let is_deref = callee_def_id(func)
+                        .map_or(false, |callee| matches!(tcx.item_name(callee).as_str(), "deref" | "deref_mut"));
+                    if !is_deref {
+                        return None;
+                    }
+                    next = args.first().and_then(operand_local);
+                }
+            }
+        }
+        cur = next?;
+    }
+    None
+}
@@ -0,0 +1,263 @@
+use rustc_middle::mir::TerminatorKind;
+use rustc_middle::ty::{self, Ty, TyCtxt, TypeVisitable};
+use rustc_middle::ty::subst::Subst;
+use rustc_span::def_id::DefId;
+use rustc_target::abi::VariantIdx;
+
+use crate::rlc_info;
+use crate::analysis::RcxMut;
+use crate::analysis::flow_analysis::inter_visitor::callee_def_id;
+use crate::analysis::flow_analysis::known_fn::KnownFn;
+use crate::analysis::type_analysis::TypeAnalysis;
+use crate::analysis::type_analysis::type_visitor::{mir_body, TyWithIndex};
+use crate::components::report::{RawPairingReport, RawSite};
+
+use std::collections::HashMap;
+
+// The raw pointers escaped and recovered in the crate for one pointee type, the sites are kept with
+// the known functions escaping or recovering them, in the order of the bodies.
+struct RawInventory<'tcx> {
+    ty: Ty<'tcx>,
+    escapes: Vec<(KnownFn, RawSite)>,
+    recoveries: Vec<(KnownFn, RawSite)>,
+}
+
+// The escape or recovery of a generic pointee, e.g., `Box::from_raw` in `fn free<T>(p: *mut T)`, it escapes
+// or recovers the types that the function is instantiated with at the call sites of the crate.
+struct GenericSite<'tcx> {
+    known: KnownFn,
+    site: RawSite,
+    pointee: Ty<'tcx>,
+    is_escape: bool,
+    // whether some instantiation is found, or some call site is generic as well
+    instantiated: bool,
+    unresolved: bool,
+}
+
+impl<'tcx, 'a> TypeAnalysis<'tcx, 'a> {
+    // Pair the raw pointers escaped by `Box::into_raw`, `Arc::into_raw` and `CString::into_raw` with the
+    // recoveries of the crate: the pointee types are the identities of the pointers across the functions.
+    // Each recovery whose constructor differs from all the escapes of its type is reported, as it frees
+    // the pointers with a wrong allocator or layout, and a type escaped by `Box::into_raw` that is not
+    // recovered by `Box::from_raw` in any function is never released.
+    pub fn raw_pairing(&mut self) {
+        let tcx = self.tcx();
+        let dids: Vec<DefId> = tcx.mir_keys(()).iter().map(|each_mir| each_mir.to_def_id()).collect();
+        let mut keys: Vec<TyWithIndex<'tcx>> = Vec::default();
+        let mut inventory: HashMap<TyWithIndex<'tcx>, RawInventory<'tcx>> = HashMap::default();
+        let mut generics: Vec<GenericSite<'tcx>> = Vec::default();
+
+        let mut record = |pointee: Ty<'tcx>, known: KnownFn, site: RawSite, is_escape: bool| {
+            let key = pointee_key(pointee);
+            if key == TyWithIndex(None) {
+                return;
+            }
+            let entry = inventory.entry(key.clone()).or_insert_with(|| {
+                keys.push(key);
+                RawInventory { ty: pointee, escapes: Vec::default(), recoveries: Vec::default() }
+            });
+            // the same site is recorded once even if its function is instantiated with the type many times
+            let sites = if is_escape { &mut entry.escapes } else { &mut entry.recoveries };
+            if !sites.iter().any(|(k, s)| *k == known && s.span() == site.span()) {
+                sites.push((known, site));
+            }
+        };
+
+        for did in dids.iter() {
+            let body = mir_body(tcx, *did);
+            for data in body.basic_blocks().iter() {
+                let terminator = data.terminator();
+                let (func, args, destination) = match &terminator.kind {
+                    TerminatorKind::Call { func, args, destination, .. } => (func, args, destination),
+                    _ => continue,
+                };
+                let known = match callee_def_id(func).and_then(|callee| KnownFn::resolve(tcx, callee)) {
+                    Some(known) => known,
+                    None => continue,
+                };
+
+                // the pointer is the return value of the escape, and the first arg of the recovery
+                let is_escape = known.paired_recovery().is_some();
+                let ptr_ty = if is_escape {
+                    destination.ty(body, tcx).ty
+                } else if is_paired_recovery(known) {
+                    match args.first() {
+                        Some(arg) => arg.ty(body, tcx),
+                        None => continue,
+                    }
+                } else {
+                    continue;
+                };
+                let pointee = match ptr_ty.builtin_deref(true) {
+                    Some(type_and_mut) => tcx.erase_regions(type_and_mut.ty),
+                    None => continue,
+                };
+
+                let site = RawSite::new(*did, terminator.source_info.span, known.to_string());
+                if !pointee.needs_subst() {
+                    record(pointee, known, site, is_escape);
+                } else {
+                    let generic = GenericSite { known, site, pointee, is_escape, instantiated: false, unresolved: false };
+                    generics.push(generic);
+                }
+            }
+        }
+
+        // the generic sites take the pointee types substituted by the call sites of their functions, e.g.,
+        // `into_handle::<Node>` escapes `Node` by the `Box::into_raw` in `fn into_handle<T>(b: Box<T>) -> *mut T`
+        for did in dids.iter() {
+            let body = mir_body(tcx, *did);
+            for data in body.basic_blocks().iter() {
+                let func = match &data.terminator().kind {
+                    TerminatorKind::Call { func, .. } => func,
+                    _ => continue,
+                };
+                let (callee, substs) = match func.ty(body, tcx).kind() {
+                    ty::FnDef(callee, substs) => (*callee, *substs),
+                    _ => continue,
+                };
+                for generic in generics.iter_mut().filter(|generic| generic.site.did() == callee) {
+                    let pointee = tcx.erase_regions(ty::EarlyBinder(generic.pointee).subst(tcx, substs));
+                    if pointee.needs_subst() {
+                        generic.unresolved = true;
+                        continue;
+                    }
+                    generic.instantiated = true;
+                    record(pointee, generic.known, generic.site.clone(), generic.is_escape);
+                }
+            }
+        }
+        // the generic sites without any concrete instantiation may take any type, e.g., the public functions
+        // called by the other crates, the recoveries are listed in the reports and the escapes in the summary
+        let (unresolved_escapes, unresolved): (Vec<GenericSite<'tcx>>, Vec<GenericSite<'tcx>>) = generics
+            .into_iter()
+            .filter(|generic| generic.unresolved || !generic.instantiated)
+            .partition(|generic| generic.is_escape);
+        let unresolved: Vec<(KnownFn, RawSite)> = unresolved
+            .into_iter()
+            .map(|generic| (generic.known, generic.site))
+            .collect();
+        let unresolved_escapes: Vec<RawSite> = unresolved_escapes
+            .into_iter()
+            .map(|generic| generic.site)
+            .filter(|site| self.rcx().is_span_analyzed(site.span()))
+            .collect();
+
+        let mut reports = Vec::default();
+        for key in keys.iter() {
+            let entry = &inventory[key];
+            let escapes: Vec<RawSite> = entry
+                .escapes
+                .iter()
+                .filter(|(_, site)| self.rcx().is_span_analyzed(site.span()))
+                .map(|(_, site)| site.clone())
+                .collect();
+            if escapes.is_empty() {
+                continue;
+            }
+            let mut families: Vec<KnownFn> = Vec::default();
+            for (known, _) in entry.escapes.iter() {
+                if !families.contains(known) {
+                    families.push(*known);
+                }
+            }
+            let paired: Vec<KnownFn> = families.iter().filter_map(|known| known.paired_recovery()).collect();
+            let expected = names(&paired, " or ");
+
+            // each recovery of a constructor that matches none of the escapes, whether or not the paired
+            // recovery appears elsewhere
+            let mut mismatched: Vec<KnownFn> = Vec::default();
+            for (known, _) in entry.recoveries.iter() {
+                if !paired.contains(known) && !mismatched.contains(known) {
+                    mismatched.push(*known);
+                }
+            }
+            for recovery in mismatched.iter() {
+                let recoveries: Vec<RawSite> = entry
+                    .recoveries
+                    .iter()
+                    .filter(|(known, _)| known == recovery)
+                    .map(|(_, site)| site.clone())
+                    .collect();
+                reports.push(RawPairingReport::new(
+                    entry.ty.to_string(),
+                    names(&families, ", "),
+                    expected.clone(),
+                    escapes.clone(),
+                    recoveries,
+                    Vec::default(),
+                ));
+            }
+
+            // the escapes never recovered by the paired constructor, unless reported as mismatched above
+            if !mismatched.is_empty() {
+                continue;
+            }
+            for escape in families.iter() {
+                let expected = escape.paired_recovery().unwrap();
+                if entry.recoveries.iter().any(|(known, _)| *known == expected) {
+                    continue;
+                }
+                let sites: Vec<RawSite> = entry
+                    .escapes
+                    .iter()
+                    .filter(|(known, site)| known == escape && self.rcx().is_span_analyzed(site.span()))
+                    .map(|(_, site)| site.clone())
+                    .collect();
+                if sites.is_empty() {
+                    continue;
+                }
+                let generic_recoveries: Vec<RawSite> = unresolved
+                    .iter()
+                    .filter(|(known, _)| *known == expected)
+                    .map(|(_, site)| site.clone())
+                    .collect();
+                reports.push(RawPairingReport::new(
+                    entry.ty.to_string(),
+                    escape.to_string(),
+                    expected.to_string(),
+                    sites,
+                    Vec::default(),
+                    generic_recoveries,
+                ));
+            }
+        }
+        reports.sort_by_key(|report| tcx.def_path_hash(report.escapes()[0].did()));
+
+        rlc_info!("Raw Pairing: {} escaped pointee type(s) without the paired recovery", reports.len());
+        if !unresolved_escapes.is_empty() {
+            rlc_info!("Raw Pairing: {} generic escape(s) are not instantiated in the crate", unresolved_escapes.len());
+            let source_map = tcx.sess.source_map();
+            for site in unresolved_escapes.iter() {
+                rlc_info!(
+                    "    {}: {} at {}",
+                    tcx.def_path_str(site.did()),
+                    site.callee(),
+                    source_map.span_to_diagnostic_string(site.span()),
+                );
+            }
+        }
+        *self.rcx_mut().raw_pairing_reports_mut() = reports;
+    }
+}
+
+fn is_paired_recovery(known: KnownFn) -> bool {
+    matches!(known, KnownFn::BoxFromRaw | KnownFn::ArcFromRaw | KnownFn::CStringFromRaw)
+}
+
+// e.g., `Box::from_raw or Arc::from_raw`
+fn names(fns: &[KnownFn], sep: &str) -> String {
+    let mut names: Vec<String> = fns.iter().map(|known| known.to_string()).collect();
+    names.dedup();
+    names.join(sep)
+}
+
+// The identity of the pointee type, the pointer to an enum points to the whole value, thus its first
+// variant stands for the enum.
+fn pointee_key(pointee: Ty<'_>) -> TyWithIndex<'_> {
+    let vidx = pointee
+        .ty_adt_def()
+        .filter(|adt_def| adt_def.is_enum() && !adt_def.variants().is_empty())
+        .map(|_| VariantIdx::from_u32(0));
+    TyWithIndex::new(pointee, vidx)
+}
@@ -0,0 +1,222 @@
+use rustc_middle::mir::{BasicBlock, Body, Local, Operand, ProjectionElem, Rvalue, StatementKind, TerminatorKind};
+use rustc_middle::ty::{Ty, TyCtxt};
+use rustc_span::Span;
+use rustc_span::def_id::DefId;
+use rustc_span::symbol::sym;
+
+use crate::rlc_info;
+use crate::analysis::RcxMut;
+use crate::analysis::flow_analysis::inter_visitor::callee_def_id;
+use crate::analysis::flow_analysis::known_fn::KnownFn;
+use crate::analysis::type_analysis::TypeAnalysis;
+use crate::analysis::type_analysis::proxy_type::{borrowed_local, forgotten_locals, operand_local, proxy_field_of,
+                                                 ProxyField};
+use crate::analysis::type_analysis::type_visitor::mir_body;
+use crate::components::report::{RawPartsMismatch, RawPartsReport};
+
+use std::collections::HashMap;
+
+// The raw parts of a Vec or a String whose buffer is kept after the value is forgotten.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum RawPart {
+    Ptr,
+    Len,
+    Cap,
+}
+
+// The raw part held by a local or a field, the length is shrunk if `shrink_to_fit` is called on the
+// value on all the paths before, thus it equals the capacity. The span is where the part is taken from the value.
+#[derive(Debug, Copy, Clone)]
+struct PartValue {
+    part: RawPart,
+    shrunk: bool,
+    span: Span,
+}
+
+// The raw parts stored into the fields of the adts, the field holds the part only if all stores agree.
+type FieldParts = HashMap<ProxyField, PartValue>;
+
+impl<'tcx, 'a> TypeAnalysis<'tcx, 'a> {
+    // Check the reconstructions by `Vec::from_raw_parts` and `String::from_raw_parts` whose pointer is taken
+    // from a forgotten value (by `as_ptr` and `mem::forget`, or by `into_raw_parts`): the capacity must be
+    // the original capacity, or the length if the value is shrunk by `shrink_to_fit`, otherwise the buffer
+    // is freed with a wrong layout. The parts are traced through the locals and the fields of the adts,
+    // e.g., `BytesPtr { ptr, len }` built in one function and rebuilt in the Drop impl.
+    pub fn raw_parts(&mut self) {
+        let tcx = self.tcx();
+        let dids: Vec<DefId> = tcx.mir_keys(()).iter().map(|each_mir| each_mir.to_def_id()).collect();
+
+        // the parts stored into the fields are collected first, they are read in the other functions
+        let mut stores: HashMap<ProxyField, Vec<PartValue>> = HashMap::default();
+        for did in dids.iter() {
+            let body = mir_body(tcx, *did);
+            let parts = body_parts(tcx, body, &FieldParts::default());
+            for data in body.basic_blocks().iter() {
+                for stmt in data.statements.iter() {
+                    let (place, op) = match &stmt.kind {
+                        StatementKind::Assign(box (place, Rvalue::Use(op) | Rvalue::Cast(_, op, _))) => (place, op),
+                        _ => continue,
+                    };
+                    let field = match proxy_field_of(tcx, body, place) {
+                        Some(field) => field,
+                        None => continue,
+                    };
+                    if let Some(value) = operand_local(op).and_then(|local| parts.get(&local)) {
+                        stores.entry(field).or_default().push(*value);
+                    }
+                }
+            }
+        }
+        let field_parts: FieldParts = stores
+            .into_iter()
+            .filter(|(_, values)| values.iter().all(|value| value.part == values[0].part))
+            .map(|(field, values)| {
+                let shrunk = values.iter().all(|value| value.shrunk);
+                (field, PartValue { part: values[0].part, shrunk, span: values[0].span })
+            })
+            .collect();
+
+        // the parts stored in the excluded paths are still traced, while only the reconstructions in the
+        // included paths are reported
+        let mut reports = Vec::default();
+        for did in dids.into_iter().filter(|did| self.rcx().is_def_analyzed(*did)) {
+            let body = mir_body(tcx, did);
+            let parts = body_parts(tcx, body, &field_parts);
+            for data in body.basic_blocks().iter() {
+                let terminator = data.terminator();
+                let (func, args) = match &terminator.kind {
+                    TerminatorKind::Call { func, args, .. } => (func, args),
+                    _ => continue,
+                };
+                let known = match callee_def_id(func).and_then(|callee| KnownFn::resolve(tcx, callee)) {
+                    Some(known @ (KnownFn::VecFromRawParts | KnownFn::StringFromRawParts)) => known,
+                    _ => continue,
+                };
+                let ptr = match args.first().and_then(operand_local).and_then(|local| parts.get(&local)) {
+                    Some(value) if value.part == RawPart::Ptr => *value,
+                    _ => continue,
+                };
+                let cap = args.get(2).and_then(operand_local).and_then(|local| parts.get(&local));
+                let mismatch = match cap {
+                    Some(value) if value.part == RawPart::Cap => continue,
+                    Some(value) if value.part == RawPart::Len && value.shrunk => continue,
+                    Some(value) if value.part == RawPart::Len => RawPartsMismatch::Length(value.span),
+                    _ => RawPartsMismatch::Untraced,
+                };
+                reports.push(RawPartsReport::new(did, terminator.source_info.span, known.to_string(), ptr.span, mismatch));
+            }
+        }
+
+        rlc_info!("Raw Parts: {} reconstruction(s) with a mismatched capacity", reports.len());
+        *self.rcx_mut().raw_parts_reports_mut() = reports;
+    }
+}
+
+fn is_vec_or_string(tcx: TyCtxt, ty: Ty) -> bool {
+    match ty.ty_adt_def() {
+        Some(adt_def) => tcx.is_diagnostic_item(sym::Vec, adt_def.did()) || tcx.is_diagnostic_item(sym::String, adt_def.did()),
+        None => false,
+    }
+}
+
+// The locals holding the raw parts of the Vec and String values in the body, through the calls taking
+// the parts, the copies and the casts (e.g., `v.len() as u32`), and the fields holding the parts.
+fn body_parts<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, field_parts: &FieldParts) -> HashMap<Local, PartValue> {
+    let forgotten = forgotten_locals(tcx, body);
+    let mut parts: HashMap<Local, PartValue> = HashMap::default();
+    // the tuples returned by `into_raw_parts` and the spans of the calls
+    let mut tuples: HashMap<Local, Span> = HashMap::default();
+
+    // the blocks calling `shrink_to_fit` on the values, the length is shrunk only if the call dominates
+    // the call taking the length, as the calls of the body are not ordered
+    let mut shrinks: HashMap<Local, Vec<BasicBlock>> = HashMap::default();
+    for (bb, data) in body.basic_blocks().iter_enumerated() {
+        if let TerminatorKind::Call { func, args, .. } = &data.terminator().kind {
+            let known = callee_def_id(func).and_then(|callee| KnownFn::resolve(tcx, callee));
+            if !matches!(known, Some(KnownFn::VecShrinkToFit | KnownFn::StringShrinkToFit)) {
+                continue;
+            }
+            if let Some(value) = args.first().and_then(operand_local).and_then(|local| borrowed_local(tcx, body, local)) {
+                shrinks.entry(value).or_default().push(bb);
+            }
+        }
+    }
+    let dominators = body.basic_blocks.dominators();
+
+    for (bb, data) in body.basic_blocks().iter_enumerated() {
+        let terminator = data.terminator();
+        let (func, args, destination) = match &terminator.kind {
+            TerminatorKind::Call { func, args, destination, .. } if destination.projection.is_empty() => (func, args, destination),
+            _ => continue,
+        };
+        let known = match callee_def_id(func).and_then(|callee| KnownFn::resolve(tcx, callee)) {
+            Some(known) => known,
+            None => continue,
+        };
+        let span = terminator.source_info.span;
+        let arg = match args.first().and_then(operand_local) {
+            Some(arg) => arg,
+            None => continue,
+        };
+        if matches!(known, KnownFn::VecIntoRawParts | KnownFn::StringIntoRawParts) {
+            tuples.insert(destination.local, span);
+            continue;
+        }
+
+        let value = match borrowed_local(tcx, body, arg) {
+            Some(value) if is_vec_or_string(tcx, body.local_decls[value].ty) => value,
+            _ => continue,
+        };
+        let part = match known {
+            KnownFn::VecAsPtr | KnownFn::VecAsMutPtr | KnownFn::StrAsPtr | KnownFn::StrAsMutPtr if forgotten.contains(&value) => {
+                RawPart::Ptr
+            },
+            KnownFn::VecLen | KnownFn::StringLen => RawPart::Len,
+            KnownFn::VecCapacity | KnownFn::StringCapacity => RawPart::Cap,
+            _ => continue,
+        };
+        let shrunk = shrinks
+            .get(&value)
+            .map_or(false, |blocks| blocks.iter().any(|shrink| *shrink != bb && dominators.is_dominated_by(bb, *shrink)));
+        parts.insert(destination.local, PartValue { part, shrunk, span });
+    }
+
+    // the parts are propagated through the copies, the moves and the casts until the fixed point
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for data in body.basic_blocks().iter() {
+            for stmt in data.statements.iter() {
+                let (place, op) = match &stmt.kind {
+                    StatementKind::Assign(box (place, Rvalue::Use(op) | Rvalue::Cast(_, op, _))) if place.projection.is_empty() => (place, op),
+                    _ => continue,
+                };
+                if parts.contains_key(&place.local) {
+                    continue;
+                }
+                let from = match op {
+                    Operand::Move(from) | Operand::Copy(from) => from,
+                    _ => continue,
+                };
+                let value = if from.projection.is_empty() {
+                    parts.get(&from.local).copied()
+                } else if let (Some(span), [ProjectionElem::Field(field, _)]) = (tuples.get(&from.local), from.projection.as_ref()) {
+                    // the (ptr, len, cap) of `into_raw_parts`
+                    let part = match field.index() {
+                        0 => RawPart::Ptr,
+                        1 => RawPart::Len,
+                        _ => RawPart::Cap,
+                    };
+                    Some(PartValue { part, shrunk: false, span: *span })
+                } else {
+                    proxy_field_of(tcx, body, from).and_then(|field| field_parts.get(&field)).copied()
+                };
+                if let Some(value) = value {
+                    parts.insert(place.local, value);
+                    changed = true;
+                }
+            }
+        }
+    }
+    parts
+}
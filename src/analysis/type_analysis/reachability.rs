@@ -0,0 +1,331 @@
+use rustc_hir::def::DefKind;
+use rustc_middle::mir::{Body, Local, Rvalue, StatementKind, TerminatorKind};
+use rustc_middle::ty::{self, Instance, InstanceDef, ParamEnv, Ty, TyCtxt, TypeVisitable};
+use rustc_middle::ty::subst::InternalSubsts;
+use rustc_span::def_id::DefId;
+
+use crate::analysis::RcxMut;
+use crate::analysis::flow_analysis::inter_visitor::callee_def_id;
+use crate::analysis::flow_analysis::known_fn::KnownFn;
+use crate::analysis::type_analysis::TypeAnalysis;
+use crate::analysis::type_analysis::proxy_type::operand_local;
+use crate::analysis::type_analysis::type_visitor::mir_body;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// The instances visited beyond the limit are not expanded any more and taken as unknown, it keeps the
+// walk through the MIR of the dependencies (e.g., std) bounded.
+pub const REACHABILITY_NODE_LIMIT: usize = 50_000;
+
+pub type AllocEffects = HashMap<DefId, AllocEffect>;
+// The indexes of the params of the local functions whose pointers reach a deallocation in the function.
+pub type DeallocParams = HashMap<DefId, Vec<usize>>;
+
+// AllocEffect tells whether a function may reach the allocator of Rust (`__rust_alloc` and the like)
+// or the deallocator (`__rust_dealloc`) through its callees and the drop glue of its locals. It is an
+// over-approximation: the calls that cannot be resolved (e.g., through dyn or fn pointers) may do both,
+// and the function reaching any of them is opaque.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AllocEffect {
+    may_alloc: bool,
+    may_dealloc: bool,
+    opaque: bool,
+}
+
+impl AllocEffect {
+    pub fn new(may_alloc: bool, may_dealloc: bool) -> Self {
+        Self { may_alloc, may_dealloc, opaque: false }
+    }
+
+    // The effect of the functions that are not analyzed.
+    pub fn unknown() -> Self {
+        Self { may_alloc: true, may_dealloc: true, opaque: true }
+    }
+
+    pub fn may_alloc(&self) -> bool {
+        self.may_alloc
+    }
+
+    pub fn may_dealloc(&self) -> bool {
+        self.may_dealloc
+    }
+
+    // Whether the effect comes from the unresolved calls in part, i.e., it is not exact.
+    pub fn is_opaque(&self) -> bool {
+        self.opaque
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            may_alloc: self.may_alloc || other.may_alloc,
+            may_dealloc: self.may_dealloc || other.may_dealloc,
+            opaque: self.opaque || other.opaque,
+        }
+    }
+
+    // The effect of the allocator shims declared in `alloc::alloc`, the other foreign functions are
+    // opaque to rustc and taken as neither, like the llvm phase does.
+    fn of_foreign(name: &str) -> Self {
+        match name {
+            "__rust_alloc" | "__rust_alloc_zeroed" => Self::new(true, false),
+            "__rust_dealloc" => Self::new(false, true),
+            "__rust_realloc" => Self::new(true, true),
+            _ => Self::default(),
+        }
+    }
+}
+
+// The call graph of the monomorphized instances reachable from the functions of the local crate. The
+// instances with params are keyed with the param env of their root, since the generic roots are walked
+// with their own params and the params of two roots are not the same even if they look alike.
+struct InstanceGraph<'tcx> {
+    nodes: Vec<(ParamEnv<'tcx>, Instance<'tcx>)>,
+    index: HashMap<(ParamEnv<'tcx>, Instance<'tcx>), usize>,
+    effects: Vec<AllocEffect>,
+    pre: Vec<Vec<usize>>,
+}
+
+impl<'tcx> InstanceGraph<'tcx> {
+    fn new() -> Self {
+        Self {
+            nodes: Vec::default(),
+            index: HashMap::default(),
+            effects: Vec::default(),
+            pre: Vec::default(),
+        }
+    }
+
+    // Get the node of the instance, the new node is pushed into the queue to be expanded.
+    fn node(&mut self, key: (ParamEnv<'tcx>, Instance<'tcx>), queue: &mut VecDeque<usize>) -> usize {
+        if let Some(idx) = self.index.get(&key) {
+            return *idx;
+        }
+        let idx = self.nodes.len();
+        self.nodes.push(key);
+        self.index.insert(key, idx);
+        self.pre.push(Vec::default());
+        if idx < REACHABILITY_NODE_LIMIT {
+            self.effects.push(AllocEffect::default());
+            queue.push_back(idx);
+        } else {
+            self.effects.push(AllocEffect::unknown());
+        }
+        idx
+    }
+
+    fn add_effect(&mut self, idx: usize, effect: AllocEffect) {
+        self.effects[idx] = self.effects[idx].union(&effect);
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize) {
+        self.pre[to].push(from);
+    }
+
+    // Propagate the effects from the callees to the callers until the fixed point.
+    fn propagate(&mut self) {
+        let mut worklist: VecDeque<usize> = (0..self.nodes.len()).collect();
+        while let Some(idx) = worklist.pop_front() {
+            let effect = self.effects[idx];
+            for pidx in self.pre[idx].clone() {
+                let merged = self.effects[pidx].union(&effect);
+                if merged != self.effects[pidx] {
+                    self.effects[pidx] = merged;
+                    worklist.push_back(pidx);
+                }
+            }
+        }
+    }
+}
+
+impl<'tcx, 'a> TypeAnalysis<'tcx, 'a> {
+    // Compute the may-allocate and may-deallocate functions over MIR, it replaces the llvm phase which
+    // finds the functions reaching `__rust_dealloc` in the llvm ir. The roots are the functions of the
+    // local crate, and the calls are resolved to the instances under the substs of the callers, thus
+    // the generic functions of the dependencies are walked with the concrete types where possible.
+    pub fn reachability(&mut self) {
+        let tcx = self.tcx();
+        let mut graph = InstanceGraph::new();
+        let mut queue = VecDeque::default();
+
+        let mut roots = Vec::default();
+        for each_mir in tcx.mir_keys(()) {
+            let did = each_mir.to_def_id();
+            if !matches!(tcx.def_kind(did), DefKind::Fn | DefKind::AssocFn | DefKind::Closure) {
+                continue;
+            }
+            let instance = Instance::new(did, InternalSubsts::identity_for_item(tcx, did));
+            let root = graph.node(instance_key(tcx.param_env(did), instance), &mut queue);
+            roots.push((did, root));
+        }
+
+        while let Some(idx) = queue.pop_front() {
+            let (param_env, instance) = graph.nodes[idx];
+            match instance_body(tcx, instance) {
+                Ok(Some(body)) => visit_instance_body(tcx, param_env, instance, body, idx, &mut graph, &mut queue),
+                Ok(None) => (),
+                Err(effect) => graph.add_effect(idx, effect),
+            }
+        }
+
+        graph.propagate();
+
+        // the effects of the instances are merged into their def ids, thus a def id may deallocate
+        // if any instance of it may deallocate
+        let mut effects = AllocEffects::default();
+        for (idx, (_, instance)) in graph.nodes.iter().enumerate() {
+            let effect = effects.entry(instance.def_id()).or_default();
+            *effect = effect.union(&graph.effects[idx]);
+        }
+        for (did, root) in roots {
+            effects.insert(did, graph.effects[root]);
+        }
+
+        *self.rcx_mut().alloc_effects_mut() = effects;
+        *self.rcx_mut().dealloc_params_mut() = dealloc_params(tcx);
+    }
+}
+
+// The params of the local functions whose pointers are passed to the deallocator, to a recovery like
+// `Box::from_raw`, or to a dealloc param of another local function, until the fixed point. Unlike the
+// effects, a function deallocating other pointers does not release the pointers passed to it.
+fn dealloc_params(tcx: TyCtxt) -> DeallocParams {
+    let dids: Vec<DefId> = tcx.mir_keys(()).iter().map(|each_mir| each_mir.to_def_id()).collect();
+    let mut params = DeallocParams::default();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for did in dids.iter() {
+            let body = mir_body(tcx, *did);
+            for pidx in 0..body.arg_count {
+                if params.get(did).map_or(false, |found| found.contains(&pidx)) {
+                    continue;
+                }
+                if reaches_dealloc(tcx, body, Local::from_usize(pidx + 1), &params) {
+                    params.entry(*did).or_default().push(pidx);
+                    changed = true;
+                }
+            }
+        }
+    }
+    params
+}
+
+// Whether the pointer in the param reaches a deallocation, it is followed through the copies and the casts.
+fn reaches_dealloc<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, param: Local, params: &DeallocParams) -> bool {
+    let mut aliases: HashSet<Local> = HashSet::default();
+    aliases.insert(param);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for data in body.basic_blocks().iter() {
+            for stmt in data.statements.iter() {
+                if let StatementKind::Assign(box (place, Rvalue::Use(op) | Rvalue::Cast(_, op, _))) = &stmt.kind {
+                    let from = operand_local(op).filter(|local| aliases.contains(local));
+                    if from.is_some() && place.projection.is_empty() && aliases.insert(place.local) {
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    body.basic_blocks().iter().any(|data| match &data.terminator().kind {
+        TerminatorKind::Call { func, args, .. } => {
+            let callee = match callee_def_id(func) {
+                Some(callee) => callee,
+                None => return false,
+            };
+            args.iter().enumerate().any(|(aidx, arg)| {
+                operand_local(arg).map_or(false, |local| aliases.contains(&local)) && is_dealloc_arg(tcx, callee, aidx, params)
+            })
+        },
+        _ => false,
+    })
+}
+
+fn is_dealloc_arg(tcx: TyCtxt, callee: DefId, aidx: usize, params: &DeallocParams) -> bool {
+    if params.get(&callee).map_or(false, |found| found.contains(&aidx)) {
+        return true;
+    }
+    if aidx != 0 {
+        return false;
+    }
+    if KnownFn::resolve(tcx, callee).map_or(false, |known| known.is_recovery()) {
+        return true;
+    }
+    if tcx.is_foreign_item(callee) {
+        return matches!(tcx.item_name(callee).as_str(), "__rust_dealloc" | "free");
+    }
+    matches!(tcx.def_path_str(callee).as_str(), "alloc::alloc::dealloc" | "std::alloc::dealloc")
+}
+
+// The concrete instances are shared by all roots, only the ones with params are bound to the root.
+fn instance_key<'tcx>(param_env: ParamEnv<'tcx>, instance: Instance<'tcx>) -> (ParamEnv<'tcx>, Instance<'tcx>) {
+    if instance.substs.needs_subst() {
+        (param_env, instance)
+    } else {
+        (ParamEnv::reveal_all(), instance)
+    }
+}
+
+// The body of the instance, None if it has no effect at all, or the effect if it cannot be walked.
+fn instance_body<'tcx>(tcx: TyCtxt<'tcx>, instance: Instance<'tcx>) -> Result<Option<&'tcx Body<'tcx>>, AllocEffect> {
+    match instance.def {
+        InstanceDef::Item(def) => {
+            if tcx.is_foreign_item(def.did) {
+                return Err(AllocEffect::of_foreign(tcx.item_name(def.did).as_str()));
+            }
+            if matches!(tcx.def_kind(def.did), DefKind::Ctor(..)) {
+                return Ok(None);
+            }
+            if !tcx.is_mir_available(def.did) {
+                return Err(AllocEffect::unknown());
+            }
+            Ok(Some(tcx.instance_mir(instance.def)))
+        },
+        InstanceDef::Intrinsic(..) | InstanceDef::DropGlue(_, None) => Ok(None),
+        InstanceDef::Virtual(..) => Err(AllocEffect::unknown()),
+        _ => Ok(Some(tcx.instance_mir(instance.def))),
+    }
+}
+
+fn visit_instance_body<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    param_env: ParamEnv<'tcx>,
+    instance: Instance<'tcx>,
+    body: &'tcx Body<'tcx>,
+    idx: usize,
+    graph: &mut InstanceGraph<'tcx>,
+    queue: &mut VecDeque<usize>,
+) {
+    let subst = |ty: Ty<'tcx>| instance.try_subst_mir_and_normalize_erasing_regions(tcx, param_env, ty).ok();
+
+    for data in body.basic_blocks().iter() {
+        let callee = match &data.terminator().kind {
+            TerminatorKind::Call { func, .. } => {
+                match subst(func.ty(body, tcx)).map(|ty| ty.kind()) {
+                    Some(ty::FnDef(did, substs)) => Instance::resolve(tcx, param_env, *did, substs).ok().flatten(),
+                    // the calls through fn pointers
+                    _ => None,
+                }
+            },
+            TerminatorKind::Drop { place, .. } | TerminatorKind::DropAndReplace { place, .. } => {
+                match subst(place.ty(body, tcx).ty) {
+                    Some(ty) if !ty.needs_drop(tcx, param_env) => continue,
+                    // the drop glue of the params and the trait objects is unknown until runtime
+                    Some(ty) if !ty.needs_subst() && !ty.is_trait() => Some(Instance::resolve_drop_in_place(tcx, ty)),
+                    _ => None,
+                }
+            },
+            _ => continue,
+        };
+
+        match callee {
+            Some(callee) => {
+                let cidx = graph.node(instance_key(param_env, callee), queue);
+                graph.add_edge(idx, cidx);
+            },
+            None => graph.add_effect(idx, AllocEffect::unknown()),
+        }
+    }
+}
@@ -84,13 +84,11 @@ impl<'tcx, 'a> TypeAnalysis<'tcx, 'a> {
         }
 
         // Get the Global TyCtxt from rustc
-        // Grasp all mir Keys defined in current crate
+        // Grasp all mir Keys defined in current crate and the functions of the dependencies
         let tcx = self.tcx();
-        let mir_keys = tcx.mir_keys(());
 
-        for each_mir in mir_keys {
-            // Get the defid of current crate and get mir Body through this id
-            let def_id = each_mir.to_def_id();
+        for def_id in self.rcx().analyzed_fns() {
+            // Get the mir Body through the defid
             let body = mir_body(tcx, def_id);
             show_mir_if_needed(def_id, body);
 
@@ -1,11 +1,17 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports, unused_variables, unused_mut, dead_code))]
 
-use rlc::{RlcPhase, rlc_info,
-          RLC_DEFAULT_ARGS, RLC_ROOT, RLC_LLVM_CACHE, RLC_LLVM_IR};
+use rlc::{RlcPhase, rlc_info, RLC_CONFIG_ARG, RLC_DEFAULT_ARGS};
 use rlc::components::log::{Verbosity, rlc_error_and_exit};
 use rlc::components::fs::{rlc_create_dir, rlc_remove_dir, rlc_copy_file, rlc_can_read_dir};
+use rlc::components::project::RlcProjectConfig;
+use rlc::components::suppression::rlc_tool_attr_args;
+use rlc::components::sarif::{merge_sarif_runs, RLC_SARIF_DIR_ENV};
+use rlc::components::cache::RLC_CACHE_DIR_ENV;
+use rlc::components::workspace::{RlcWorkspace, RLC_DIR_ENV, RLC_WORKSPACE_ENV};
+use rlc::analysis::type_analysis::RLC_LLVM_CROSS_CHECK_ENV;
 
 use std::env;
+use std::fs;
 use std::iter::TakeWhile;
 use std::process::Command;
 use std::path::{PathBuf, Path};
@@ -24,11 +30,23 @@ Usage:
 
 Options:
     --help                 Print help message
+    --format <FMT>         Output format of the leak reports: text (default) or sarif
+    --output <FILE>        Write the SARIF log to FILE, required by `--format sarif`
+    --no-cache             Solve every function again instead of reusing the results of the last run
+    -j <N>                 Number of workers solving the functions of a crate concurrently (1 by default),
+                           it shadows `-j` of cargo, use `--jobs <N>` for the build jobs of cargo
+    --rlc-dir <DIR>        Directory of the artifacts of RLC instead of `target/rlc/<profile>`
+    --llvm                 Cross-check the deallocating functions with `rlc_phase_llvm` on the LLVM IR
 
 The cargo options are exactly the same as for `cargo run` and `cargo test`, respectively.
+The project config is read from `rlc.toml` in the workspace root and `[package.metadata.rlc]` in the manifest.
+The artifacts are kept in `target/rlc/<profile>`, or in the directory given by `--rlc-dir` or `RLC_DIR`.
+The results of the unchanged functions are cached in `target/rlc/<profile>/cache` and reused in the next run.
 
 Examples:
     cargo rlc run
+    cargo rlc --format sarif --output rlc.sarif
+    cargo rlc -j 8
 "#;
 
 
@@ -98,6 +116,28 @@ fn get_arg_flag_value(name: &str) -> Option<String> {
     ArgFlagValueIter::new(name).next()
 }
 
+/// The flags consumed by cargo-rlc itself, they are not forwarded to cargo.
+const CARGO_RLC_FLAGS: &[&str] = &["--no-cache", "--llvm"];
+const CARGO_RLC_FLAGS_WITH_VALUE: &[&str] = &["--format", "--output", "-j", "--rlc-dir"];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum OutputFormat {
+    Text,
+    Sarif,
+}
+
+impl OutputFormat {
+    // The format in the command line overrides the one in the project config.
+    fn from_args(project: &RlcProjectConfig) -> Self {
+        let format = get_arg_flag_value("--format").or_else(|| project.format().cloned());
+        match format.as_deref() {
+            None | Some("text") => OutputFormat::Text,
+            Some("sarif") => OutputFormat::Sarif,
+            Some(other) => rlc_error_and_exit(format!("Unknown output format: {}, expected text or sarif", other)),
+        }
+    }
+}
+
 /// Returns the path to the `rlc` binary
 fn find_rlc() -> PathBuf {
     let mut path = env::current_exe()
@@ -157,7 +197,8 @@ fn test_sysroot_consistency() {
     );
 }
 
-fn make_package() -> cargo_metadata::Package {
+// Returns the package with the root and the target dir of its workspace.
+fn make_package() -> (cargo_metadata::Package, PathBuf, PathBuf) {
     // We need to get the manifest, and then the metadata, to enumerate targets.
     let manifest_path =
         get_arg_flag_value("--manifest-path")
@@ -194,15 +235,44 @@ fn make_package() -> cargo_metadata::Package {
             rlc_error_and_exit("Workspace is not supported.");
         });
 
-    metadata.packages.remove(package_index)
+    let workspace_root = PathBuf::from(metadata.workspace_root.as_std_path());
+    let target_dir = PathBuf::from(metadata.target_directory.as_std_path());
+    (metadata.packages.remove(package_index), workspace_root, target_dir)
 }
 
-fn make_package_with_sorted_target() -> (cargo_metadata::Package, Vec<cargo_metadata::Target>) {
+fn make_package_with_sorted_target() -> (cargo_metadata::Package, Vec<cargo_metadata::Target>, PathBuf, PathBuf) {
     // Ensure `lib` is compiled before `bin`
-    let package = make_package();
+    let (package, workspace_root, target_dir) = make_package();
     let mut targets: Vec<_> = package.targets.clone().into_iter().collect();
     targets.sort_by_key(|target| TargetKind::from(target) as u8);
-    (package, targets)
+    (package, targets, workspace_root, target_dir)
+}
+
+// The dir name of the profile that cargo builds with, e.g., `debug` for the dev profile.
+fn profile_dir() -> String {
+    match get_arg_flag_value("--profile").as_deref() {
+        Some("dev") | Some("test") => "debug".to_owned(),
+        Some("bench") => "release".to_owned(),
+        Some(profile) => profile.to_owned(),
+        None if has_arg_flag("--release") => "release".to_owned(),
+        None => "debug".to_owned(),
+    }
+}
+
+// The dir of the artifacts of rlc: `--rlc-dir`, then `RLC_DIR`, then `target/rlc/<profile>`.
+// It is made absolute since rlc is invoked by cargo in another dir.
+fn rlc_dir(target_dir: &Path) -> PathBuf {
+    let dir = get_arg_flag_value("--rlc-dir")
+        .or_else(|| env::var(RLC_DIR_ENV).ok())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| target_dir.join("rlc").join(profile_dir()));
+    env::current_dir()
+        .expect("Cannot read current directory")
+        .join(dir)
+}
+
+fn package_workspace(package: &cargo_metadata::Package, target_dir: &Path) -> RlcWorkspace {
+    RlcWorkspace::new(rlc_dir(target_dir).join(&package.name))
 }
 
 fn clean_package(package_name: &str) {
@@ -299,25 +369,26 @@ fn phase_preprocess() {
     // Make sure that the `rlc` and `rustc` binary are from the same sysroot.
     test_sysroot_consistency();
 
-    let mut cmd = Command::new("cargo");
-    cmd.arg("clean");
-    run_cmd(cmd, RlcPhase::PreProcess);
-    rlc_info!("Running cargo clean for local package");
-
-    rlc_remove_dir(RLC_ROOT, "Failed to init RLC root dir");
+    // The whole target dir is not cleaned any more, the packages to analyze are cleaned one by one
+    // in phase_cargo_rlc to make cargo rebuild them, thus the result cache in the target dir is kept.
+    // Only the workspace of this package is wiped, the runs on other packages are left untouched.
+    let (package, _, target_dir) = make_package();
+    let workspace = package_workspace(&package, &target_dir);
+    rlc_remove_dir(workspace.root(), "Failed to init RLC workspace");
 
     rlc_info!("Phase-Preprocess has been done");
 }
 
 fn llvm_ir_emitter() {
     rlc_info!("Ready for RLC Phase II-SubPhase: LLVM-IR-Emitter");
-    let (package, targets) = make_package_with_sorted_target();
+    let (package, targets, _, target_dir) = make_package_with_sorted_target();
+    let workspace = package_workspace(&package, &target_dir);
     for target in targets {
 
         let mut cmd = Command::new("cargo");
         cmd.arg("rustc")
             .arg("--target-dir")
-            .arg(RLC_LLVM_CACHE);
+            .arg(workspace.llvm_cache());
 
         if !is_identified_target(&package, &target, &mut cmd) {
             continue;
@@ -325,7 +396,8 @@ fn llvm_ir_emitter() {
 
         cmd.arg("--")
             .arg("--emit=llvm-ir")
-            .args(RLC_DEFAULT_ARGS);
+            .args(RLC_DEFAULT_ARGS)
+            .args(rlc_tool_attr_args(Some(target.src_path.as_std_path())));
 
         if has_arg_flag("-v") || has_arg_flag("-vv") {
             rlc_info!("Command is: {:?}", cmd);
@@ -335,11 +407,11 @@ fn llvm_ir_emitter() {
             rlc_error_and_exit(format!("Cannot emit llvm ir: {}", e));
         }
 
-        rlc_create_dir(RLC_LLVM_IR, "Failed to creat dir for llvm ir in /tmp");
+        rlc_create_dir(workspace.llvm_ir(), "Failed to creat dir for llvm ir");
 
-        for entry in WalkDir::new(RLC_LLVM_CACHE) {
+        for entry in WalkDir::new(workspace.llvm_cache()) {
             let entry_path = entry.unwrap().into_path();
-            let mut dest_path = PathBuf::from(RLC_LLVM_IR);
+            let mut dest_path = workspace.llvm_ir();
             if entry_path
                 .iter()
                 .last()
@@ -373,7 +445,7 @@ fn llvm_ir_emitter() {
             }
         }
 
-        rlc_remove_dir(RLC_LLVM_CACHE, "Failed to remove RLC_LLVM_Cache");
+        rlc_remove_dir(workspace.llvm_cache(), "Failed to remove RLC_LLVM_Cache");
 
     }
     rlc_info!("Ready for RLC Phase II-SubPhase: LLVM-IR-Emitter");
@@ -384,8 +456,10 @@ fn phase_llvm_ir() {
 
     llvm_ir_emitter();
 
-    if rlc_can_read_dir(RLC_LLVM_IR, "Cannot read LLVM IR files") {
-        for entry in WalkDir::new(RLC_LLVM_IR) {
+    let (package, _, target_dir) = make_package();
+    let workspace = package_workspace(&package, &target_dir);
+    if rlc_can_read_dir(workspace.llvm_ir(), "Cannot read LLVM IR files") {
+        for entry in WalkDir::new(workspace.llvm_ir()) {
             let path = entry.unwrap().into_path();
             if !path
                 .iter()
@@ -415,7 +489,53 @@ fn phase_cargo_rlc() {
 
     rlc_info!("Ready for RLC Phase III: Cargo-RLC");
 
-    let (package, targets) = make_package_with_sorted_target();
+    let (package, targets, workspace_root, target_dir) = make_package_with_sorted_target();
+    let project = RlcProjectConfig::load(&workspace_root, &package.metadata);
+    let mut rlc_config = project.to_rlc_config();
+    if let Some(jobs) = get_arg_flag_value("-j") {
+        let jobs = jobs
+            .parse()
+            .unwrap_or_else(|_| rlc_error_and_exit(format!("Invalid number of jobs: {}", jobs)));
+        rlc_config.set_jobs(jobs);
+    }
+
+    // the extra crates in the project config are appended to the ones in `RLC_ADDITIONAL`,
+    // they are cleaned here since cargo only rebuilds them after they are cleaned
+    let mut additional: Vec<String> = env::var("RLC_ADDITIONAL")
+        .map(|s| s.split(',').filter(|s| !s.is_empty()).map(ToString::to_string).collect())
+        .unwrap_or_default();
+    if let Some(project_additional) = project.additional() {
+        additional.extend(project_additional.iter().cloned());
+    }
+    for name in additional.iter() {
+        clean_package(name);
+    }
+
+    // the workspace is per package while the cache is shared by the packages in the target dir,
+    // the entries of the cache are written atomically
+    let workspace = package_workspace(&package, &target_dir);
+    let cache_dir = if has_arg_flag("--no-cache") {
+        None
+    } else {
+        let cache_dir = rlc_dir(&target_dir).join("cache");
+        fs::create_dir_all(&cache_dir)
+            .unwrap_or_else(|e| rlc_error_and_exit(format!("Failed to create dir for the result cache: {}", e)));
+        Some(cache_dir)
+    };
+
+    let format = OutputFormat::from_args(&project);
+    let output = match format {
+        OutputFormat::Sarif => {
+            let output = get_arg_flag_value("--output")
+                .or_else(|| project.output().cloned())
+                .unwrap_or_else(|| rlc_error_and_exit("The SARIF format requires `--output <FILE>`"));
+            // each rlc invocation writes a partial run into this dir, they are merged after all targets
+            rlc_create_dir(workspace.sarif(), "Failed to create dir for SARIF results");
+            Some(output)
+        },
+        OutputFormat::Text => None,
+    };
+
     for target in targets {
         let mut args = env::args().skip(2);
         // Now we run `cargo check $FLAGS $ARGS`, giving the user the
@@ -434,10 +554,21 @@ fn phase_cargo_rlc() {
         }
 
         // Forward user-defined `cargo` args until first `--`.
+        // The flags of cargo-rlc itself are skipped with their values.
         while let Some(arg) = args.next() {
             if arg == "--" {
                 break;
             }
+            if CARGO_RLC_FLAGS.contains(&arg.as_str()) {
+                continue;
+            }
+            if CARGO_RLC_FLAGS_WITH_VALUE.contains(&arg.as_str()) {
+                args.next();
+                continue;
+            }
+            if CARGO_RLC_FLAGS_WITH_VALUE.iter().any(|flag| arg.starts_with(&format!("{}=", flag))) {
+                continue;
+            }
             cmd.arg(arg);
         }
 
@@ -457,7 +588,11 @@ fn phase_cargo_rlc() {
         // our actual target crate (the binary or the test we are running).
         // Since we're using "cargo check", we have no other way of passing
         // these arguments.
-        let args_vec: Vec<String> = args.collect();
+        // The config of rlc goes first, thus the magic args from the user can override it.
+        let mut args_vec: Vec<String> = vec![
+            format!("{}{}", RLC_CONFIG_ARG, serde_json::to_string(&rlc_config).expect("failed to serialize rlc config")),
+        ];
+        args_vec.extend(args);
         cmd.env(
             "RLC_ARGS",
             serde_json::to_string(&args_vec).expect("failed to serialize args"),
@@ -494,6 +629,31 @@ fn phase_cargo_rlc() {
 
         rlc_add_env(&mut cmd);
 
+        cmd.env(RLC_WORKSPACE_ENV, workspace.root());
+
+        if has_arg_flag("--llvm") {
+            cmd.env(RLC_LLVM_CROSS_CHECK_ENV, "");
+        }
+
+        if format == OutputFormat::Sarif {
+            cmd.env(RLC_SARIF_DIR_ENV, workspace.sarif());
+        }
+
+        if let Some(cache_dir) = &cache_dir {
+            cmd.env(RLC_CACHE_DIR_ENV, cache_dir);
+        }
+
+        if !additional.is_empty() {
+            cmd.env("RLC_ADDITIONAL", additional.join(","));
+        }
+
+        // the functions of the dependencies are analyzed at the Ultra grain, thus all crates are built
+        // with their MIR encoded, the flag goes into RUSTFLAGS so that cargo rebuilds the dependencies
+        if rlc_config.grain().is_cross_crate() || has_rlc_arg_flag("-GRAIN=ULTRA") {
+            let rustflags = env::var("RUSTFLAGS").unwrap_or_default();
+            cmd.env("RUSTFLAGS", format!("{} -Zalways-encode-mir", rustflags).trim_start());
+        }
+
         rlc_info!("Running RLC for target {}:{}", TargetKind::from(&target), &target.name);
 
         let mut child = cmd
@@ -518,6 +678,11 @@ fn phase_cargo_rlc() {
 
     }
 
+    if let Some(output) = output {
+        merge_sarif_runs(workspace.sarif(), &output);
+        rlc_info!("SARIF log has been written to: {}", output);
+    }
+
     rlc_info!("Phase-Cargo-RLC has been done");
 }
 
@@ -683,7 +848,10 @@ fn main() {
         // but with the `RUSTC` env var set to the `cargo-rlc` binary so that we come back in the other branch,
         // and dispatch the invocations to `rustc` and `rlc`, respectively.
         phase_preprocess();
-        // phase_llvm_ir();
+        // the deallocating functions are found over MIR in rlc, the llvm phase is only a cross-check
+        if has_arg_flag("--llvm") {
+            phase_llvm_ir();
+        }
         phase_cargo_rlc();
     } else if let Some("rustc") = env::args().nth(1).as_ref().map(AsRef::as_ref) {
         // `cargo rlc`: `RUSTC_WRAPPER` env var:
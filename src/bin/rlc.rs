@@ -22,15 +22,18 @@ use std::env;
 use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
 
-use rlc::{RlcConfig, compile_time_sysroot, RLC_DEFAULT_ARGS, start_analyzer};
-use rlc::analysis::flow_analysis::{IcxSliceDisplay, Z3GoalDisplay};
-use rlc::analysis::type_analysis::AdtOwnerDisplay;
+use rlc::{RlcConfig, compile_time_sysroot, RLC_CONFIG_ARG, RLC_DEFAULT_ARGS, start_analyzer};
+use rlc::analysis::flow_analysis::{IcxSliceDisplay, UnwindMode, Z3GoalDisplay};
+use rlc::analysis::type_analysis::{AdtOwnerDisplay, DropCheckMode};
 use rlc::components::display::MirDisplay;
 use rlc::components::grain::RlcGrain;
-use rlc::components::log::Verbosity;
+use rlc::components::lint::RLC_LINTS;
+use rlc::components::log::{Verbosity, rlc_error_and_exit};
+use rlc::components::model::FnModel;
+use rlc::components::suppression::rlc_tool_attr_args;
 use rlc::rlc_info;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 struct RlcCompilerCalls {
     rlc_config: RlcConfig,
 }
@@ -60,6 +63,11 @@ impl Callbacks for RlcCompilerCalls {
                crate_source
            };
         });
+
+        // register the lints of rlc, thus the reports respect the lint levels set by the user
+        config.register_lints = Some(Box::new(|_, lint_store| {
+            lint_store.register_lints(RLC_LINTS);
+        }));
     }
 
     fn after_analysis<'tcx>(
@@ -72,7 +80,7 @@ impl Callbacks for RlcCompilerCalls {
 
         rlc_info!("RLC Start");
         queries.global_ctxt().unwrap().peek_mut().enter(
-            |tcx| start_analyzer(tcx, self.rlc_config)
+            |tcx| start_analyzer(tcx, self.rlc_config.clone())
         );
         rlc_info!("RLC Stop");
 
@@ -125,13 +133,44 @@ impl RlcArgs {
 
     pub fn set_icx_slice_display(&mut self) { self.rlc_cc.rlc_config.set_icx_slice_display(IcxSliceDisplay::Verbose); }
 
+    pub fn set_unwind_mode(&mut self) { self.rlc_cc.rlc_config.set_unwind_mode(UnwindMode::Enabled); }
+
+    pub fn set_drop_check_mode(&mut self) { self.rlc_cc.rlc_config.set_drop_check_mode(DropCheckMode::Enabled); }
+
+    pub fn set_config(&mut self, rlc_config: RlcConfig) { self.rlc_cc.rlc_config = rlc_config; }
+
+    pub fn set_solver_timeout(&mut self, ms: u64) { self.rlc_cc.rlc_config.set_solver_timeout(ms); }
+
+    pub fn set_analysis_budget(&mut self, ms: u64) { self.rlc_cc.rlc_config.set_analysis_budget(ms); }
+
+    pub fn set_jobs(&mut self, jobs: usize) { self.rlc_cc.rlc_config.set_jobs(jobs); }
+
+    pub fn add_fn_models(&mut self, path: &str) { self.rlc_cc.rlc_config.fn_models_mut().extend(FnModel::load_file(path)); }
+
     pub fn push_args(&mut self, arg: String) { self.args.push(arg); }
 
     pub fn splice_args(&mut self) {
-        self.args.splice(1..1, RLC_DEFAULT_ARGS.iter().map(ToString::to_string));
+        // the crate root is the source file passed to rustc
+        let crate_root = self.args.iter().skip(1).find(|arg| arg.ends_with(".rs")).map(PathBuf::from);
+        let tool_attr_args = rlc_tool_attr_args(crate_root.as_deref());
+        self.args.splice(1..1, RLC_DEFAULT_ARGS.iter().map(ToString::to_string).chain(tool_attr_args));
     }
 }
 
+// Parse the milliseconds in the magic arg, e.g., -TIMEOUT=5000.
+fn parse_ms(arg: &str, prefix: &str) -> u64 {
+    arg[prefix.len()..]
+        .parse()
+        .unwrap_or_else(|_| rlc_error_and_exit(format!("Invalid milliseconds in {}", arg)))
+}
+
+// Parse the number of the workers in the magic arg, e.g., -JOBS=4.
+fn parse_jobs(arg: &str, prefix: &str) -> usize {
+    arg[prefix.len()..]
+        .parse()
+        .unwrap_or_else(|_| rlc_error_and_exit(format!("Invalid number of jobs in {}", arg)))
+}
+
 fn config_parse() -> RlcArgs {
     let mut rlc_args = RlcArgs::default();
     for arg in env::args() {
@@ -145,6 +184,18 @@ fn config_parse() -> RlcArgs {
             "-ADT=V" => rlc_args.set_adt_display_verbose(),
             "-Z3-GOAL=V" => rlc_args.set_z3_goal_display_verbose(),
             "-ICX-SLICE=V" => rlc_args.set_icx_slice_display(),
+            "-UNWIND" => rlc_args.set_unwind_mode(),
+            "-DROP-CHECK" => rlc_args.set_drop_check_mode(),
+            // the config serialized by cargo-rlc from the project config, it comes before the magic args
+            _ if arg.starts_with(RLC_CONFIG_ARG) => {
+                let config = serde_json::from_str(&arg[RLC_CONFIG_ARG.len()..])
+                    .unwrap_or_else(|e| rlc_error_and_exit(format!("Invalid rlc config in {}: {}", RLC_CONFIG_ARG, e)));
+                rlc_args.set_config(config);
+            },
+            _ if arg.starts_with("-TIMEOUT=") => rlc_args.set_solver_timeout(parse_ms(&arg, "-TIMEOUT=")),
+            _ if arg.starts_with("-BUDGET=") => rlc_args.set_analysis_budget(parse_ms(&arg, "-BUDGET=")),
+            _ if arg.starts_with("-JOBS=") => rlc_args.set_jobs(parse_jobs(&arg, "-JOBS=")),
+            _ if arg.starts_with("-MODELS=") => rlc_args.add_fn_models(&arg["-MODELS=".len()..]),
             _ => rlc_args.push_args(arg),
         }
     }
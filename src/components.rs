@@ -1,5 +1,13 @@
+pub mod cache;
 pub mod context;
 pub mod display;
 pub mod fs;
 pub mod grain;
-pub mod log;
\ No newline at end of file
+pub mod lint;
+pub mod log;
+pub mod model;
+pub mod project;
+pub mod report;
+pub mod sarif;
+pub mod suppression;
+pub mod workspace;
\ No newline at end of file
@@ -0,0 +1,322 @@
+use rustc_data_structures::stable_hasher::{HashStable, StableHasher};
+use rustc_middle::mir::TerminatorKind;
+use rustc_middle::ty::{self, subst::GenericArgKind, TyCtxt};
+use rustc_span::{BytePos, Span, DUMMY_SP};
+use rustc_span::source_map::SourceMap;
+use rustc_span::def_id::DefId;
+
+use crate::rlc_info;
+use crate::analysis::flow_analysis::OwnershipSummary;
+use crate::analysis::flow_analysis::inter_visitor::callee_def_id;
+use crate::analysis::type_analysis::type_visitor::mir_body;
+use crate::components::context::RlcGlobalCtxt;
+use crate::components::report::{LeakPath, LeakReport, LeakVerdict, TraceKind, TraceStep};
+
+use std::fs;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+
+// The env var that holds the dir of the result cache, it is set by cargo-rlc unless `--no-cache`
+// is given, and rlc runs without the cache if it is absent.
+pub static RLC_CACHE_DIR_ENV: &str = "RLC_CACHE_DIR";
+
+// RlcCache is the persistent cache of the results of each function across runs. One entry is one
+// json file in the dir named by the key of the function, thus the rlc invocations of the crates
+// built in parallel never write the same file.
+#[derive(Debug, Clone)]
+pub struct RlcCache {
+    dir: PathBuf,
+    hits: usize,
+    misses: usize,
+}
+
+impl RlcCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: PathBuf::from(dir.as_ref()),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn add_hit(&mut self) {
+        self.hits += 1;
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    pub fn add_miss(&mut self) {
+        self.misses += 1;
+    }
+
+    // Load the entry of the key, the missing and the broken entries are both None.
+    pub fn load(&self, key: &str) -> Option<CacheEntry> {
+        let content = fs::read_to_string(self.entry_path(key)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    // Store the entry of the key. The entry is written into a temp file and renamed, thus a reader
+    // never sees a partial entry, and the failures only cost the cache hit in the next run.
+    pub fn store(&self, key: &str, entry: &CacheEntry) {
+        let path = self.entry_path(key);
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        let content = serde_json::to_string(entry).expect("failed to serialize cache entry");
+        if fs::write(&tmp, content).is_err() || fs::rename(&tmp, &path).is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+// The key of one function is the stable hash of everything its results depend on: the version of rlc,
+// the knobs of the config that change the analysis, the MIR body (including the spans), the ownership
+// layouts of the adts used by the locals and the summaries (the alloc effects and the models) of the callees.
+pub fn fn_cache_key(rcx: &RlcGlobalCtxt, did: DefId) -> String {
+    let tcx = rcx.tcx();
+    let body = mir_body(tcx, did);
+    let config = rcx.config();
+    let mut hasher = StableHasher::new();
+
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    config.grain().hash(&mut hasher);
+    config.effective_unwind_mode().hash(&mut hasher);
+    config.solver_timeout_of(&tcx.def_path_str(did)).hash(&mut hasher);
+
+    tcx.with_stable_hashing_context(|mut hcx| body.hash_stable(&mut hcx, &mut hasher));
+
+    let mut adts = Vec::default();
+    for decl in body.local_decls.iter() {
+        for arg in decl.ty.walk() {
+            if let GenericArgKind::Type(ty) = arg.unpack() {
+                if let ty::Adt(adt_def, ..) = ty.kind() {
+                    if let Some(owner) = rcx.adt_owner().get(&adt_def.did()) {
+                        adts.push((tcx.def_path_hash(adt_def.did()), owner));
+                    }
+                }
+            }
+        }
+    }
+    adts.sort_by_key(|(hash, _)| *hash);
+    adts.dedup_by_key(|(hash, _)| *hash);
+    adts.hash(&mut hasher);
+
+    for data in body.basic_blocks().iter() {
+        if let TerminatorKind::Call { func, .. } = &data.terminator().kind {
+            if let Some(callee) = callee_def_id(func) {
+                (tcx.def_path_hash(callee), rcx.fn_summaries().get(&callee), rcx.alloc_effect_of(callee), rcx.dealloc_params().get(&callee),
+                 rcx.fn_model_of(callee)).hash(&mut hasher);
+            }
+        }
+    }
+
+    let hash: u128 = hasher.finish();
+    format!("{:032x}", hash)
+}
+
+// The span is stored by the name of its source file and the offsets in the file, since the positions
+// in the source map differ from one compilation to another.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedSpan {
+    file: String,
+    lo: u32,
+    hi: u32,
+}
+
+impl CachedSpan {
+    fn encode(source_map: &SourceMap, span: Span) -> Option<Self> {
+        if span.is_dummy() {
+            return None;
+        }
+        let lo = source_map.lookup_byte_offset(span.lo());
+        let hi = source_map.lookup_byte_offset(span.hi());
+        Some(Self {
+            file: lo.sf.name.prefer_local().to_string(),
+            lo: lo.pos.0,
+            hi: hi.pos.0,
+        })
+    }
+
+    fn decode(source_map: &SourceMap, span: &Option<Self>) -> Option<Span> {
+        let span = match span {
+            Some(span) => span,
+            None => return Some(DUMMY_SP),
+        };
+        let files = source_map.files();
+        let file = files.iter().find(|sf| sf.name.prefer_local().to_string() == span.file)?;
+        Some(Span::with_root_ctxt(file.start_pos + BytePos(span.lo), file.start_pos + BytePos(span.hi)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedReport {
+    fn_span: Option<CachedSpan>,
+    local: Option<usize>,
+    local_span: Option<CachedSpan>,
+    return_span: Option<CachedSpan>,
+    verdict: LeakVerdict,
+    path: LeakPath,
+    trace: Vec<(usize, TraceKind, Option<CachedSpan>)>,
+    manually_managed: bool,
+}
+
+// CacheEntry is the result of one function: the summary computed by the inter procedural visitor
+// and the reports of the intro procedural visitor. The suppressions are not cached, they are applied
+// to the reports after the analysis in each run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    summary: Option<OwnershipSummary>,
+    reports: Vec<CachedReport>,
+}
+
+impl CacheEntry {
+    pub fn new(tcx: TyCtxt, summary: Option<OwnershipSummary>, reports: &[LeakReport]) -> Self {
+        let source_map = tcx.sess.source_map();
+        let reports = reports
+            .iter()
+            .map(|report| CachedReport {
+                fn_span: CachedSpan::encode(source_map, report.fn_span()),
+                local: report.local(),
+                local_span: report.local_span().and_then(|span| CachedSpan::encode(source_map, span)),
+                return_span: CachedSpan::encode(source_map, report.return_span()),
+                verdict: report.verdict(),
+                path: report.path(),
+                trace: report
+                    .trace()
+                    .iter()
+                    .map(|step| (step.bidx(), step.kind(), CachedSpan::encode(source_map, step.span())))
+                    .collect(),
+                manually_managed: report.is_manually_managed(),
+            })
+            .collect();
+        Self { summary, reports }
+    }
+
+    pub fn summary(&self) -> Option<&OwnershipSummary> {
+        self.summary.as_ref()
+    }
+
+    // Rebuild the reports of the function, None if any span cannot be found in current compilation.
+    pub fn reports(&self, tcx: TyCtxt, did: DefId) -> Option<Vec<LeakReport>> {
+        let source_map = tcx.sess.source_map();
+        let mut reports = Vec::with_capacity(self.reports.len());
+        for cached in self.reports.iter() {
+            let local_span = match &cached.local_span {
+                Some(span) => Some(CachedSpan::decode(source_map, &Some(span.clone()))?),
+                None => None,
+            };
+            let mut report = LeakReport::new(
+                did,
+                CachedSpan::decode(source_map, &cached.fn_span)?,
+                cached.local,
+                local_span,
+                CachedSpan::decode(source_map, &cached.return_span)?,
+                cached.verdict,
+                cached.path,
+            );
+            for (bidx, kind, span) in cached.trace.iter() {
+                report.trace_mut().push(TraceStep::new(*bidx, *kind, CachedSpan::decode(source_map, span)?));
+            }
+            report.set_manually_managed(cached.manually_managed);
+            reports.push(report);
+        }
+        Some(reports)
+    }
+}
+
+pub fn show_cache_stats(rcx: &RlcGlobalCtxt) {
+    if let Some(cache) = rcx.cache() {
+        rlc_info!("Cache: {} hit(s), {} miss(es) in {}", cache.hits(), cache.misses(), cache.dir().display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{with_tcx_of, RlcConfig};
+    use crate::components::grain::RlcGrain;
+    use rustc_span::FileName;
+    use rustc_span::source_map::FilePathMapping;
+
+    // Compile the sources in turn as the same crate and compute the cache key of the function `f` in each
+    // compilation, thus the keys of the same source are computed with the same path in different source maps.
+    fn keys_of_f(name: &str, sources: &[(&str, RlcConfig)]) -> Vec<String> {
+        sources
+            .iter()
+            .map(|(source, config)| {
+                with_tcx_of(name, source, |tcx| {
+                    let did = tcx.hir()
+                        .body_owners()
+                        .map(|did| did.to_def_id())
+                        .find(|did| tcx.def_path_str(*did) == "f")
+                        .expect("the crate has a function f");
+                    fn_cache_key(&RlcGlobalCtxt::new(tcx, config.clone()), did)
+                })
+            })
+            .collect()
+    }
+
+    fn config_of(grain: RlcGrain) -> RlcConfig {
+        let mut config = RlcConfig::default();
+        config.set_grain(grain);
+        config
+    }
+
+    #[test]
+    fn fn_cache_key_is_stable_across_compilations() {
+        let source = "pub fn f() -> *mut u8 { Box::into_raw(Box::new(1)) }\n";
+        let keys = keys_of_f("cache_key_stable", &[(source, RlcConfig::default()), (source, RlcConfig::default())]);
+        assert_eq!(keys[0], keys[1]);
+        assert_eq!(keys[0].len(), 32);
+    }
+
+    #[test]
+    fn fn_cache_key_changes_with_body_and_config() {
+        let source = "pub fn f() -> *mut u8 { Box::into_raw(Box::new(1)) }\n";
+        let changed = "pub fn f() -> *mut u8 { Box::into_raw(Box::new(2)) }\n";
+        let keys = keys_of_f("cache_key_changes", &[
+            (source, config_of(RlcGrain::Low)),
+            (changed, config_of(RlcGrain::Low)),
+            (source, config_of(RlcGrain::High)),
+        ]);
+        assert_ne!(keys[0], keys[1]);
+        assert_ne!(keys[0], keys[2]);
+    }
+
+    #[test]
+    fn cached_span_is_decoded_in_shifted_source_map() {
+        rustc_span::create_default_session_globals_then(|| {
+            let source = "fn f() -> Box<u8> { Box::new(1) }\n";
+            let source_map = SourceMap::new(FilePathMapping::empty());
+            let file = source_map.new_source_file(FileName::Custom("lib.rs".to_string()), source.to_string());
+            let span = Span::with_root_ctxt(file.start_pos + BytePos(20), file.start_pos + BytePos(31));
+            let cached = CachedSpan::encode(&source_map, span);
+            let cached: Option<CachedSpan> = serde_json::from_str(&serde_json::to_string(&cached).unwrap()).unwrap();
+
+            // the other compilation loads another file first, thus the same file starts at another position
+            let shifted = SourceMap::new(FilePathMapping::empty());
+            shifted.new_source_file(FileName::Custom("other.rs".to_string()), "fn g() {}\n".to_string());
+            shifted.new_source_file(FileName::Custom("lib.rs".to_string()), source.to_string());
+            let decoded = CachedSpan::decode(&shifted, &cached).unwrap();
+            assert_ne!(decoded, span);
+            assert_eq!(shifted.span_to_snippet(decoded).unwrap(), "Box::new(1)");
+
+            // the span of a file absent from the compilation is not found, while the dummy span is kept
+            assert_eq!(CachedSpan::decode(&SourceMap::new(FilePathMapping::empty()), &cached), None);
+            assert!(CachedSpan::encode(&source_map, DUMMY_SP).is_none());
+            assert_eq!(CachedSpan::decode(&shifted, &None), Some(DUMMY_SP));
+        })
+    }
+}
@@ -1,17 +1,40 @@
 use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use rustc_span::def_id::DefId;
 
 use crate::{RlcConfig, Elapsed};
-use crate::analysis::flow_analysis::MirGraph;
+use crate::analysis::flow_analysis::{FnSummaries, MirGraph};
 use crate::analysis::type_analysis::AdtOwner;
+use crate::analysis::type_analysis::cross_crate::CrossCrateFns;
+use crate::analysis::type_analysis::reachability::{AllocEffect, AllocEffects, DeallocParams};
+use crate::components::cache::RlcCache;
+use crate::components::model::{default_fn_models, FnModel};
+use crate::components::report::{DropCheckReport, InconclusiveFn, LeakReport, ProxyTypeReport, RawPairingReport,
+                                RawPartsReport};
 
 use std::collections::HashMap;
+use std::path::Path;
 
 #[derive(Clone)]
 pub struct RlcGlobalCtxt<'tcx> {
     tcx: TyCtxt<'tcx>,
     config: RlcConfig,
     adt_owner: AdtOwner,
+    // the may-allocate and may-deallocate effects of the functions computed over MIR
+    alloc_effects: AllocEffects,
+    dealloc_params: DeallocParams,
+    // the functions of the dependencies analyzed with the local crate, only at the Ultra grain
+    cross_crate_fns: CrossCrateFns,
     mir_graph: MirGraph,
+    fn_summaries: FnSummaries,
+    leak_reports: Vec<LeakReport>,
+    inconclusive_fns: Vec<InconclusiveFn>,
+    proxy_type_reports: Vec<ProxyTypeReport>,
+    drop_check_reports: Vec<DropCheckReport>,
+    raw_parts_reports: Vec<RawPartsReport>,
+    raw_pairing_reports: Vec<RawPairingReport>,
+    // the result cache across runs, None if the cache is disabled
+    cache: Option<RlcCache>,
     elasped: Elapsed,
 }
 
@@ -21,7 +44,18 @@ impl<'tcx> RlcGlobalCtxt<'tcx> {
             tcx,
             config,
             adt_owner: HashMap::default(),
+            alloc_effects: HashMap::default(),
+            dealloc_params: HashMap::default(),
+            cross_crate_fns: HashMap::default(),
             mir_graph: HashMap::default(),
+            fn_summaries: HashMap::default(),
+            leak_reports: Vec::default(),
+            inconclusive_fns: Vec::default(),
+            proxy_type_reports: Vec::default(),
+            drop_check_reports: Vec::default(),
+            raw_parts_reports: Vec::default(),
+            raw_pairing_reports: Vec::default(),
+            cache: None,
             elasped: (0, 0),
         }
     }
@@ -30,8 +64,19 @@ impl<'tcx> RlcGlobalCtxt<'tcx> {
         self.tcx
     }
 
-    pub fn config(&self) -> RlcConfig {
-        self.config
+    pub fn config(&self) -> &RlcConfig {
+        &self.config
+    }
+
+    // Whether the source file of the span is in the included paths of the project config.
+    pub fn is_span_analyzed(&self, span: Span) -> bool {
+        let file_name = self.tcx.sess.source_map().span_to_filename(span);
+        self.config.is_path_analyzed(Path::new(&file_name.prefer_local().to_string()))
+    }
+
+    // The included paths only apply to the local crate, the items of the dependencies are kept.
+    pub fn is_def_analyzed(&self, did: DefId) -> bool {
+        !did.is_local() || self.is_span_analyzed(self.tcx.def_span(did))
     }
 
     pub fn adt_owner(&self) -> &AdtOwner {
@@ -42,6 +87,57 @@ impl<'tcx> RlcGlobalCtxt<'tcx> {
         &mut self.adt_owner
     }
 
+    pub fn alloc_effects(&self) -> &AllocEffects {
+        &self.alloc_effects
+    }
+
+    pub fn alloc_effects_mut(&mut self) -> &mut AllocEffects {
+        &mut self.alloc_effects
+    }
+
+    // The effect of the function, the functions absent in the reachability analysis may do both.
+    pub fn alloc_effect_of(&self, did: DefId) -> AllocEffect {
+        self.alloc_effects.get(&did).copied().unwrap_or_else(AllocEffect::unknown)
+    }
+
+    pub fn dealloc_params(&self) -> &DeallocParams {
+        &self.dealloc_params
+    }
+
+    pub fn dealloc_params_mut(&mut self) -> &mut DeallocParams {
+        &mut self.dealloc_params
+    }
+
+    pub fn cross_crate_fns(&self) -> &CrossCrateFns {
+        &self.cross_crate_fns
+    }
+
+    pub fn cross_crate_fns_mut(&mut self) -> &mut CrossCrateFns {
+        &mut self.cross_crate_fns
+    }
+
+    // The functions to analyze: the functions of the local crate in the order of the mir keys, then
+    // the functions of the dependencies in the order of their def path hashes.
+    pub fn analyzed_fns(&self) -> Vec<DefId> {
+        let mut fns: Vec<DefId> = self.tcx.mir_keys(()).iter().map(|each_mir| each_mir.to_def_id()).collect();
+        let mut deps: Vec<DefId> = self.cross_crate_fns.keys().copied().collect();
+        deps.sort_by_key(|did| self.tcx.def_path_hash(*did));
+        fns.extend(deps);
+        fns
+    }
+
+    // The model of the function given by the user, or the default model if it is a foreign function.
+    pub fn fn_model_of(&self, did: DefId) -> Option<FnModel> {
+        let def_path = self.tcx.def_path_str(did);
+        if let Some(model) = self.config.fn_model_of(&def_path) {
+            return Some(model.clone());
+        }
+        if !self.tcx.is_foreign_item(did) {
+            return None;
+        }
+        default_fn_models().into_iter().find(|model| model.matches(&def_path))
+    }
+
     pub fn mir_graph(&self) -> &MirGraph {
         &self.mir_graph
     }
@@ -50,6 +146,74 @@ impl<'tcx> RlcGlobalCtxt<'tcx> {
         &mut self.mir_graph
     }
 
+    pub fn fn_summaries(&self) -> &FnSummaries {
+        &self.fn_summaries
+    }
+
+    pub fn fn_summaries_mut(&mut self) -> &mut FnSummaries {
+        &mut self.fn_summaries
+    }
+
+    pub fn leak_reports(&self) -> &Vec<LeakReport> {
+        &self.leak_reports
+    }
+
+    pub fn leak_reports_mut(&mut self) -> &mut Vec<LeakReport> {
+        &mut self.leak_reports
+    }
+
+    pub fn inconclusive_fns(&self) -> &Vec<InconclusiveFn> {
+        &self.inconclusive_fns
+    }
+
+    pub fn inconclusive_fns_mut(&mut self) -> &mut Vec<InconclusiveFn> {
+        &mut self.inconclusive_fns
+    }
+
+    pub fn proxy_type_reports(&self) -> &Vec<ProxyTypeReport> {
+        &self.proxy_type_reports
+    }
+
+    pub fn proxy_type_reports_mut(&mut self) -> &mut Vec<ProxyTypeReport> {
+        &mut self.proxy_type_reports
+    }
+
+    pub fn drop_check_reports(&self) -> &Vec<DropCheckReport> {
+        &self.drop_check_reports
+    }
+
+    pub fn drop_check_reports_mut(&mut self) -> &mut Vec<DropCheckReport> {
+        &mut self.drop_check_reports
+    }
+
+    pub fn raw_parts_reports(&self) -> &Vec<RawPartsReport> {
+        &self.raw_parts_reports
+    }
+
+    pub fn raw_parts_reports_mut(&mut self) -> &mut Vec<RawPartsReport> {
+        &mut self.raw_parts_reports
+    }
+
+    pub fn raw_pairing_reports(&self) -> &Vec<RawPairingReport> {
+        &self.raw_pairing_reports
+    }
+
+    pub fn raw_pairing_reports_mut(&mut self) -> &mut Vec<RawPairingReport> {
+        &mut self.raw_pairing_reports
+    }
+
+    pub fn cache(&self) -> Option<&RlcCache> {
+        self.cache.as_ref()
+    }
+
+    pub fn cache_mut(&mut self) -> Option<&mut RlcCache> {
+        self.cache.as_mut()
+    }
+
+    pub fn set_cache(&mut self, cache: RlcCache) {
+        self.cache = Some(cache);
+    }
+
     pub fn get_time_build(&self) -> i64 {
         self.elasped.0
     }
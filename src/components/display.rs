@@ -18,7 +18,7 @@ type BasicBlocks<'tcx> = IndexVec<BasicBlock, BasicBlockData<'tcx>>;
 // that makes entire rlc verbose.
 
 // MirDisplay is the controller in rlc to determine if the Display trait should be derived.
-#[derive(Debug, Copy, Clone, Hash)]
+#[derive(Debug, Copy, Clone, Hash, Serialize, Deserialize)]
 pub enum MirDisplay {
     // Basic MIR information for Debug
     Verbose,
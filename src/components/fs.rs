@@ -8,7 +8,7 @@ use rustc_demangle::try_demangle;
 
 pub fn rlc_create_dir<P: AsRef<Path>>(path: P, msg: impl AsRef<str>) {
     if fs::read_dir(&path).is_err() {
-        fs::create_dir(path)
+        fs::create_dir_all(path)
             .unwrap_or_else(|e|
                 rlc_error_and_exit(format!("{}: {}", msg.as_ref(), e))
             );
@@ -1,6 +1,11 @@
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug, Copy, Clone, Hash, Ord, PartialOrd, Eq, PartialEq)]
+// RlcGrain is the dial of the precision and the cost of the analysis, each level adds to the lower one:
+//   Low    : intra-procedural only, the layouts are field-insensitive
+//   Medium : field-sensitive layouts and interprocedural ownership summaries
+//   High   : path-sensitive constraints for SwitchInt
+//   Ultra  : the unwind paths and the MIR of other crates
+#[derive(Debug, Copy, Clone, Hash, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub enum RlcGrain {
     Low = 0,
     Medium = 1,
@@ -17,8 +22,63 @@ impl Display for RlcGrain {
                 RlcGrain::Ultra => "Ultra",
                 RlcGrain::High => "High",
                 RlcGrain::Medium => "Medium",
-                RlcGrain::Low => "Low,"
+                RlcGrain::Low => "Low"
             }
         )
     }
-}
\ No newline at end of file
+}
+
+impl RlcGrain {
+    // The fields of an adt are tracked as separate ownership bits, otherwise a local accessed through
+    // its fields is not tracked.
+    pub fn is_field_sensitive(&self) -> bool {
+        *self >= RlcGrain::Medium
+    }
+
+    // The ownership summaries of the callees are computed and applied at the call sites.
+    pub fn has_summaries(&self) -> bool {
+        *self >= RlcGrain::Medium
+    }
+
+    // The successors of SwitchInt are guarded by the switch values, and the merges and the exits are
+    // conditioned on the paths, thus the infeasible combinations of branches are not reported.
+    pub fn is_path_sensitive(&self) -> bool {
+        *self >= RlcGrain::High
+    }
+
+    // The cleanup blocks are visited and the leaks on the unwind paths are reported, as `-UNWIND` does.
+    pub fn has_unwind_paths(&self) -> bool {
+        *self >= RlcGrain::Ultra
+    }
+
+    // The functions of the dependencies reachable from the local crate are analyzed with their MIR
+    // from the metadata, thus the leaks inside them are reported at the local call sites.
+    pub fn is_cross_crate(&self) -> bool {
+        *self >= RlcGrain::Ultra
+    }
+
+    // The effects of the grain that are recorded in the reports.
+    pub fn effects(&self) -> Vec<&'static str> {
+        let mut effects = Vec::default();
+        if self.has_summaries() {
+            effects.push("interprocedural summaries");
+        } else {
+            effects.push("intra-procedural only");
+        }
+        if self.is_field_sensitive() {
+            effects.push("field-sensitive layouts");
+        } else {
+            effects.push("field-insensitive layouts");
+        }
+        if self.is_path_sensitive() {
+            effects.push("path-sensitive branches");
+        }
+        if self.has_unwind_paths() {
+            effects.push("unwind paths");
+        }
+        if self.is_cross_crate() {
+            effects.push("cross-crate summaries");
+        }
+        effects
+    }
+}
@@ -0,0 +1,357 @@
+use rustc_errors::MultiSpan;
+use rustc_span::Span;
+use rustc_span::def_id::DefId;
+use rustc_session::{declare_tool_lint, lint::Lint};
+
+use crate::components::context::RlcGlobalCtxt;
+use crate::components::report::{DropCheckReport, LeakReport, LeakRule, LeakVerdict, ProxyTypeReport, RawPairingReport,
+                                RawPartsMismatch, RawPartsReport};
+use crate::components::suppression::{Suppression, SuppressionKind};
+
+declare_tool_lint! {
+    /// The owning heap item is not freed when the function returns.
+    pub rlc::MEMORY_LEAK,
+    Warn,
+    "owning heap items that are not freed when the function returns"
+}
+
+declare_tool_lint! {
+    /// The owning heap item is not freed when the function panics.
+    pub rlc::MEMORY_LEAK_ON_PANIC,
+    Warn,
+    "owning heap items that are not freed when the function unwinds"
+}
+
+declare_tool_lint! {
+    /// The solver cannot prove that the function is free of leaks.
+    pub rlc::LEAK_CHECK_INCONCLUSIVE,
+    Warn,
+    "functions that cannot be proven free of leaks"
+}
+
+declare_tool_lint! {
+    /// The raw pointer field receives owned allocations but the type has no Drop impl.
+    pub rlc::PROXY_TYPE_LEAK,
+    Warn,
+    "raw pointer fields holding owned allocations in the types without Drop impl"
+}
+
+declare_tool_lint! {
+    /// The Drop impl does not release the owned raw pointer field when it returns.
+    pub rlc::DROP_LEAK,
+    Warn,
+    "owned raw pointer fields that are not released by the Drop impl"
+}
+
+declare_tool_lint! {
+    /// The buffer of a forgotten value is rebuilt with a capacity other than its own.
+    pub rlc::RAW_PARTS_CAPACITY_MISMATCH,
+    Warn,
+    "`from_raw_parts` with a capacity that is not provably the original capacity"
+}
+
+declare_tool_lint! {
+    /// The raw pointers escaped by `into_raw` are never recovered by the paired `from_raw` in the crate.
+    pub rlc::UNPAIRED_RAW_POINTER,
+    Warn,
+    "pointee types escaped by `into_raw` without the paired `from_raw` in the crate"
+}
+
+declare_tool_lint! {
+    /// The value wrapped by `ManuallyDrop::new` is never released by hand.
+    pub rlc::MANUALLY_DROP_LEAK,
+    Warn,
+    "values wrapped by `ManuallyDrop` that are neither released nor escaped when the function returns"
+}
+
+// All lints of rlc, they are registered into the lint store of the compiler, thus the levels
+// can be set by `#![warn(rlc::memory_leak)]` or `-D rlc::memory_leak` like other tool lints.
+pub static RLC_LINTS: &[&Lint] = &[MEMORY_LEAK, MEMORY_LEAK_ON_PANIC, LEAK_CHECK_INCONCLUSIVE, PROXY_TYPE_LEAK,
+                                         DROP_LEAK, RAW_PARTS_CAPACITY_MISMATCH, UNPAIRED_RAW_POINTER,
+                                         MANUALLY_DROP_LEAK];
+
+impl LeakRule {
+    pub fn lint(&self) -> &'static Lint {
+        match self {
+            LeakRule::MemoryLeak => MEMORY_LEAK,
+            LeakRule::PanicPathLeak => MEMORY_LEAK_ON_PANIC,
+            LeakRule::Inconclusive => LEAK_CHECK_INCONCLUSIVE,
+            LeakRule::ProxyType => PROXY_TYPE_LEAK,
+            LeakRule::DropLeak => DROP_LEAK,
+            LeakRule::RawPartsCapacity => RAW_PARTS_CAPACITY_MISMATCH,
+            LeakRule::RawPairing => UNPAIRED_RAW_POINTER,
+            LeakRule::ManuallyDropLeak => MANUALLY_DROP_LEAK,
+        }
+    }
+}
+
+// Emit the leak reports as the diagnostics of the compiler, the level of the lint is looked up at
+// the function being reported, and a denied lint makes the compilation fail.
+// The reports of all checkers allowed by `#[rlc::allow]` are not emitted, and the intentional leaks are
+// downgraded to notes.
+pub fn emit_leak_lints(rcx: &RlcGlobalCtxt) {
+    for report in rcx.leak_reports() {
+        let span = report.primary_span();
+        emit_unless_suppressed(rcx, report.suppression(), report.did(), span, || emit_leak_lint(rcx, report));
+    }
+    for report in rcx.proxy_type_reports() {
+        let span = report.field_span();
+        emit_unless_suppressed(rcx, report.suppression(), report.scope(), span, || emit_proxy_type_lint(rcx, report));
+    }
+    for report in rcx.drop_check_reports() {
+        let span = report.return_spans().first().copied().unwrap_or_else(|| rcx.tcx().def_span(report.scope()));
+        emit_unless_suppressed(rcx, report.suppression(), report.scope(), span, || emit_drop_check_lint(rcx, report));
+    }
+    for report in rcx.raw_parts_reports() {
+        let span = report.span();
+        emit_unless_suppressed(rcx, report.suppression(), report.scope(), span, || emit_raw_parts_lint(rcx, report));
+    }
+    for report in rcx.raw_pairing_reports() {
+        let span = report.escapes()[0].span();
+        emit_unless_suppressed(rcx, report.suppression(), report.scope(), span, || emit_raw_pairing_lint(rcx, report));
+    }
+}
+
+// The intentional leak is noted at the primary span of the report instead of the lint.
+fn emit_unless_suppressed(
+    rcx: &RlcGlobalCtxt,
+    suppression: Option<Suppression>,
+    did: DefId,
+    span: Span,
+    emit: impl FnOnce(),
+) {
+    match suppression.map(|s| s.kind()) {
+        None => emit(),
+        Some(SuppressionKind::Allow) => (),
+        Some(SuppressionKind::IntentionalLeak(reason)) => {
+            let tcx = rcx.tcx();
+            let msg = format!("intentional leak in `{}`: {}", tcx.def_path_str(did), reason);
+            tcx.sess.span_note_without_error(span, &msg);
+        },
+    }
+}
+
+fn emit_leak_lint(rcx: &RlcGlobalCtxt, report: &LeakReport) {
+    let tcx = rcx.tcx();
+    let local_did = match report.local_did() {
+        Some(local_did) => local_did,
+        None => return,
+    };
+    let hir_id = tcx.hir().local_def_id_to_hir_id(local_did);
+    let fn_name = tcx.def_path_str(report.did());
+
+    let mut msg = match (report.verdict(), report.local()) {
+        (LeakVerdict::Unknown, _) => format!("cannot prove that `{}` is free of memory leaks", fn_name),
+        (LeakVerdict::Unsat, Some(_)) if report.rule() == LeakRule::ManuallyDropLeak => {
+            "memory leak: the value wrapped by `ManuallyDrop` is never released".to_string()
+        },
+        (LeakVerdict::Unsat, Some(_)) => format!("memory leak: the owning value is not freed when {}", report.path()),
+        (LeakVerdict::Unsat, None) => format!("memory leak: owning heap items of `{}` are not freed when {}", fn_name, report.path()),
+    };
+    // the leak in a dependency is reported at the local call site, the spans of the dependency follow
+    let lint_span = match report.entry() {
+        Some((_, call_span)) => {
+            msg += &format!(" in dependency `{}`", tcx.crate_name(report.did().krate));
+            call_span
+        },
+        None => report.primary_span(),
+    };
+
+    tcx.struct_span_lint_hir(
+        report.rule().lint(),
+        hir_id,
+        lint_span,
+        |lint| {
+            let mut diag = lint.build(&msg);
+            if report.entry().is_some() {
+                diag.span_label(lint_span, format!("`{}` is reached from here", fn_name));
+            }
+            if report.local_span().is_some() {
+                diag.span_label(report.primary_span(), "the owning value is declared here");
+            }
+            match report.verdict() {
+                LeakVerdict::Unsat => diag.span_label(report.return_span(), format!("leaks when {} here", report.path())),
+                LeakVerdict::Unknown => diag.span_label(report.return_span(), "the check is inconclusive at this exit"),
+            };
+
+            // the trace is attached as a note with one label per step in the order of the MIR
+            let steps: Vec<_> = report
+                .trace()
+                .iter()
+                .filter(|step| !step.span().is_dummy())
+                .collect();
+            if !steps.is_empty() {
+                let mut trace_span = MultiSpan::from_spans(steps.iter().map(|step| step.span()).collect());
+                for (idx, step) in steps.iter().enumerate() {
+                    trace_span.push_span_label(step.span(), format!("{}: {}", idx + 1, step.kind()));
+                }
+                diag.span_note(trace_span, "the ownership flows through these steps");
+            }
+
+            if report.is_panic_only() {
+                diag.note("the leak only happens when the function panics");
+            }
+            if report.rule() == LeakRule::ManuallyDropLeak {
+                diag.help("release it with `ManuallyDrop::drop`, `ManuallyDrop::into_inner` or `ManuallyDrop::take`, or move it into a field or the return value");
+            }
+            diag.note(&format!("analyzed at {}", report.to_grain_message()));
+            diag.emit();
+        },
+    );
+}
+
+// The proxy type is reported at the raw pointer field, the level of the lint is looked up at the adt,
+// thus `#[allow(rlc::proxy_type_leak)]` on the type silences it.
+fn emit_proxy_type_lint(rcx: &RlcGlobalCtxt, report: &ProxyTypeReport) {
+    let tcx = rcx.tcx();
+    let local_did = match report.adt().as_local() {
+        Some(local_did) => local_did,
+        None => return,
+    };
+    let hir_id = tcx.hir().local_def_id_to_hir_id(local_did);
+    let adt_name = tcx.def_path_str(report.adt());
+    let msg = format!(
+        "memory leak: `{}` receives owned allocations but `{}` has no `Drop` impl",
+        tcx.def_path_str(report.field()),
+        adt_name,
+    );
+
+    tcx.struct_span_lint_hir(
+        report.rule().lint(),
+        hir_id,
+        report.field_span(),
+        |lint| {
+            let mut diag = lint.build(&msg);
+            diag.span_label(report.field_span(), "the allocations stored in this field are never released");
+
+            let sites: Vec<_> = report
+                .sites()
+                .iter()
+                .filter(|site| !site.span().is_dummy())
+                .collect();
+            if !sites.is_empty() {
+                let mut site_span = MultiSpan::from_spans(sites.iter().map(|site| site.span()).collect());
+                for site in sites.iter() {
+                    site_span.push_span_label(site.span(), format!("stores the allocation from {}", site.source()));
+                }
+                diag.span_note(site_span, "the owned allocations are stored here");
+            }
+
+            if report.is_owner() {
+                diag.note(&format!("`{}` owns its pointee in the ownership layout, but the drop glue does not release raw pointers", adt_name));
+            }
+            diag.help(&format!("implement `Drop` for `{}` to recover the ownership, e.g., with `Box::from_raw`", adt_name));
+            diag.emit();
+        },
+    );
+}
+
+// The unreleased field is reported at the first return of `Drop::drop`, the level of the lint is looked
+// up at the drop function.
+fn emit_drop_check_lint(rcx: &RlcGlobalCtxt, report: &DropCheckReport) {
+    let tcx = rcx.tcx();
+    let local_did = match report.drop_fn().as_local() {
+        Some(local_did) => local_did,
+        None => return,
+    };
+    let lint_span = match report.return_spans().first() {
+        Some(span) => *span,
+        None => return,
+    };
+    let hir_id = tcx.hir().local_def_id_to_hir_id(local_did);
+    let mut msg = format!("memory leak: the `Drop` impl of `{}` does not release `{}`", tcx.def_path_str(report.adt()), report.field_path());
+    if let Some(variant) = report.variant() {
+        msg += &format!(" of variant `{}`", tcx.def_path_str(variant));
+    }
+
+    tcx.struct_span_lint_hir(
+        report.rule().lint(),
+        hir_id,
+        lint_span,
+        |lint| {
+            let mut diag = lint.build(&msg);
+            for span in report.return_spans() {
+                diag.span_label(*span, "returns without releasing the field here");
+            }
+            diag.span_note(tcx.def_span(report.field()), "the field holds an owned allocation");
+            diag.emit();
+        },
+    );
+}
+
+fn emit_raw_parts_lint(rcx: &RlcGlobalCtxt, report: &RawPartsReport) {
+    let tcx = rcx.tcx();
+    let local_did = match report.did().as_local() {
+        Some(local_did) => local_did,
+        None => return,
+    };
+    let hir_id = tcx.hir().local_def_id_to_hir_id(local_did);
+    let msg = format!("`{}` takes a capacity that is not the capacity of the forgotten value", report.callee());
+
+    tcx.struct_span_lint_hir(
+        report.rule().lint(),
+        hir_id,
+        report.span(),
+        |lint| {
+            let mut diag = lint.build(&msg);
+            diag.span_label(report.span(), format!("the capacity is {}", report.mismatch()));
+            diag.span_note(report.ptr_span(), "the pointer is taken from the value here");
+            if let RawPartsMismatch::Length(span) = report.mismatch() {
+                diag.span_note(span, "the length passed as the capacity is taken here");
+            }
+            diag.help("pass the original `capacity()`, or call `shrink_to_fit` before taking the length");
+            diag.emit();
+        },
+    );
+}
+
+fn emit_raw_pairing_lint(rcx: &RlcGlobalCtxt, report: &RawPairingReport) {
+    let tcx = rcx.tcx();
+    let escape = &report.escapes()[0];
+    let local_did = match escape.did().as_local() {
+        Some(local_did) => local_did,
+        None => return,
+    };
+    let hir_id = tcx.hir().local_def_id_to_hir_id(local_did);
+    let msg = if report.is_mismatched() {
+        format!("`{}` escaped by `{}` is recovered by another constructor", report.ty(), report.escape())
+    } else {
+        format!("memory leak: `{}` escaped by `{}` is never recovered in the crate", report.ty(), report.escape())
+    };
+
+    tcx.struct_span_lint_hir(
+        report.rule().lint(),
+        hir_id,
+        escape.span(),
+        |lint| {
+            let mut diag = lint.build(&msg);
+            if report.is_mismatched() {
+                diag.span_label(escape.span(), format!("escaped by `{}`, thus paired with `{}`", escape.callee(), report.expected()));
+            } else {
+                diag.span_label(escape.span(), format!("no function of the crate calls `{}` for this type", report.expected()));
+            }
+
+            let others: Vec<_> = report.escapes()[1..].iter().filter(|site| !site.span().is_dummy()).collect();
+            if !others.is_empty() {
+                diag.span_note(MultiSpan::from_spans(others.iter().map(|site| site.span()).collect()), "the type is escaped here as well");
+            }
+            let recoveries: Vec<_> = report.recoveries().iter().filter(|site| !site.span().is_dummy()).collect();
+            if !recoveries.is_empty() {
+                let mut recovery_span = MultiSpan::from_spans(recoveries.iter().map(|site| site.span()).collect());
+                for site in recoveries.iter() {
+                    recovery_span.push_span_label(site.span(), format!("recovered by `{}`", site.callee()));
+                }
+                diag.span_note(recovery_span, "the pointers of the type are recovered by other constructors here");
+            }
+            let generics: Vec<_> = report.generic_recoveries().iter().filter(|site| !site.span().is_dummy()).collect();
+            if !generics.is_empty() {
+                diag.span_note(
+                    MultiSpan::from_spans(generics.iter().map(|site| site.span()).collect()),
+                    "the generic recoveries here may take this type, but no instantiation with it is found in the crate",
+                );
+            }
+            diag.help(&format!("recover the pointers with `{}` to release them", report.expected()));
+            diag.emit();
+        },
+    );
+}
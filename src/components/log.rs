@@ -4,7 +4,7 @@ use chrono::Local;
 use fern::{self, Dispatch};
 use log::LevelFilter;
 
-#[derive(Debug, Copy, Clone, Hash)]
+#[derive(Debug, Copy, Clone, Hash, Serialize, Deserialize)]
 pub enum Verbosity {
     Info,
     Debug,
@@ -71,6 +71,10 @@ macro_rules! rlc_error {
 }
 
 pub fn rlc_error_and_exit(msg: impl AsRef<str>) -> ! {
+    // the errors in the magic args of rlc are raised before the log system is set up
+    if log::max_level() == LevelFilter::Off {
+        eprintln!("Fatal error in RLC: {}", msg.as_ref());
+    }
     rlc_error!("Fatal error in RLC: {}", msg.as_ref());
     std::process::exit(1)
 }
\ No newline at end of file
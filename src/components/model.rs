@@ -0,0 +1,139 @@
+use crate::components::log::rlc_error_and_exit;
+
+use std::fs;
+use std::path::Path;
+
+// The ownership effect of a function on the raw pointer passed in one param, the params that are not
+// raw pointers are not affected by the model.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParamModel {
+    // the function takes the ownership carried by the pointer, e.g., `free(p)`
+    Take,
+    // the function only uses the pointee, the ownership stays with the caller
+    Borrow,
+}
+
+// FnModel is the ownership summary of a function declared by the user, it is used for the functions
+// that the analysis cannot see through, e.g., the `extern "C"` functions of a C library:
+//   [[function]]
+//   path = "rocksdb_options_destroy"    : the def path, or the tail of the def path after `::`
+//   params = ["take"]                   : take or borrow, one for each param in order
+//   [[function]]
+//   path = "rocksdb_options_create"
+//   owned-return = true                 : the returned pointer is an owned allocation
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FnModel {
+    path: String,
+    #[serde(default)]
+    params: Vec<ParamModel>,
+    #[serde(default)]
+    owned_return: bool,
+}
+
+impl FnModel {
+    pub fn new(path: &str, params: Vec<ParamModel>, owned_return: bool) -> Self {
+        Self {
+            path: path.to_string(),
+            params,
+            owned_return,
+        }
+    }
+
+    pub fn path(&self) -> &String {
+        &self.path
+    }
+
+    // The effect on the param (0-based), the params out of the model are borrowed.
+    pub fn param(&self, idx: usize) -> ParamModel {
+        self.params.get(idx).copied().unwrap_or(ParamModel::Borrow)
+    }
+
+    pub fn is_taken(&self, idx: usize) -> bool {
+        self.param(idx) == ParamModel::Take
+    }
+
+    pub fn is_owned_return(&self) -> bool {
+        self.owned_return
+    }
+
+    // The model matches the def path itself or its tail, e.g., `free` matches `libc::free`.
+    pub fn matches(&self, def_path: &str) -> bool {
+        def_path == self.path || def_path.ends_with(&format!("::{}", self.path))
+    }
+
+    // Load the models in the file, the json files are parsed as json and the others as toml.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Vec<FnModel> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct FnModelFile {
+            #[serde(default)]
+            function: Vec<FnModel>,
+        }
+
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .unwrap_or_else(|e| rlc_error_and_exit(format!("Failed to read the models in {}: {}", path.display(), e)));
+        let file: FnModelFile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content)
+                .unwrap_or_else(|e| rlc_error_and_exit(format!("Failed to parse the models in {}: {}", path.display(), e))),
+            _ => toml::from_str(&content)
+                .unwrap_or_else(|e| rlc_error_and_exit(format!("Failed to parse the models in {}: {}", path.display(), e))),
+        };
+        file.function
+    }
+}
+
+// The models shipped with rlc for the allocator of libc, they only apply to the foreign functions,
+// thus the Rust functions named alike are not affected.
+pub fn default_fn_models() -> Vec<FnModel> {
+    vec![
+        FnModel::new("malloc", Vec::default(), true),
+        FnModel::new("calloc", Vec::default(), true),
+        FnModel::new("realloc", vec![ParamModel::Take], true),
+        FnModel::new("free", vec![ParamModel::Take], false),
+    ]
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    #[test]
+    fn matches_path_or_tail() {
+        let model = FnModel::new("ffi::free", vec![ParamModel::Take], false);
+        assert!(model.matches("ffi::free"));
+        assert!(model.matches("sys::ffi::free"));
+        assert!(!model.matches("sys::myffi::free"));
+        assert!(!model.matches("ffi::free::inner"));
+        assert!(!model.matches("free"));
+    }
+
+    #[test]
+    fn load_toml_and_json() {
+        let dir = env::temp_dir().join(format!("rlc-model-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let toml_path = dir.join("ffi.toml");
+        fs::write(&toml_path, "[[function]]\npath = \"db_destroy\"\nparams = [\"take\", \"borrow\"]\n\n\
+                               [[function]]\npath = \"db_create\"\nowned-return = true\n").unwrap();
+        let models = FnModel::load_file(&toml_path);
+        assert_eq!(models, vec![
+            FnModel::new("db_destroy", vec![ParamModel::Take, ParamModel::Borrow], false),
+            FnModel::new("db_create", Vec::default(), true),
+        ]);
+        assert!(models[0].is_taken(0));
+        // the params out of the model are borrowed
+        assert!(!models[0].is_taken(2));
+
+        let json_path = dir.join("ffi.json");
+        fs::write(&json_path, r#"{ "function": [ { "path": "db_free", "params": ["take"] } ] }"#).unwrap();
+        assert_eq!(FnModel::load_file(&json_path), vec![FnModel::new("db_free", vec![ParamModel::Take], false)]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
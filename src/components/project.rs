@@ -0,0 +1,191 @@
+use crate::RlcConfig;
+use crate::components::grain::RlcGrain;
+use crate::components::log::rlc_error_and_exit;
+use crate::components::model::FnModel;
+use crate::components::suppression::SuppressionConfig;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The name of the project config file in the workspace root.
+pub static RLC_PROJECT_CONFIG_FILE: &str = "rlc.toml";
+
+// RlcProjectConfig is the config of the project being checked, it is read by cargo-rlc from the
+// `rlc.toml` in the workspace root and the `[package.metadata.rlc]` table in the manifest.
+// The keys in the manifest override the ones in rlc.toml, and the command line overrides both.
+//   format = "sarif"                    : the output format, text or sarif
+//   output = "rlc.sarif"                : the output file of the sarif format
+//   solver-timeout = 1000               : the timeout of the solver for each function in ms
+//   analysis-budget = 600000            : the time budget of the whole analysis in ms
+//   include = ["src/ffi"]               : only the functions in these paths are analyzed
+//   exclude = ["src/ffi/generated"]     : the functions in these paths are not analyzed
+//   additional = ["my-sys"]             : the extra crates to analyze
+//   grain = "high"                      : the grain level, low, medium, high or ultra
+//   models = ["ffi.toml"]               : the files of the function models, see `FnModel`
+//   [function-timeouts]
+//   "parser::parse" = 5000              : the timeout of the functions under the def path in ms
+//   [[suppress]]
+//   path = "registry"
+//   reason = "the registry lives until exit"
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RlcProjectConfig {
+    format: Option<String>,
+    output: Option<String>,
+    solver_timeout: Option<u64>,
+    analysis_budget: Option<u64>,
+    function_timeouts: Option<BTreeMap<String, u64>>,
+    include: Option<Vec<PathBuf>>,
+    exclude: Option<Vec<PathBuf>>,
+    additional: Option<Vec<String>>,
+    grain: Option<String>,
+    models: Option<Vec<PathBuf>>,
+    suppress: Option<Vec<SuppressionConfig>>,
+}
+
+impl RlcProjectConfig {
+    // Load the project config from the workspace root and the metadata of the package.
+    pub fn load<P: AsRef<Path>>(workspace_root: P, metadata: &serde_json::Value) -> Self {
+        let mut config = Self::default();
+
+        let path = workspace_root.as_ref().join(RLC_PROJECT_CONFIG_FILE);
+        if path.is_file() {
+            let content = fs::read_to_string(&path)
+                .unwrap_or_else(|e| rlc_error_and_exit(format!("Failed to read {}: {}", path.display(), e)));
+            let file_config: Self = toml::from_str(&content)
+                .unwrap_or_else(|e| rlc_error_and_exit(format!("Failed to parse {}: {}", path.display(), e)));
+            config.merge(file_config);
+        }
+
+        if let Some(rlc_metadata) = metadata.get("rlc") {
+            let metadata_config: Self = serde_json::from_value(rlc_metadata.clone())
+                .unwrap_or_else(|e| rlc_error_and_exit(format!("Failed to parse [package.metadata.rlc]: {}", e)));
+            config.merge(metadata_config);
+        }
+
+        // the model files are relative to the workspace root
+        if let Some(models) = config.models.as_mut() {
+            for path in models.iter_mut() {
+                *path = workspace_root.as_ref().join(&path);
+            }
+        }
+
+        config
+    }
+
+    // Merge the other config into self, the keys set in the other config take precedence.
+    pub fn merge(&mut self, other: Self) {
+        if other.format.is_some() { self.format = other.format; }
+        if other.output.is_some() { self.output = other.output; }
+        if other.solver_timeout.is_some() { self.solver_timeout = other.solver_timeout; }
+        if other.analysis_budget.is_some() { self.analysis_budget = other.analysis_budget; }
+        if other.function_timeouts.is_some() { self.function_timeouts = other.function_timeouts; }
+        if other.include.is_some() { self.include = other.include; }
+        if other.exclude.is_some() { self.exclude = other.exclude; }
+        if other.additional.is_some() { self.additional = other.additional; }
+        if other.grain.is_some() { self.grain = other.grain; }
+        if other.models.is_some() { self.models = other.models; }
+        if other.suppress.is_some() { self.suppress = other.suppress; }
+    }
+
+    pub fn format(&self) -> Option<&String> {
+        self.format.as_ref()
+    }
+
+    pub fn output(&self) -> Option<&String> {
+        self.output.as_ref()
+    }
+
+    pub fn additional(&self) -> Option<&Vec<String>> {
+        self.additional.as_ref()
+    }
+
+    // Build the config of rlc, the knobs absent in the project config keep the default values.
+    pub fn to_rlc_config(&self) -> RlcConfig {
+        let mut config = RlcConfig::default();
+        if let Some(grain) = &self.grain {
+            let grain = match grain.to_lowercase().as_str() {
+                "low" => RlcGrain::Low,
+                "medium" => RlcGrain::Medium,
+                "high" => RlcGrain::High,
+                "ultra" => RlcGrain::Ultra,
+                _ => rlc_error_and_exit(format!("Unknown grain level: {}, expected low, medium, high or ultra", grain)),
+            };
+            config.set_grain(grain);
+        }
+        if let Some(solver_timeout) = self.solver_timeout {
+            config.set_solver_timeout(solver_timeout);
+        }
+        if let Some(analysis_budget) = self.analysis_budget {
+            config.set_analysis_budget(analysis_budget);
+        }
+        if let Some(function_timeouts) = &self.function_timeouts {
+            config.fn_timeouts_mut().extend(function_timeouts.iter().map(|(k, v)| (k.clone(), *v)));
+        }
+        if let Some(include) = &self.include {
+            config.include_paths_mut().extend(include.iter().cloned());
+        }
+        if let Some(exclude) = &self.exclude {
+            config.exclude_paths_mut().extend(exclude.iter().cloned());
+        }
+        if let Some(suppress) = &self.suppress {
+            config.suppressions_mut().extend(suppress.iter().cloned());
+        }
+        if let Some(models) = &self.models {
+            for path in models {
+                config.fn_models_mut().extend(FnModel::load_file(path));
+            }
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the same steps as `load`: rlc.toml first, then the metadata of the manifest over it
+    fn load_from(file: &str, metadata: serde_json::Value) -> RlcProjectConfig {
+        let mut config = RlcProjectConfig::default();
+        config.merge(toml::from_str(file).unwrap());
+        config.merge(serde_json::from_value(metadata).unwrap());
+        config
+    }
+
+    #[test]
+    fn manifest_overrides_file() {
+        let file = "format = \"text\"\nsolver-timeout = 1000\ngrain = \"high\"\ninclude = [\"src\"]\n\n\
+                    [function-timeouts]\n\"parser\" = 2000\n";
+        let metadata = serde_json::json!({
+            "format": "sarif",
+            "solver-timeout": 5000,
+            "function-timeouts": { "lexer": 3000 },
+        });
+        let config = load_from(file, metadata);
+
+        assert_eq!(config.format().map(|format| format.as_str()), Some("sarif"));
+        let rlc_config = config.to_rlc_config();
+        assert_eq!(rlc_config.solver_timeout(), 5000);
+        // the keys absent in the manifest are kept from the file
+        assert_eq!(rlc_config.grain(), RlcGrain::High);
+        assert_eq!(rlc_config.include_paths(), &vec![PathBuf::from("src")]);
+        // a table in the manifest replaces the one in the file as a whole
+        assert_eq!(rlc_config.solver_timeout_of("parser::parse"), 5000);
+        assert_eq!(rlc_config.solver_timeout_of("lexer::next"), 3000);
+    }
+
+    #[test]
+    fn absent_keys_keep_defaults() {
+        let config = load_from("", serde_json::json!({}));
+        let rlc_config = config.to_rlc_config();
+        let default = RlcConfig::default();
+
+        assert!(config.format().is_none());
+        assert_eq!(rlc_config.solver_timeout(), default.solver_timeout());
+        assert_eq!(rlc_config.grain(), default.grain());
+        assert!(rlc_config.analysis_budget().is_none());
+        assert!(rlc_config.include_paths().is_empty());
+        assert!(rlc_config.suppressions().is_empty());
+    }
+}
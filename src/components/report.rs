@@ -0,0 +1,987 @@
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use rustc_span::def_id::{DefId, LocalDefId};
+
+use crate::rlc_info;
+use crate::components::context::RlcGlobalCtxt;
+use crate::components::grain::RlcGrain;
+use crate::components::suppression::{Suppression, SuppressionKind};
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+// The verdict given by the solver for the ownership constraints of one function.
+// Only the verdicts that indicate a (possible) leak are recorded as reports.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LeakVerdict {
+    // The constraints are unsatisfiable: some owning bit cannot be freed before return.
+    Unsat,
+    // The solver gave up (e.g., timeout) and the function cannot be proven leak-free.
+    Unknown,
+}
+
+impl Display for LeakVerdict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                LeakVerdict::Unsat => "Unsat",
+                LeakVerdict::Unknown => "Unknown",
+            }
+        )
+    }
+}
+
+// The rule of the leak pattern that one report matches, each rule has a stable id for the tools
+// consuming the reports (e.g., the SARIF output).
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum LeakRule {
+    // the owning bits are not freed when the function returns
+    MemoryLeak,
+    // the owning bits are not freed only when the function unwinds
+    PanicPathLeak,
+    // the solver cannot prove the function leak-free
+    Inconclusive,
+    // the raw pointer field of an adt receives owned allocations but the adt has no Drop impl
+    ProxyType,
+    // the Drop impl does not release an owned raw pointer field of self on some path
+    DropLeak,
+    // the buffer of a forgotten Vec or String is rebuilt with a capacity other than the original one
+    RawPartsCapacity,
+    // the pointee type is escaped by into_raw but never recovered by the paired from_raw in the crate
+    RawPairing,
+    // the owning bits wrapped by ManuallyDrop::new are not released by hand when the function returns
+    ManuallyDropLeak,
+}
+
+impl LeakRule {
+    pub fn all() -> Vec<LeakRule> {
+        vec![LeakRule::MemoryLeak, LeakRule::PanicPathLeak, LeakRule::Inconclusive, LeakRule::ProxyType,
+             LeakRule::DropLeak, LeakRule::RawPartsCapacity, LeakRule::RawPairing,
+             LeakRule::ManuallyDropLeak]
+    }
+
+    // The index of the rule in `all`, e.g., the ruleIndex of the SARIF results.
+    pub fn index(&self) -> usize {
+        LeakRule::all().iter().position(|rule| rule == self).unwrap()
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            LeakRule::MemoryLeak => "RLC0001",
+            LeakRule::PanicPathLeak => "RLC0002",
+            LeakRule::Inconclusive => "RLC0003",
+            LeakRule::ProxyType => "RLC0004",
+            LeakRule::DropLeak => "RLC0005",
+            LeakRule::RawPartsCapacity => "RLC0006",
+            LeakRule::RawPairing => "RLC0007",
+            LeakRule::ManuallyDropLeak => "RLC0008",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LeakRule::MemoryLeak => "memory-leak",
+            LeakRule::PanicPathLeak => "memory-leak-on-panic",
+            LeakRule::Inconclusive => "leak-check-inconclusive",
+            LeakRule::ProxyType => "proxy-type-leak",
+            LeakRule::DropLeak => "drop-leak",
+            LeakRule::RawPartsCapacity => "raw-parts-capacity-mismatch",
+            LeakRule::RawPairing => "unpaired-raw-pointer",
+            LeakRule::ManuallyDropLeak => "manually-drop-leak",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            LeakRule::MemoryLeak => "The owning heap item is not freed when the function returns.",
+            LeakRule::PanicPathLeak => "The owning heap item is not freed when the function panics.",
+            LeakRule::Inconclusive => "The solver cannot prove that the function is free of leaks.",
+            LeakRule::ProxyType => "The raw pointer field receives owned allocations but the type has no Drop impl.",
+            LeakRule::DropLeak => "The Drop impl does not release the owned raw pointer field when it returns.",
+            LeakRule::RawPartsCapacity => "The buffer of a forgotten value is rebuilt with a capacity other than its own.",
+            LeakRule::RawPairing => "The raw pointers of the type are never recovered by the paired constructor in the crate.",
+            LeakRule::ManuallyDropLeak => "The value wrapped by ManuallyDrop is never released by hand when the function returns.",
+        }
+    }
+}
+
+// The exit of the function where the owning bits are found not freed.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LeakPath {
+    // the normal return of the function
+    Return,
+    // the resume of the unwinding, the leak only happens if the function panics
+    Unwind,
+}
+
+impl Display for LeakPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                LeakPath::Return => "returning",
+                LeakPath::Unwind => "unwinding",
+            }
+        )
+    }
+}
+
+// The kind of one step in the trace of a leak, each step is an ownership constraint of the function.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum TraceKind {
+    // the argument holds the ownership when the function is called
+    ArgInit,
+    // the states of the predecessors are merged at the entry of the block
+    Merge,
+    // the block is entered through a guarded edge, e.g., one arm of a match
+    Branch,
+    // the lvalue is initialized without ownership
+    Init,
+    // the old value of the lvalue is overwritten, it must not hold the ownership
+    Overwrite,
+    // the ownership is transferred by copy
+    Copy,
+    // the ownership is transferred by move
+    Move,
+    // the value is passed to a call
+    ParamPass,
+    // the return value of a call is allocated with the ownership
+    CallReturn,
+    // the ownership is moved into a raw pointer
+    IntoRaw,
+    // the ownership is recovered from a raw pointer
+    Recover,
+    // the ownership is released by drop
+    Drop,
+    // the ownership is moved into ManuallyDrop and must be released by hand
+    Wrap,
+    // the ownership is moved out of ManuallyDrop by into_inner or take
+    Unwrap,
+    // the function exits and all ownership must be released
+    Exit(LeakPath),
+}
+
+impl Display for TraceKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceKind::ArgInit => write!(f, "passed in as argument"),
+            TraceKind::Merge => write!(f, "merged from branches"),
+            TraceKind::Branch => write!(f, "branch taken"),
+            TraceKind::Init => write!(f, "initialized"),
+            TraceKind::Overwrite => write!(f, "overwritten"),
+            TraceKind::Copy => write!(f, "copied"),
+            TraceKind::Move => write!(f, "moved"),
+            TraceKind::ParamPass => write!(f, "passed to call"),
+            TraceKind::CallReturn => write!(f, "allocated"),
+            TraceKind::IntoRaw => write!(f, "moved into raw pointer"),
+            TraceKind::Recover => write!(f, "recovered from raw pointer"),
+            TraceKind::Drop => write!(f, "dropped"),
+            TraceKind::Wrap => write!(f, "wrapped into ManuallyDrop"),
+            TraceKind::Unwrap => write!(f, "taken out of ManuallyDrop"),
+            TraceKind::Exit(LeakPath::Return) => write!(f, "function returns"),
+            TraceKind::Exit(LeakPath::Unwind) => write!(f, "function unwinds"),
+        }
+    }
+}
+
+// One step in the trace of a leak: the constraint of this step is in the unsat core.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub struct TraceStep {
+    bidx: usize,
+    kind: TraceKind,
+    span: Span,
+}
+
+impl TraceStep {
+    pub fn new(bidx: usize, kind: TraceKind, span: Span) -> Self {
+        Self { bidx, kind, span }
+    }
+
+    pub fn bidx(&self) -> usize {
+        self.bidx
+    }
+
+    pub fn kind(&self) -> TraceKind {
+        self.kind
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+// LeakReport is the result of the flow analysis for one leaking local in one function.
+// If the solver cannot attribute the conflict to a specific local, the local is None
+// and the report only points to the function and its return block.
+#[derive(Debug, Clone)]
+pub struct LeakReport {
+    did: DefId,
+    fn_span: Span,
+    local: Option<usize>,
+    local_span: Option<Span>,
+    return_span: Span,
+    verdict: LeakVerdict,
+    path: LeakPath,
+    trace: Vec<TraceStep>,
+    suppression: Option<Suppression>,
+    grain: RlcGrain,
+    // the local function and the call site that reach the function of a dependency, None if the
+    // function is in the local crate
+    entry: Option<(DefId, Span)>,
+    // the leaking local holds a value wrapped by ManuallyDrop::new
+    manually_managed: bool,
+}
+
+impl LeakReport {
+    pub fn new(
+        did: DefId,
+        fn_span: Span,
+        local: Option<usize>,
+        local_span: Option<Span>,
+        return_span: Span,
+        verdict: LeakVerdict,
+        path: LeakPath,
+    ) -> Self {
+        Self {
+            did,
+            fn_span,
+            local,
+            local_span,
+            return_span,
+            verdict,
+            path,
+            trace: Vec::default(),
+            suppression: None,
+            grain: RlcGrain::Low,
+            entry: None,
+            manually_managed: false,
+        }
+    }
+
+    pub fn did(&self) -> DefId {
+        self.did
+    }
+
+    pub fn fn_span(&self) -> Span {
+        self.fn_span
+    }
+
+    pub fn local(&self) -> Option<usize> {
+        self.local
+    }
+
+    pub fn local_span(&self) -> Option<Span> {
+        self.local_span
+    }
+
+    pub fn return_span(&self) -> Span {
+        self.return_span
+    }
+
+    pub fn verdict(&self) -> LeakVerdict {
+        self.verdict
+    }
+
+    pub fn path(&self) -> LeakPath {
+        self.path
+    }
+
+    pub fn trace(&self) -> &Vec<TraceStep> {
+        &self.trace
+    }
+
+    pub fn trace_mut(&mut self) -> &mut Vec<TraceStep> {
+        &mut self.trace
+    }
+
+    pub fn suppression(&self) -> Option<Suppression> {
+        self.suppression
+    }
+
+    pub fn set_suppression(&mut self, suppression: Suppression) {
+        self.suppression = Some(suppression);
+    }
+
+    pub fn grain(&self) -> RlcGrain {
+        self.grain
+    }
+
+    pub fn set_grain(&mut self, grain: RlcGrain) {
+        self.grain = grain;
+    }
+
+    pub fn entry(&self) -> Option<(DefId, Span)> {
+        self.entry
+    }
+
+    pub fn set_entry(&mut self, entry: (DefId, Span)) {
+        self.entry = Some(entry);
+    }
+
+    pub fn is_manually_managed(&self) -> bool {
+        self.manually_managed
+    }
+
+    pub fn set_manually_managed(&mut self, manually_managed: bool) {
+        self.manually_managed = manually_managed;
+    }
+
+    // The local function that the report is attributed to, i.e., the function itself or the entry
+    // of the dependency.
+    pub fn local_did(&self) -> Option<LocalDefId> {
+        match self.entry {
+            Some((did, _)) => did.as_local(),
+            None => self.did.as_local(),
+        }
+    }
+
+    // e.g., grain Medium (interprocedural summaries, field-sensitive layouts)
+    pub fn to_grain_message(&self) -> String {
+        format!("grain {} ({})", self.grain, self.grain.effects().join(", "))
+    }
+
+    pub fn rule(&self) -> LeakRule {
+        match (self.verdict, self.path) {
+            (LeakVerdict::Unknown, _) => LeakRule::Inconclusive,
+            (_, LeakPath::Unwind) => LeakRule::PanicPathLeak,
+            (_, LeakPath::Return) if self.manually_managed => LeakRule::ManuallyDropLeak,
+            (_, LeakPath::Return) => LeakRule::MemoryLeak,
+        }
+    }
+
+    // The primary location of the report: the leaking local if it is known, or the exit of the function.
+    pub fn primary_span(&self) -> Span {
+        match self.local_span {
+            Some(span) => span,
+            None => self.return_span,
+        }
+    }
+
+    pub fn is_panic_only(&self) -> bool {
+        self.path == LeakPath::Unwind
+    }
+
+    pub fn to_message(&self, tcx: TyCtxt) -> String {
+        let source_map = tcx.sess.source_map();
+        let mut s = format!("[{}] {}", self.verdict, tcx.def_path_str(self.did));
+        match (self.local, self.local_span) {
+            (Some(local), Some(span)) if self.manually_managed => {
+                s += &format!(
+                    ": ManuallyDrop _{} declared at {} is never released",
+                    local,
+                    source_map.span_to_diagnostic_string(span),
+                );
+            },
+            (Some(local), Some(span)) => {
+                s += &format!(
+                    ": _{} declared at {} is not freed",
+                    local,
+                    source_map.span_to_diagnostic_string(span),
+                );
+            },
+            _ => {
+                s += ": owning bits are not freed";
+            },
+        }
+        s += &format!(" when {} at {}", self.path, source_map.span_to_diagnostic_string(self.return_span));
+        if self.is_panic_only() {
+            s += " (panic path only)";
+        }
+        if let Some((did, span)) = self.entry {
+            s += &format!(
+                " in dependency `{}`, reached from {} at {}",
+                tcx.crate_name(self.did.krate),
+                tcx.def_path_str(did),
+                source_map.span_to_diagnostic_string(span),
+            );
+        }
+        s
+    }
+
+    // The trace is printed in the order of the MIR, e.g.,
+    // allocated at a.rs:3:13 -> moved into raw pointer at a.rs:4:13 -> function returns at a.rs:6:2
+    pub fn to_trace_message(&self, tcx: TyCtxt) -> Option<String> {
+        if self.trace.is_empty() {
+            return None;
+        }
+        let source_map = tcx.sess.source_map();
+        let steps: Vec<String> = self.trace
+            .iter()
+            .map(|step| format!("{} at {}", step.kind(), source_map.span_to_diagnostic_string(step.span())))
+            .collect();
+        Some(steps.join(" -> "))
+    }
+}
+
+// The reason why a function cannot be proven free of leaks.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum InconclusiveReason {
+    // the solver returned unknown within the timeout in milliseconds
+    Timeout(u64),
+    // the global time budget of the analysis was exhausted before the function is visited
+    Budget,
+    // the solver panicked on the constraints of the function
+    SolverError,
+}
+
+impl Display for InconclusiveReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InconclusiveReason::Timeout(ms) => write!(f, "solver timeout ({} ms)", ms),
+            InconclusiveReason::Budget => write!(f, "analysis budget exhausted"),
+            InconclusiveReason::SolverError => write!(f, "solver error"),
+        }
+    }
+}
+
+// InconclusiveFn records the function whose constraints are not solved, thus the absence of its
+// leak reports does not mean that it is free of leaks.
+#[derive(Debug, Clone)]
+pub struct InconclusiveFn {
+    did: DefId,
+    reason: InconclusiveReason,
+    // the count of the tracked constraints when the solver gave up
+    constraints: usize,
+    // the widths of the bit-vectors of the locals checked at the exit
+    bv_widths: Vec<usize>,
+}
+
+impl InconclusiveFn {
+    pub fn new(did: DefId, reason: InconclusiveReason, constraints: usize, bv_widths: Vec<usize>) -> Self {
+        Self { did, reason, constraints, bv_widths }
+    }
+
+    pub fn did(&self) -> DefId {
+        self.did
+    }
+
+    pub fn reason(&self) -> InconclusiveReason {
+        self.reason
+    }
+
+    pub fn constraints(&self) -> usize {
+        self.constraints
+    }
+
+    pub fn bv_widths(&self) -> &Vec<usize> {
+        &self.bv_widths
+    }
+
+    // e.g., foo::bar: solver timeout (1000 ms), 342 constraint(s), bit-vectors: 1-bit x20, 3-bit x4
+    pub fn to_message(&self, tcx: TyCtxt) -> String {
+        let mut s = format!("{}: {}", tcx.def_path_str(self.did), self.reason);
+        if let InconclusiveReason::Timeout(_) = self.reason {
+            let mut widths: BTreeMap<usize, usize> = BTreeMap::default();
+            for width in self.bv_widths.iter() {
+                *widths.entry(*width).or_default() += 1;
+            }
+            let widths: Vec<String> = widths
+                .iter()
+                .map(|(width, count)| format!("{}-bit x{}", width, count))
+                .collect();
+            let widths = if widths.is_empty() { "none".to_string() } else { widths.join(", ") };
+            s += &format!(", {} constraint(s), bit-vectors: {}", self.constraints, widths);
+        }
+        s
+    }
+}
+
+// The site where an owned allocation is stored into the raw pointer field of a proxy type.
+#[derive(Debug, Clone)]
+pub struct ProxySite {
+    did: DefId,
+    span: Span,
+    // the function that the allocation comes from, e.g., Box::into_raw
+    source: String,
+}
+
+impl ProxySite {
+    pub fn new(did: DefId, span: Span, source: String) -> Self {
+        Self { did, span, source }
+    }
+
+    pub fn did(&self) -> DefId {
+        self.did
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn source(&self) -> &String {
+        &self.source
+    }
+}
+
+// ProxyTypeReport is the result of the proxy type checker for one raw pointer field: the field
+// receives owned allocations in the crate, while neither the adt nor the adts holding it implement
+// Drop, thus the allocations are never released when the values are dropped.
+#[derive(Debug, Clone)]
+pub struct ProxyTypeReport {
+    adt: DefId,
+    field: DefId,
+    field_span: Span,
+    // the variant of the adt is an owner in its ownership layout, e.g., a phantom unit
+    owner: bool,
+    sites: Vec<ProxySite>,
+    suppression: Option<Suppression>,
+}
+
+impl ProxyTypeReport {
+    pub fn new(adt: DefId, field: DefId, field_span: Span, owner: bool, sites: Vec<ProxySite>) -> Self {
+        Self { adt, field, field_span, owner, sites, suppression: None }
+    }
+
+    pub fn adt(&self) -> DefId {
+        self.adt
+    }
+
+    pub fn field(&self) -> DefId {
+        self.field
+    }
+
+    pub fn field_span(&self) -> Span {
+        self.field_span
+    }
+
+    pub fn is_owner(&self) -> bool {
+        self.owner
+    }
+
+    pub fn sites(&self) -> &Vec<ProxySite> {
+        &self.sites
+    }
+
+    // the suppression attributes are looked up from the adt
+    pub fn scope(&self) -> DefId {
+        self.adt
+    }
+
+    pub fn suppression(&self) -> Option<Suppression> {
+        self.suppression
+    }
+
+    pub fn set_suppression(&mut self, suppression: Suppression) {
+        self.suppression = Some(suppression);
+    }
+
+    pub fn rule(&self) -> LeakRule {
+        LeakRule::ProxyType
+    }
+
+    // e.g., [Proxy] Foo::ptr receives owned allocations but Foo has no Drop impl, stored by main at a.rs:20:15
+    pub fn to_message(&self, tcx: TyCtxt) -> String {
+        let source_map = tcx.sess.source_map();
+        let sites: Vec<String> = self.sites
+            .iter()
+            .map(|site| format!(
+                "{} from {} at {}",
+                tcx.def_path_str(site.did()),
+                site.source(),
+                source_map.span_to_diagnostic_string(site.span()),
+            ))
+            .collect();
+        format!(
+            "[Proxy] {} receives owned allocations but {} has no Drop impl, stored by {}",
+            tcx.def_path_str(self.field),
+            tcx.def_path_str(self.adt),
+            sites.join(", "),
+        )
+    }
+}
+
+// DropCheckReport is the result of the drop check for one owned raw pointer field of self: the field
+// is not released by `Drop::drop` on some path, only the paths where self may be of the variant of
+// the field are considered.
+#[derive(Debug, Clone)]
+pub struct DropCheckReport {
+    adt: DefId,
+    drop_fn: DefId,
+    // the raw pointer field and its path from self, e.g., `self.0.ptr`
+    field: DefId,
+    field_path: String,
+    // the variant of the enum holding the field, None for a struct
+    variant: Option<DefId>,
+    return_spans: Vec<Span>,
+    suppression: Option<Suppression>,
+}
+
+impl DropCheckReport {
+    pub fn new(
+        adt: DefId,
+        drop_fn: DefId,
+        field: DefId,
+        field_path: String,
+        variant: Option<DefId>,
+        return_spans: Vec<Span>,
+    ) -> Self {
+        Self { adt, drop_fn, field, field_path, variant, return_spans, suppression: None }
+    }
+
+    pub fn adt(&self) -> DefId {
+        self.adt
+    }
+
+    pub fn drop_fn(&self) -> DefId {
+        self.drop_fn
+    }
+
+    pub fn field(&self) -> DefId {
+        self.field
+    }
+
+    pub fn field_path(&self) -> &String {
+        &self.field_path
+    }
+
+    pub fn variant(&self) -> Option<DefId> {
+        self.variant
+    }
+
+    pub fn return_spans(&self) -> &Vec<Span> {
+        &self.return_spans
+    }
+
+    // the suppression attributes are looked up from the drop function, then the impl
+    pub fn scope(&self) -> DefId {
+        self.drop_fn
+    }
+
+    pub fn suppression(&self) -> Option<Suppression> {
+        self.suppression
+    }
+
+    pub fn set_suppression(&mut self, suppression: Suppression) {
+        self.suppression = Some(suppression);
+    }
+
+    pub fn rule(&self) -> LeakRule {
+        LeakRule::DropLeak
+    }
+
+    // e.g., [Drop] <Data as std::ops::Drop>::drop: self.0.ptr of variant Data::Bytes is not released when returning at a.rs:12:6
+    pub fn to_message(&self, tcx: TyCtxt) -> String {
+        let source_map = tcx.sess.source_map();
+        let mut s = format!("[Drop] {}: {}", tcx.def_path_str(self.drop_fn), self.field_path);
+        if let Some(variant) = self.variant {
+            s += &format!(" of variant {}", tcx.def_path_str(variant));
+        }
+        let spans: Vec<String> = self.return_spans
+            .iter()
+            .map(|span| source_map.span_to_diagnostic_string(*span))
+            .collect();
+        s += &format!(" is not released when returning at {}", spans.join(", "));
+        s
+    }
+}
+
+// The capacity passed to `from_raw_parts` that is not provably the capacity of the forgotten value.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum RawPartsMismatch {
+    // the length of the value taken at the span, and the value is not shrunk by `shrink_to_fit`
+    Length(Span),
+    // the capacity is not traced to the value
+    Untraced,
+}
+
+impl Display for RawPartsMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RawPartsMismatch::Length(_) => write!(f, "the length of the value without `shrink_to_fit`"),
+            RawPartsMismatch::Untraced => write!(f, "not traced to the capacity of the value"),
+        }
+    }
+}
+
+// RawPartsReport is the result of the raw parts checker for one call of `Vec::from_raw_parts` or
+// `String::from_raw_parts`, whose pointer comes from a forgotten value while the capacity does not.
+#[derive(Debug, Clone)]
+pub struct RawPartsReport {
+    did: DefId,
+    span: Span,
+    // the function rebuilding the value, e.g., Vec::from_raw_parts
+    callee: String,
+    // where the pointer is taken from the value
+    ptr_span: Span,
+    mismatch: RawPartsMismatch,
+    suppression: Option<Suppression>,
+}
+
+impl RawPartsReport {
+    pub fn new(did: DefId, span: Span, callee: String, ptr_span: Span, mismatch: RawPartsMismatch) -> Self {
+        Self { did, span, callee, ptr_span, mismatch, suppression: None }
+    }
+
+    pub fn did(&self) -> DefId {
+        self.did
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn callee(&self) -> &String {
+        &self.callee
+    }
+
+    pub fn ptr_span(&self) -> Span {
+        self.ptr_span
+    }
+
+    pub fn mismatch(&self) -> RawPartsMismatch {
+        self.mismatch
+    }
+
+    // the suppression attributes are looked up from the function rebuilding the value
+    pub fn scope(&self) -> DefId {
+        self.did
+    }
+
+    pub fn suppression(&self) -> Option<Suppression> {
+        self.suppression
+    }
+
+    pub fn set_suppression(&mut self, suppression: Suppression) {
+        self.suppression = Some(suppression);
+    }
+
+    pub fn rule(&self) -> LeakRule {
+        LeakRule::RawPartsCapacity
+    }
+
+    // e.g., [RawParts] drop: Vec::from_raw_parts at a.rs:30:14 takes a capacity that is the length of ...
+    pub fn to_message(&self, tcx: TyCtxt) -> String {
+        let source_map = tcx.sess.source_map();
+        format!(
+            "[RawParts] {}: {} at {} takes a capacity that is {}, the pointer is taken at {}",
+            tcx.def_path_str(self.did),
+            self.callee,
+            source_map.span_to_diagnostic_string(self.span),
+            self.mismatch,
+            source_map.span_to_diagnostic_string(self.ptr_span),
+        )
+    }
+}
+
+// The site where a raw pointer is escaped from or recovered into an owning value.
+#[derive(Debug, Clone)]
+pub struct RawSite {
+    did: DefId,
+    span: Span,
+    // the function escaping or recovering the pointer, e.g., Box::into_raw
+    callee: String,
+}
+
+impl RawSite {
+    pub fn new(did: DefId, span: Span, callee: String) -> Self {
+        Self { did, span, callee }
+    }
+
+    pub fn did(&self) -> DefId {
+        self.did
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn callee(&self) -> &String {
+        &self.callee
+    }
+}
+
+// RawPairingReport is the result of the raw pairing checker for one pointee type and one escape, e.g.,
+// `Box::into_raw`: no function of the crate recovers the type by the paired `Box::from_raw`, thus the
+// pointers are never released, or they are recovered by the other constructors in `recoveries`.
+#[derive(Debug, Clone)]
+pub struct RawPairingReport {
+    ty: String,
+    escape: String,
+    // the paired recovery that is missing, e.g., Box::from_raw
+    expected: String,
+    escapes: Vec<RawSite>,
+    recoveries: Vec<RawSite>,
+    // the generic recoveries never instantiated in the crate, they may take the type from the other crates
+    generic_recoveries: Vec<RawSite>,
+    suppression: Option<Suppression>,
+}
+
+impl RawPairingReport {
+    pub fn new(
+        ty: String,
+        escape: String,
+        expected: String,
+        escapes: Vec<RawSite>,
+        recoveries: Vec<RawSite>,
+        generic_recoveries: Vec<RawSite>,
+    ) -> Self {
+        Self { ty, escape, expected, escapes, recoveries, generic_recoveries, suppression: None }
+    }
+
+    pub fn ty(&self) -> &String {
+        &self.ty
+    }
+
+    pub fn escape(&self) -> &String {
+        &self.escape
+    }
+
+    pub fn expected(&self) -> &String {
+        &self.expected
+    }
+
+    pub fn escapes(&self) -> &Vec<RawSite> {
+        &self.escapes
+    }
+
+    pub fn recoveries(&self) -> &Vec<RawSite> {
+        &self.recoveries
+    }
+
+    pub fn generic_recoveries(&self) -> &Vec<RawSite> {
+        &self.generic_recoveries
+    }
+
+    // the type is recovered, but only by the other constructors
+    pub fn is_mismatched(&self) -> bool {
+        !self.recoveries.is_empty()
+    }
+
+    // the suppression attributes are looked up from the function of the first escape
+    pub fn scope(&self) -> DefId {
+        self.escapes[0].did()
+    }
+
+    pub fn suppression(&self) -> Option<Suppression> {
+        self.suppression
+    }
+
+    pub fn set_suppression(&mut self, suppression: Suppression) {
+        self.suppression = Some(suppression);
+    }
+
+    pub fn rule(&self) -> LeakRule {
+        LeakRule::RawPairing
+    }
+
+    // e.g., [RawPairing] Node: escaped by Box::into_raw at a.rs:12:5 (2 site(s)), never recovered by Box::from_raw
+    pub fn to_message(&self, tcx: TyCtxt) -> String {
+        let source_map = tcx.sess.source_map();
+        let recovered = if self.is_mismatched() {
+            let mut callees: Vec<&str> = self.recoveries.iter().map(|site| site.callee().as_str()).collect();
+            callees.sort_unstable();
+            callees.dedup();
+            format!("recovered by {} instead of {}", callees.join(", "), self.expected)
+        } else if self.generic_recoveries.is_empty() {
+            format!("never recovered by {}", self.expected)
+        } else {
+            let sites: Vec<String> = self
+                .generic_recoveries
+                .iter()
+                .map(|site| source_map.span_to_diagnostic_string(site.span()))
+                .collect();
+            format!("never recovered by {}, unless by the generic one(s) at {}", self.expected, sites.join(", "))
+        };
+        format!(
+            "[RawPairing] {}: escaped by {} at {} ({} site(s)), {}",
+            self.ty,
+            self.escape,
+            source_map.span_to_diagnostic_string(self.escapes[0].span()),
+            self.escapes.len(),
+            recovered,
+        )
+    }
+}
+
+// Print the summary of all leak reports collected in the global context.
+// The details of each report are emitted as the lints of the compiler, see `emit_leak_lints`.
+pub fn show_leak_summary(rcx: &RlcGlobalCtxt) {
+    let tcx = rcx.tcx();
+
+    // the reports of the type checkers are already emitted as lints, thus only the counts and the
+    // suppressed reports are listed, the same as the leak reports
+    let proxy_types = rcx.proxy_type_reports();
+    let suppressed = proxy_types.iter().filter_map(|r| r.suppression().map(|s| (s, r.to_message(tcx)))).collect();
+    let header = format!("Proxy Types: {} raw pointer field(s) are never released", proxy_types.len());
+    show_checker_summary(rcx, header, proxy_types.len(), suppressed);
+
+    let inconclusive = rcx.inconclusive_fns();
+    if !inconclusive.is_empty() {
+        rlc_info!("Inconclusive: {} function(s) are not fully checked", inconclusive.len());
+        for each in inconclusive {
+            rlc_info!("    {}", each.to_message(tcx));
+        }
+    }
+
+    let drop_checks = rcx.drop_check_reports();
+    let suppressed = drop_checks.iter().filter_map(|r| r.suppression().map(|s| (s, r.to_message(tcx)))).collect();
+    let header = format!("Drop Check: {} owned field(s) are not released by the Drop impls", drop_checks.len());
+    show_checker_summary(rcx, header, drop_checks.len(), suppressed);
+
+    let raw_parts = rcx.raw_parts_reports();
+    let suppressed = raw_parts.iter().filter_map(|r| r.suppression().map(|s| (s, r.to_message(tcx)))).collect();
+    let header = format!("Raw Parts: {} reconstruction(s) with a mismatched capacity", raw_parts.len());
+    show_checker_summary(rcx, header, raw_parts.len(), suppressed);
+
+    let raw_pairing = rcx.raw_pairing_reports();
+    let suppressed = raw_pairing.iter().filter_map(|r| r.suppression().map(|s| (s, r.to_message(tcx)))).collect();
+    let header = format!("Raw Pairing: {} escaped pointee type(s) without the paired recovery", raw_pairing.len());
+    show_checker_summary(rcx, header, raw_pairing.len(), suppressed);
+
+    let reports = rcx.leak_reports();
+    if reports.is_empty() {
+        rlc_info!("Leak Summary: no leak is found");
+        return;
+    }
+
+    let mut fns: Vec<DefId> = reports.iter().map(|r| r.did()).collect();
+    fns.dedup();
+    let suppressed: Vec<(Suppression, &LeakReport)> = reports
+        .iter()
+        .filter_map(|r| r.suppression().map(|s| (s, r)))
+        .collect();
+    rlc_info!(
+        "Leak Summary: {} report(s) in {} function(s), {} suppressed, at {}",
+        reports.len(),
+        fns.len(),
+        suppressed.len(),
+        reports[0].to_grain_message(),
+    );
+    for (suppression, report) in suppressed {
+        rlc_info!("    {}", suppressed_message(rcx, suppression, report.to_message(tcx)));
+    }
+}
+
+// e.g., Raw Parts: 2 reconstruction(s) with a mismatched capacity, 1 suppressed
+fn show_checker_summary(rcx: &RlcGlobalCtxt, header: String, count: usize, suppressed: Vec<(Suppression, String)>) {
+    if count == 0 {
+        return;
+    }
+    rlc_info!("{}, {} suppressed", header, suppressed.len());
+    for (suppression, message) in suppressed {
+        rlc_info!("    {}", suppressed_message(rcx, suppression, message));
+    }
+}
+
+// e.g., allowed by #[rlc::allow] at a.rs:3:1: 3:24: [Proxy] ...
+fn suppressed_message(rcx: &RlcGlobalCtxt, suppression: Suppression, message: String) -> String {
+    let label = match suppression.kind() {
+        SuppressionKind::Allow => "allowed",
+        SuppressionKind::IntentionalLeak(_) => "intentional",
+    };
+    let source = match suppression.span() {
+        Some(span) => format!(
+            "{} at {}",
+            suppression.kind(),
+            rcx.tcx().sess.source_map().span_to_diagnostic_string(span),
+        ),
+        None => match suppression.reason() {
+            Some(reason) => format!("the project config ({})", reason),
+            None => "the project config".to_string(),
+        },
+    };
+    format!("{} by {}: {}", label, source, message)
+}
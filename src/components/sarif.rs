@@ -0,0 +1,302 @@
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use rustc_span::def_id::LOCAL_CRATE;
+
+use crate::components::context::RlcGlobalCtxt;
+use crate::components::fs::{rlc_create_file, rlc_write};
+use crate::components::log::rlc_error_and_exit;
+use crate::components::report::{DropCheckReport, LeakReport, LeakRule, LeakVerdict, ProxyTypeReport, RawPairingReport,
+                                RawPartsReport};
+use crate::components::suppression::Suppression;
+
+use serde_json::{json, Value};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub static SARIF_VERSION: &str = "2.1.0";
+pub static SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+pub static RLC_INFORMATION_URI: &str = "https://github.com/Artisan-Lab/rCanary";
+
+// The env var that holds the dir of the partial SARIF results, it is set by cargo-rlc and each
+// rlc invocation writes the run of its crate into this dir.
+pub static RLC_SARIF_DIR_ENV: &str = "RLC_SARIF_DIR";
+
+fn sarif_tool() -> Value {
+    let rules: Vec<Value> = LeakRule::all()
+        .iter()
+        .map(|rule| json!({
+            "id": rule.id(),
+            "name": rule.name(),
+            "shortDescription": { "text": rule.description() },
+        }))
+        .collect();
+
+    json!({
+        "driver": {
+            "name": "rlc",
+            "version": env!("CARGO_PKG_VERSION"),
+            "informationUri": RLC_INFORMATION_URI,
+            "rules": rules,
+        }
+    })
+}
+
+fn sarif_physical_location(tcx: TyCtxt, span: Span) -> Value {
+    let source_map = tcx.sess.source_map();
+    let lo = source_map.lookup_char_pos(span.lo());
+    let hi = source_map.lookup_char_pos(span.hi());
+
+    json!({
+        "physicalLocation": {
+            "artifactLocation": {
+                "uri": lo.file.name.prefer_local().to_string(),
+            },
+            "region": {
+                "startLine": lo.line,
+                "startColumn": lo.col.0 + 1,
+                "endLine": hi.line,
+                "endColumn": hi.col.0 + 1,
+            }
+        }
+    })
+}
+
+fn sarif_result(tcx: TyCtxt, report: &LeakReport) -> Value {
+    let rule = report.rule();
+    let level = match report.verdict() {
+        LeakVerdict::Unsat => "warning",
+        LeakVerdict::Unknown => "note",
+    };
+
+    let mut result = json!({
+        "ruleId": rule.id(),
+        "ruleIndex": rule.index(),
+        "level": level,
+        "message": { "text": report.to_message(tcx) },
+        "locations": [ sarif_physical_location(tcx, report.primary_span()) ],
+        "properties": {
+            "grain": report.grain().to_string(),
+            "grainEffects": report.grain().effects(),
+        },
+    });
+
+    // the leak in a dependency keeps its own location and points to the local call site it is reached from
+    if let Some((_, call_span)) = report.entry() {
+        let mut location = sarif_physical_location(tcx, call_span);
+        location["message"] = json!({ "text": "the dependency is reached from here" });
+        result["relatedLocations"] = json!([ location ]);
+        result["properties"]["dependency"] = json!(tcx.crate_name(report.did().krate).to_string());
+    }
+
+    // the code flow is built from the trace of the leak, one thread flow location per step
+    if !report.trace().is_empty() {
+        let locations: Vec<Value> = report
+            .trace()
+            .iter()
+            .map(|step| {
+                let mut location = sarif_physical_location(tcx, step.span());
+                location["message"] = json!({ "text": step.kind().to_string() });
+                json!({ "location": location })
+            })
+            .collect();
+        result["codeFlows"] = json!([ { "threadFlows": [ { "locations": locations } ] } ]);
+    }
+
+    sarif_suppress(&mut result, report.suppression());
+    result
+}
+
+// The suppressed reports are kept in the log with the in-source suppression, and the intentional leaks
+// are downgraded to notes.
+fn sarif_suppress(result: &mut Value, suppression: Option<Suppression>) {
+    if let Some(suppression) = suppression {
+        let mut entry = json!({ "kind": "inSource" });
+        if let Some(reason) = suppression.reason() {
+            result["level"] = json!("note");
+            entry["justification"] = json!(reason.to_string());
+        }
+        result["suppressions"] = json!([ entry ]);
+    }
+}
+
+fn sarif_proxy_type_result(tcx: TyCtxt, report: &ProxyTypeReport) -> Value {
+    let rule = report.rule();
+    // the sites storing the allocations are the related locations of the field
+    let locations: Vec<Value> = report
+        .sites()
+        .iter()
+        .map(|site| {
+            let mut location = sarif_physical_location(tcx, site.span());
+            location["message"] = json!({ "text": format!("stores the allocation from {}", site.source()) });
+            location
+        })
+        .collect();
+
+    let mut result = json!({
+        "ruleId": rule.id(),
+        "ruleIndex": rule.index(),
+        "level": "warning",
+        "message": { "text": report.to_message(tcx) },
+        "locations": [ sarif_physical_location(tcx, report.field_span()) ],
+        "relatedLocations": locations,
+    });
+    sarif_suppress(&mut result, report.suppression());
+    result
+}
+
+fn sarif_drop_check_result(tcx: TyCtxt, report: &DropCheckReport) -> Value {
+    let rule = report.rule();
+    let locations: Vec<Value> = report
+        .return_spans()
+        .iter()
+        .map(|span| sarif_physical_location(tcx, *span))
+        .collect();
+    let mut field = sarif_physical_location(tcx, tcx.def_span(report.field()));
+    field["message"] = json!({ "text": "the field holds an owned allocation" });
+
+    let mut result = json!({
+        "ruleId": rule.id(),
+        "ruleIndex": rule.index(),
+        "level": "warning",
+        "message": { "text": report.to_message(tcx) },
+        "locations": locations,
+        "relatedLocations": [ field ],
+    });
+    sarif_suppress(&mut result, report.suppression());
+    result
+}
+
+fn sarif_raw_parts_result(tcx: TyCtxt, report: &RawPartsReport) -> Value {
+    let rule = report.rule();
+    let mut ptr = sarif_physical_location(tcx, report.ptr_span());
+    ptr["message"] = json!({ "text": "the pointer is taken from the value here" });
+
+    let mut result = json!({
+        "ruleId": rule.id(),
+        "ruleIndex": rule.index(),
+        "level": "warning",
+        "message": { "text": report.to_message(tcx) },
+        "locations": [ sarif_physical_location(tcx, report.span()) ],
+        "relatedLocations": [ ptr ],
+    });
+    sarif_suppress(&mut result, report.suppression());
+    result
+}
+
+fn sarif_raw_pairing_result(tcx: TyCtxt, report: &RawPairingReport) -> Value {
+    let rule = report.rule();
+    // the other escapes, the recoveries by other constructors and the uninstantiated generic recoveries are
+    // the related locations
+    let mut locations: Vec<Value> = Vec::default();
+    for site in report.escapes()[1..].iter() {
+        let mut location = sarif_physical_location(tcx, site.span());
+        location["message"] = json!({ "text": format!("escaped by {}", site.callee()) });
+        locations.push(location);
+    }
+    for site in report.recoveries().iter() {
+        let mut location = sarif_physical_location(tcx, site.span());
+        location["message"] = json!({ "text": format!("recovered by {}", site.callee()) });
+        locations.push(location);
+    }
+    for site in report.generic_recoveries().iter() {
+        let mut location = sarif_physical_location(tcx, site.span());
+        location["message"] = json!({ "text": format!("may be recovered by the generic {}", site.callee()) });
+        locations.push(location);
+    }
+
+    let mut result = json!({
+        "ruleId": rule.id(),
+        "ruleIndex": rule.index(),
+        "level": "warning",
+        "message": { "text": report.to_message(tcx) },
+        "locations": [ sarif_physical_location(tcx, report.escapes()[0].span()) ],
+        "relatedLocations": locations,
+    });
+    sarif_suppress(&mut result, report.suppression());
+    result
+}
+
+// Generate the SARIF run for the crate being analyzed.
+pub fn sarif_run(rcx: &RlcGlobalCtxt) -> Value {
+    let tcx = rcx.tcx();
+    let mut results: Vec<Value> = rcx
+        .leak_reports()
+        .iter()
+        .map(|report| sarif_result(tcx, report))
+        .collect();
+    results.extend(rcx.proxy_type_reports().iter().map(|report| sarif_proxy_type_result(tcx, report)));
+    results.extend(rcx.drop_check_reports().iter().map(|report| sarif_drop_check_result(tcx, report)));
+    results.extend(rcx.raw_parts_reports().iter().map(|report| sarif_raw_parts_result(tcx, report)));
+    results.extend(rcx.raw_pairing_reports().iter().map(|report| sarif_raw_pairing_result(tcx, report)));
+
+    // the inconclusive functions are not results, they are reported as the notifications of the run
+    let notifications: Vec<Value> = rcx
+        .inconclusive_fns()
+        .iter()
+        .map(|each| json!({
+            "level": "warning",
+            "message": { "text": format!("inconclusive: {}", each.to_message(tcx)) },
+            "locations": [ sarif_physical_location(tcx, tcx.def_span(each.did())) ],
+        }))
+        .collect();
+
+    json!({
+        "tool": sarif_tool(),
+        "automationDetails": { "id": tcx.crate_name(LOCAL_CRATE).to_string() },
+        "invocations": [ {
+            "executionSuccessful": true,
+            "toolExecutionNotifications": notifications,
+        } ],
+        "results": results,
+    })
+}
+
+// Write the SARIF run of current crate as a partial result into the given dir.
+pub fn write_sarif_run<P: AsRef<Path>>(rcx: &RlcGlobalCtxt, dir: P) {
+    let tcx = rcx.tcx();
+    let mut path = PathBuf::from(dir.as_ref());
+    path.push(
+        format!(
+            "{}-{:016x}.json",
+            tcx.crate_name(LOCAL_CRATE),
+            tcx.sess.local_stable_crate_id().to_u64(),
+        )
+    );
+
+    let run = serde_json::to_string(&sarif_run(rcx)).expect("failed to serialize SARIF run");
+    let file = rlc_create_file(&path, "Failed to create SARIF partial result");
+    rlc_write(file, run.as_bytes(), "Failed to write SARIF partial result");
+}
+
+// Merge all partial runs in the dir into one SARIF log. The runs are sorted by the file name,
+// thus the output is stable for the same crates.
+pub fn merge_sarif_runs<P: AsRef<Path>, Q: AsRef<Path>>(dir: P, output: Q) {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+            .collect(),
+        Err(_) => Vec::default(),
+    };
+    paths.sort();
+
+    let mut runs: Vec<Value> = Vec::with_capacity(paths.len());
+    for path in paths {
+        let content = fs::read_to_string(&path)
+            .unwrap_or_else(|e| rlc_error_and_exit(format!("Failed to read SARIF partial result: {}", e)));
+        let run: Value = serde_json::from_str(&content)
+            .unwrap_or_else(|e| rlc_error_and_exit(format!("Failed to parse SARIF partial result: {}", e)));
+        runs.push(run);
+    }
+
+    let log = json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": runs,
+    });
+    let log = serde_json::to_string_pretty(&log).expect("failed to serialize SARIF log");
+    let file = rlc_create_file(output, "Failed to create SARIF output");
+    rlc_write(file, log.as_bytes(), "Failed to write SARIF output");
+}
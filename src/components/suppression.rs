@@ -0,0 +1,409 @@
+use rustc_ast::{Attribute, NestedMetaItem};
+use rustc_hir::HirId;
+use rustc_middle::ty::TyCtxt;
+use rustc_session::parse::ParseSess;
+use rustc_span::{FileName, Span, Symbol};
+use rustc_span::def_id::{DefId, LocalDefId};
+
+use crate::components::context::RlcGlobalCtxt;
+use crate::components::report::LeakRule;
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+// The tool attributes of rlc that suppress the leak reports, they are read from the function,
+// the impl, the modules and the crate enclosing the leaking function, and the nearest one wins.
+//   #[rlc::allow(leak)]                            : the report is dropped
+//   #[rlc::intentional_leak(reason = "...")]       : the report is downgraded to a note
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum SuppressionKind {
+    Allow,
+    IntentionalLeak(Symbol),
+}
+
+impl Display for SuppressionKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SuppressionKind::Allow => write!(f, "#[rlc::allow]"),
+            SuppressionKind::IntentionalLeak(reason) => write!(f, "#[rlc::intentional_leak] ({})", reason),
+        }
+    }
+}
+
+// The suppression applied to one report, the span points to the attribute, or None if the
+// suppression comes from the project config.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub struct Suppression {
+    kind: SuppressionKind,
+    span: Option<Span>,
+}
+
+impl Suppression {
+    pub fn new(kind: SuppressionKind, span: Option<Span>) -> Self {
+        Self { kind, span }
+    }
+
+    pub fn kind(&self) -> SuppressionKind {
+        self.kind
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    pub fn reason(&self) -> Option<Symbol> {
+        match self.kind {
+            SuppressionKind::Allow => None,
+            SuppressionKind::IntentionalLeak(reason) => Some(reason),
+        }
+    }
+}
+
+// The suppression in the project config, it matches the functions whose def paths start with the path,
+// e.g., `registry` matches `registry::register` and `registry::Registry::new`.
+//   [[suppress]]
+//   path = "registry"
+//   reason = "..."       : the report is downgraded as `rlc::intentional_leak`, or dropped if absent
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SuppressionConfig {
+    path: String,
+    reason: Option<String>,
+}
+
+impl SuppressionConfig {
+    pub fn new(path: String, reason: Option<String>) -> Self {
+        Self { path, reason }
+    }
+
+    pub fn path(&self) -> &String {
+        &self.path
+    }
+
+    pub fn reason(&self) -> Option<&String> {
+        self.reason.as_ref()
+    }
+
+    fn matches(&self, def_path: &str) -> bool {
+        def_path == self.path || def_path.starts_with(&format!("{}::", self.path))
+    }
+
+    fn to_suppression(&self) -> Suppression {
+        let kind = match &self.reason {
+            Some(reason) => SuppressionKind::IntentionalLeak(Symbol::intern(reason)),
+            None => SuppressionKind::Allow,
+        };
+        Suppression::new(kind, None)
+    }
+}
+
+// One parsed suppression attribute and the rules that it matches.
+#[derive(Debug, Clone)]
+struct SuppressionAttr {
+    suppression: Suppression,
+    rules: Vec<LeakRule>,
+}
+
+// The names accepted by `#[rlc::allow(..)]`.
+fn allow_rules(name: &str) -> Option<Vec<LeakRule>> {
+    match name {
+        "leak" => Some(vec![LeakRule::MemoryLeak, LeakRule::PanicPathLeak, LeakRule::ManuallyDropLeak]),
+        "leak_on_panic" => Some(vec![LeakRule::PanicPathLeak]),
+        "manually_drop" => Some(vec![LeakRule::ManuallyDropLeak]),
+        "inconclusive" => Some(vec![LeakRule::Inconclusive]),
+        "proxy_type" => Some(vec![LeakRule::ProxyType]),
+        "drop" => Some(vec![LeakRule::DropLeak]),
+        "raw_parts" => Some(vec![LeakRule::RawPartsCapacity]),
+        "raw_pairing" => Some(vec![LeakRule::RawPairing]),
+        _ => None,
+    }
+}
+
+fn is_rlc_attr(attr: &Attribute, name: &str) -> bool {
+    if attr.is_doc_comment() {
+        return false;
+    }
+    let segments = &attr.get_normal_item().path.segments;
+    segments.len() == 2
+        && segments[0].ident.name.as_str() == "rlc"
+        && segments[1].ident.name.as_str() == name
+}
+
+// Parse the suppression attributes on one HIR node, the malformed ones are reported as errors.
+fn parse_suppression_attrs(tcx: TyCtxt<'_>, hir_id: HirId) -> Vec<SuppressionAttr> {
+    parse_attrs(tcx.hir().attrs(hir_id), |span, msg| {
+        tcx.sess.span_err(span, msg);
+    })
+}
+
+// Parse the suppression attributes in the list, the malformed ones are passed to `report_err`.
+fn parse_attrs(attrs: &[Attribute], mut report_err: impl FnMut(Span, &str)) -> Vec<SuppressionAttr> {
+    let mut res = Vec::default();
+    for attr in attrs {
+        if is_rlc_attr(attr, "allow") {
+            let items = attr.meta_item_list().unwrap_or_default();
+            if items.is_empty() {
+                report_err(attr.span, "malformed `rlc::allow` attribute, expected `#[rlc::allow(leak)]`");
+                continue;
+            }
+            let mut rules = Vec::default();
+            for item in items.iter() {
+                match item.ident().and_then(|ident| allow_rules(ident.name.as_str())) {
+                    Some(r) if item.is_word() => rules.extend(r),
+                    _ => {
+                        report_err(
+                            item.span(),
+                            "unknown rule in `rlc::allow`, expected one of `leak`, `leak_on_panic`, `manually_drop`, `inconclusive`, \
+                             `proxy_type`, `drop`, `raw_parts`, `raw_pairing`",
+                        );
+                    },
+                }
+            }
+            res.push(SuppressionAttr {
+                suppression: Suppression::new(SuppressionKind::Allow, Some(attr.span)),
+                rules,
+            });
+        } else if is_rlc_attr(attr, "intentional_leak") {
+            let items = attr.meta_item_list().unwrap_or_default();
+            let reason = items
+                .iter()
+                .find(|item| item.has_name(Symbol::intern("reason")))
+                .and_then(|item| item.value_str());
+            match reason {
+                Some(reason) => res.push(SuppressionAttr {
+                    suppression: Suppression::new(SuppressionKind::IntentionalLeak(reason), Some(attr.span)),
+                    rules: LeakRule::all(),
+                }),
+                None => {
+                    report_err(
+                        attr.span,
+                        "malformed `rlc::intentional_leak` attribute, expected `#[rlc::intentional_leak(reason = \"...\")]`",
+                    );
+                },
+            }
+        }
+    }
+    res
+}
+
+// Attach the suppressions to the reports in the global context. The attributes are looked up from the
+// scope of the report (the leaking function, the adt of a proxy type, ...) to the crate root through the
+// HIR parents, thus the attributes on the closures, functions, impls, modules and the crate are all
+// respected. The suppressions in the project config are checked only if no attribute matches.
+pub fn apply_suppressions(rcx: &mut RlcGlobalCtxt) {
+    let tcx = rcx.tcx();
+    let configs = rcx.config().suppressions().clone();
+    let mut cache: HashMap<HirId, Vec<SuppressionAttr>> = HashMap::default();
+    let mut suppression_of = |local_did: Option<LocalDefId>, did: DefId, rule: LeakRule| {
+        local_did.and_then(|local_did| find_suppression(tcx, &configs, &mut cache, local_did, did, rule))
+    };
+
+    // the leak in a dependency is suppressed in the local function it is reached from
+    for report in rcx.leak_reports_mut().iter_mut() {
+        if let Some(suppression) = suppression_of(report.local_did(), report.did(), report.rule()) {
+            report.set_suppression(suppression);
+        }
+    }
+    for report in rcx.proxy_type_reports_mut().iter_mut() {
+        if let Some(suppression) = suppression_of(report.scope().as_local(), report.scope(), report.rule()) {
+            report.set_suppression(suppression);
+        }
+    }
+    for report in rcx.drop_check_reports_mut().iter_mut() {
+        if let Some(suppression) = suppression_of(report.scope().as_local(), report.scope(), report.rule()) {
+            report.set_suppression(suppression);
+        }
+    }
+    for report in rcx.raw_parts_reports_mut().iter_mut() {
+        if let Some(suppression) = suppression_of(report.scope().as_local(), report.scope(), report.rule()) {
+            report.set_suppression(suppression);
+        }
+    }
+    for report in rcx.raw_pairing_reports_mut().iter_mut() {
+        if let Some(suppression) = suppression_of(report.scope().as_local(), report.scope(), report.rule()) {
+            report.set_suppression(suppression);
+        }
+    }
+}
+
+// The nearest suppression of the rule in the local scope of the report, or the project config matching
+// the def path of the reported item.
+fn find_suppression(
+    tcx: TyCtxt<'_>,
+    configs: &[SuppressionConfig],
+    cache: &mut HashMap<HirId, Vec<SuppressionAttr>>,
+    local_did: LocalDefId,
+    did: DefId,
+    rule: LeakRule,
+) -> Option<Suppression> {
+    let hir_id = tcx.hir().local_def_id_to_hir_id(local_did);
+
+    let scopes = std::iter::once(hir_id).chain(tcx.hir().parent_iter(hir_id).map(|(id, _)| id));
+    for scope in scopes {
+        let attrs = cache
+            .entry(scope)
+            .or_insert_with(|| parse_suppression_attrs(tcx, scope));
+        if let Some(attr) = attrs.iter().find(|attr| attr.rules.contains(&rule)) {
+            return Some(attr.suppression);
+        }
+    }
+
+    let def_path = tcx.def_path_str(did);
+    configs
+        .iter()
+        .find(|config| config.matches(&def_path))
+        .map(|config| config.to_suppression())
+}
+
+// The crate attrs that the tool attributes of rlc rely on. Each one is injected by `-Zcrate-attr` only if
+// the crate root does not declare it, since rustc rejects a feature declared twice (E0636) and a tool
+// registered twice.
+static RLC_TOOL_CRATE_ATTRS: &[(&str, &str)] = &[
+    ("feature", "register_tool"),
+    ("register_tool", "rlc"),
+    ("feature", "custom_inner_attributes"),
+];
+
+// The `-Zcrate-attr` args of the tool crate attrs that the crate root does not declare yet, e.g.,
+// `-Zcrate-attr=register_tool(rlc)`. All of them are returned if the crate root is unknown or unreadable.
+pub fn rlc_tool_attr_args(crate_root: Option<&Path>) -> Vec<String> {
+    let declared = crate_root
+        .and_then(|crate_root| fs::read_to_string(crate_root).ok())
+        .map(declared_crate_attrs)
+        .unwrap_or_default();
+    RLC_TOOL_CRATE_ATTRS
+        .iter()
+        .filter(|(name, arg)| !declared.iter().any(|(n, a)| n == name && a == arg))
+        .map(|(name, arg)| format!("-Zcrate-attr={}({})", name, arg))
+        .collect()
+}
+
+// The (name, arg) pairs of the inner attributes of the crate root, e.g., ("feature", "register_tool")
+// for `#![feature(register_tool)]`. The attrs under `cfg_attr(rlc, ..)` are declared for rlc as well,
+// while the other `cfg_attr` are not expanded.
+fn declared_crate_attrs(source: String) -> Vec<(String, String)> {
+    // the parsing runs before the compiler, thus it sets up its own session globals
+    rustc_span::create_default_session_globals_then(|| {
+        let sess = ParseSess::with_silent_emitter(None);
+        let name = FileName::Custom("crate root".to_string());
+        let attrs = match rustc_parse::parse_crate_attrs_from_source_str(name, source, &sess) {
+            Ok(attrs) => attrs,
+            Err(err) => {
+                err.cancel();
+                return Vec::default();
+            },
+        };
+
+        let mut declared = Vec::default();
+        for attr in attrs.iter().filter(|attr| !attr.is_doc_comment()) {
+            let items = attr.meta_item_list().unwrap_or_default();
+            let name = attr.name_or_empty();
+            if name.as_str() != "cfg_attr" {
+                declared.extend(items.iter().filter_map(|item| Some((name.to_string(), item.ident()?.to_string()))));
+                continue;
+            }
+            if !items.first().map_or(false, |cfg| cfg.is_word() && cfg.has_name(Symbol::intern("rlc"))) {
+                continue;
+            }
+            for item in items.iter().skip(1).filter_map(NestedMetaItem::meta_item) {
+                let name = item.name_or_empty().to_string();
+                let args = item.meta_item_list().unwrap_or_default();
+                declared.extend(args.iter().filter_map(|arg| Some((name.clone(), arg.ident()?.to_string()))));
+            }
+        }
+        declared
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parse the items of the source and check the suppression attributes of its first item with the
+    // messages of the malformed ones, in the session globals that the symbols are interned in.
+    fn with_first_item_attrs(source: &str, check: impl FnOnce(Vec<SuppressionAttr>, Vec<String>)) {
+        rustc_span::create_default_session_globals_then(|| {
+            let sess = ParseSess::with_silent_emitter(None);
+            let name = FileName::Custom("sup.rs".to_string());
+            let krate = rustc_parse::parse_crate_from_source_str(name, source.to_string(), &sess)
+                .map_err(|err| err.cancel())
+                .expect("the source is valid");
+            let mut errs = Vec::default();
+            let attrs = parse_attrs(&krate.items[0].attrs, |_, msg| errs.push(msg.to_string()));
+            check(attrs, errs);
+        })
+    }
+
+    #[test]
+    fn suppression_config_matches_whole_segments() {
+        let config = SuppressionConfig::new("registry".to_string(), None);
+        assert!(config.matches("registry"));
+        assert!(config.matches("registry::register"));
+        assert!(config.matches("registry::Registry::new"));
+        assert!(!config.matches("registry2::register"));
+        assert!(!config.matches("app::registry::register"));
+    }
+
+    #[test]
+    fn parse_allow_rules() {
+        with_first_item_attrs("#[rlc::allow(leak_on_panic, raw_parts)]\nfn f() {}", |attrs, errs| {
+            assert!(errs.is_empty());
+            assert_eq!(attrs.len(), 1);
+            assert_eq!(attrs[0].suppression.kind(), SuppressionKind::Allow);
+            assert_eq!(attrs[0].rules, vec![LeakRule::PanicPathLeak, LeakRule::RawPartsCapacity]);
+        });
+        // `leak` covers the leaks on panic and the values wrapped by `ManuallyDrop` as well
+        with_first_item_attrs("#[rlc::allow(leak)]\nfn f() {}", |attrs, _| {
+            assert_eq!(attrs[0].rules, vec![LeakRule::MemoryLeak, LeakRule::PanicPathLeak, LeakRule::ManuallyDropLeak]);
+        });
+    }
+
+    #[test]
+    fn parse_intentional_leak() {
+        let source = "#[doc = \"f\"]\n#[rlc::intentional_leak(reason = \"lives until exit\")]\nfn f() {}";
+        with_first_item_attrs(source, |attrs, errs| {
+            assert!(errs.is_empty());
+            assert_eq!(attrs.len(), 1);
+            let reason = attrs[0].suppression.reason().map(|reason| reason.to_string());
+            assert_eq!(reason, Some("lives until exit".to_string()));
+            assert_eq!(attrs[0].rules, LeakRule::all());
+        });
+    }
+
+    #[test]
+    fn parse_malformed_attrs() {
+        let source = "#[rlc::allow]\n#[rlc::allow(leak, forget)]\n#[rlc::intentional_leak]\n\
+                      #[allow(dead_code)]\nfn f() {}";
+        with_first_item_attrs(source, |attrs, errs| {
+            // the unknown rule is skipped, while the known ones in the same attr are kept
+            assert_eq!(attrs.len(), 1);
+            assert_eq!(attrs[0].rules, vec![LeakRule::MemoryLeak, LeakRule::PanicPathLeak, LeakRule::ManuallyDropLeak]);
+            assert_eq!(errs.len(), 3);
+            assert!(errs[0].starts_with("malformed `rlc::allow`"));
+            assert!(errs[1].starts_with("unknown rule in `rlc::allow`"));
+            assert!(errs[2].starts_with("malformed `rlc::intentional_leak`"));
+        });
+    }
+
+    #[test]
+    fn tool_attr_args_skip_declared_attrs() {
+        let all = rlc_tool_attr_args(None);
+        assert_eq!(all, vec![
+            "-Zcrate-attr=feature(register_tool)",
+            "-Zcrate-attr=register_tool(rlc)",
+            "-Zcrate-attr=feature(custom_inner_attributes)",
+        ]);
+
+        let source = "//! doc\n#![feature(rustc_private, register_tool)]\n#![register_tool(rlc)]\n\
+                      #![cfg_attr(rlc, feature(custom_inner_attributes))]\n\
+                      #![cfg_attr(test, feature(test))]\nfn f() {}";
+        let declared = declared_crate_attrs(source.to_string());
+        assert_eq!(declared, vec![
+            ("feature".to_string(), "rustc_private".to_string()),
+            ("feature".to_string(), "register_tool".to_string()),
+            ("register_tool".to_string(), "rlc".to_string()),
+            ("feature".to_string(), "custom_inner_attributes".to_string()),
+        ]);
+    }
+}
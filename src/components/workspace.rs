@@ -0,0 +1,50 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+// The env var that holds the workspace of the package being checked, it is set by cargo-rlc for
+// the rlc invocations, and the artifacts of the workspace are not written if it is absent.
+pub static RLC_WORKSPACE_ENV: &str = "RLC_WORKSPACE";
+
+// The env var that chooses the dir of the artifacts of rlc instead of `target/rlc/<profile>`,
+// the `--rlc-dir` flag of cargo-rlc overrides it.
+pub static RLC_DIR_ENV: &str = "RLC_DIR";
+
+// RlcWorkspace is the dir of the artifacts of one package: the llvm ir and the call graph of phase II
+// and the partial SARIF runs of phase III. It is `<rlc dir>/<package>`, thus the concurrent runs on
+// different packages never touch the same files even if they share the target dir.
+#[derive(Debug, Clone)]
+pub struct RlcWorkspace {
+    root: PathBuf,
+}
+
+impl RlcWorkspace {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: PathBuf::from(root.as_ref()),
+        }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        env::var_os(RLC_WORKSPACE_ENV).map(Self::new)
+    }
+
+    pub fn root(&self) -> &PathBuf {
+        &self.root
+    }
+
+    pub fn llvm_cache(&self) -> PathBuf {
+        self.root.join("llvm-cache")
+    }
+
+    pub fn llvm_ir(&self) -> PathBuf {
+        self.root.join("llvm-ir")
+    }
+
+    pub fn sarif(&self) -> PathBuf {
+        self.root.join("sarif")
+    }
+
+    pub fn call_graph(&self) -> PathBuf {
+        self.root.join("cg.json")
+    }
+}
@@ -8,11 +8,20 @@
 pub mod analysis;
 pub mod components;
 
+extern crate rustc_ast;
+extern crate rustc_data_structures;
 extern crate rustc_middle;
 extern crate rustc_hir;
 extern crate rustc_span;
 extern crate rustc_index;
 extern crate rustc_target;
+extern crate rustc_session;
+extern crate rustc_errors;
+extern crate rustc_parse;
+#[cfg(test)]
+extern crate rustc_driver;
+#[cfg(test)]
+extern crate rustc_interface;
 
 extern crate serde;
 extern crate serde_json;
@@ -23,25 +32,37 @@ extern crate core;
 
 use rustc_middle::ty::TyCtxt;
 
+use crate::components::cache::{show_cache_stats, RlcCache, RLC_CACHE_DIR_ENV};
 use crate::components::grain::RlcGrain;
 use crate::components::log::Verbosity;
 use crate::components::context::RlcGlobalCtxt;
 use crate::components::display::MirDisplay;
-use crate::analysis::flow_analysis::{FlowAnalysis, IcxSliceDisplay, Z3GoalDisplay};
-use crate::analysis::type_analysis::{TypeAnalysis, AdtOwnerDisplay};
+use crate::components::lint::emit_leak_lints;
+use crate::components::model::FnModel;
+use crate::components::report::show_leak_summary;
+use crate::components::sarif::{write_sarif_run, RLC_SARIF_DIR_ENV};
+use crate::components::suppression::{apply_suppressions, SuppressionConfig};
+use crate::analysis::flow_analysis::{FlowAnalysis, IcxSliceDisplay, UnwindMode, Z3GoalDisplay};
+use crate::analysis::type_analysis::{TypeAnalysis, AdtOwnerDisplay, DropCheckMode};
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 // Insert rustc arguments at the beginning of the argument list that RLC wants to be
-// set per default, for maximal validation power.
-pub static RLC_DEFAULT_ARGS: &[&str] =
-    &["-Zalways-encode-mir", "-Zmir-opt-level=0", "--cfg=rlc"];
-pub static RLC_ROOT:&str = "/tmp/rlc";
-pub static RLC_LLVM_CACHE:&str = "/tmp/rlc/llvm-cache";
-pub static RLC_LLVM_IR:&str = "/tmp/rlc/llvm-ir";
-pub static RLC_LLVM_RES:&str = "/tmp/rlc/llvm-res";
+// set per default, for maximal validation power. The crate attrs registering the tool attributes
+// of rlc are inserted with them unless the crate declares them, see `rlc_tool_attr_args`.
+pub static RLC_DEFAULT_ARGS: &[&str] = &["-Zalways-encode-mir", "-Zmir-opt-level=0", "--cfg=rlc"];
+
+pub static RLC_DEFAULT_SOLVER_TIMEOUT: u64 = 1000;
+// The prefix of the arg that carries the serialized RlcConfig.
+pub static RLC_CONFIG_ARG: &str = "-CONFIG=";
 
 pub type Elapsed = (i64, i64);
 
-#[derive(Debug, Copy, Clone, Hash)]
+// RlcConfig is the config of one rlc invocation. It is built by cargo-rlc from the project config
+// (rlc.toml and [package.metadata.rlc]) and passed to rlc through `RLC_ARGS` in json, the magic args
+// following it (e.g., -GRAIN=HIGH) override the fields.
+#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
 pub struct RlcConfig {
     grain: RlcGrain,
     verbose: Verbosity,
@@ -49,6 +70,24 @@ pub struct RlcConfig {
     adt_display: AdtOwnerDisplay,
     z3_goal_display: Z3GoalDisplay,
     icx_slice_display: IcxSliceDisplay,
+    unwind_mode: UnwindMode,
+    drop_check_mode: DropCheckMode,
+    // the timeout of the solver for each function in milliseconds
+    solver_timeout: u64,
+    // the timeouts of the functions whose def paths start with the keys, the longest key wins
+    fn_timeouts: BTreeMap<String, u64>,
+    // the time budget of the whole analysis in milliseconds, the functions visited after the budget
+    // is exhausted are inconclusive
+    analysis_budget: Option<u64>,
+    // the number of the workers solving the constraints of the functions concurrently
+    jobs: usize,
+    // the functions are analyzed only if their source files are under the included paths (if any)
+    // and not under the excluded paths, the paths are relative to the workspace root
+    include_paths: Vec<PathBuf>,
+    exclude_paths: Vec<PathBuf>,
+    suppressions: Vec<SuppressionConfig>,
+    // the ownership models of the functions that the analysis cannot see through, e.g., ffi
+    fn_models: Vec<FnModel>,
 }
 
 impl Default for RlcConfig {
@@ -60,11 +99,22 @@ impl Default for RlcConfig {
             adt_display: AdtOwnerDisplay::Disabled,
             z3_goal_display: Z3GoalDisplay::Disabled,
             icx_slice_display: IcxSliceDisplay::Disabled,
+            unwind_mode: UnwindMode::Disabled,
+            drop_check_mode: DropCheckMode::Disabled,
+            solver_timeout: RLC_DEFAULT_SOLVER_TIMEOUT,
+            fn_timeouts: BTreeMap::default(),
+            analysis_budget: None,
+            jobs: 1,
+            include_paths: Vec::default(),
+            exclude_paths: Vec::default(),
+            suppressions: Vec::default(),
+            fn_models: Vec::default(),
         }
     }
 }
 
 impl RlcConfig {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         grain: RlcGrain,
         verbose: Verbosity,
@@ -72,6 +122,16 @@ impl RlcConfig {
         adt_display: AdtOwnerDisplay,
         z3_goal_display: Z3GoalDisplay,
         icx_slice_display: IcxSliceDisplay,
+        unwind_mode: UnwindMode,
+        drop_check_mode: DropCheckMode,
+        solver_timeout: u64,
+        fn_timeouts: BTreeMap<String, u64>,
+        analysis_budget: Option<u64>,
+        jobs: usize,
+        include_paths: Vec<PathBuf>,
+        exclude_paths: Vec<PathBuf>,
+        suppressions: Vec<SuppressionConfig>,
+        fn_models: Vec<FnModel>,
     ) -> Self {
         Self {
             grain,
@@ -80,6 +140,16 @@ impl RlcConfig {
             adt_display,
             z3_goal_display,
             icx_slice_display,
+            unwind_mode,
+            drop_check_mode,
+            solver_timeout,
+            fn_timeouts,
+            analysis_budget,
+            jobs,
+            include_paths,
+            exclude_paths,
+            suppressions,
+            fn_models,
         }
     }
 
@@ -107,6 +177,76 @@ impl RlcConfig {
 
     pub fn set_icx_slice_display(&mut self, icx_slice_display: IcxSliceDisplay) { self.icx_slice_display = icx_slice_display; }
 
+    pub fn unwind_mode(&self) -> UnwindMode { self.unwind_mode }
+
+    // The unwind paths are checked if `-UNWIND` is given or the grain includes them.
+    pub fn effective_unwind_mode(&self) -> UnwindMode {
+        if self.grain.has_unwind_paths() { UnwindMode::Enabled } else { self.unwind_mode }
+    }
+
+    pub fn set_unwind_mode(&mut self, unwind_mode: UnwindMode) { self.unwind_mode = unwind_mode; }
+
+    pub fn drop_check_mode(&self) -> DropCheckMode { self.drop_check_mode }
+
+    pub fn set_drop_check_mode(&mut self, drop_check_mode: DropCheckMode) { self.drop_check_mode = drop_check_mode; }
+
+    pub fn solver_timeout(&self) -> u64 { self.solver_timeout }
+
+    pub fn set_solver_timeout(&mut self, solver_timeout: u64) { self.solver_timeout = solver_timeout; }
+
+    pub fn fn_timeouts(&self) -> &BTreeMap<String, u64> { &self.fn_timeouts }
+
+    pub fn fn_timeouts_mut(&mut self) -> &mut BTreeMap<String, u64> { &mut self.fn_timeouts }
+
+    pub fn analysis_budget(&self) -> Option<u64> { self.analysis_budget }
+
+    pub fn set_analysis_budget(&mut self, analysis_budget: u64) { self.analysis_budget = Some(analysis_budget); }
+
+    pub fn jobs(&self) -> usize { self.jobs }
+
+    // At least one worker is needed to solve the constraints.
+    pub fn set_jobs(&mut self, jobs: usize) { self.jobs = jobs.max(1); }
+
+    // The timeout of the solver for the function, e.g., the key `parser` matches `parser::parse`.
+    pub fn solver_timeout_of(&self, def_path: &str) -> u64 {
+        self.fn_timeouts
+            .iter()
+            .filter(|(path, _)| def_path == path.as_str() || def_path.starts_with(&format!("{}::", path)))
+            .max_by_key(|(path, _)| path.len())
+            .map_or(self.solver_timeout, |(_, timeout)| *timeout)
+    }
+
+    pub fn include_paths(&self) -> &Vec<PathBuf> { &self.include_paths }
+
+    pub fn include_paths_mut(&mut self) -> &mut Vec<PathBuf> { &mut self.include_paths }
+
+    pub fn exclude_paths(&self) -> &Vec<PathBuf> { &self.exclude_paths }
+
+    pub fn exclude_paths_mut(&mut self) -> &mut Vec<PathBuf> { &mut self.exclude_paths }
+
+    pub fn suppressions(&self) -> &Vec<SuppressionConfig> { &self.suppressions }
+
+    pub fn suppressions_mut(&mut self) -> &mut Vec<SuppressionConfig> { &mut self.suppressions }
+
+    pub fn fn_models(&self) -> &Vec<FnModel> { &self.fn_models }
+
+    pub fn fn_models_mut(&mut self) -> &mut Vec<FnModel> { &mut self.fn_models }
+
+    // The model of the function, e.g., the model `ffi::free` matches `sys::ffi::free`, the longest path wins.
+    pub fn fn_model_of(&self, def_path: &str) -> Option<&FnModel> {
+        self.fn_models
+            .iter()
+            .filter(|model| model.matches(def_path))
+            .max_by_key(|model| model.path().len())
+    }
+
+    pub fn is_path_analyzed(&self, path: &Path) -> bool {
+        if !self.include_paths.is_empty() && !self.include_paths.iter().any(|p| path.starts_with(p)) {
+            return false;
+        }
+        !self.exclude_paths.iter().any(|p| path.starts_with(p))
+    }
+
 }
 
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
@@ -157,6 +297,11 @@ pub fn start_analyzer(tcx: TyCtxt, config: RlcConfig) {
     let rcx_boxed = Box::new(RlcGlobalCtxt::new(tcx, config));
     let rcx = Box::leak(rcx_boxed);
 
+    // the result cache is only used when cargo-rlc gives its dir
+    if let Ok(dir) = std::env::var(RLC_CACHE_DIR_ENV) {
+        rcx.set_cache(RlcCache::new(dir));
+    }
+
     run_analyzer(
         "Type Analysis",
         ||
@@ -168,4 +313,91 @@ pub fn start_analyzer(tcx: TyCtxt, config: RlcConfig) {
         ||
             FlowAnalysis::new(rcx).start()
     );
-}
\ No newline at end of file
+
+    show_cache_stats(rcx);
+    apply_suppressions(rcx);
+    show_leak_summary(rcx);
+    emit_leak_lints(rcx);
+
+    // the partial SARIF result is only required when cargo-rlc asks for it
+    if let Ok(dir) = std::env::var(RLC_SARIF_DIR_ENV) {
+        write_sarif_run(rcx, dir);
+    }
+}
+
+// The callbacks running the test on the TyCtxt of the crate after its analysis.
+#[cfg(test)]
+struct TestCallbacks<F, R> {
+    test: Option<F>,
+    result: Option<R>,
+}
+
+#[cfg(test)]
+impl<F: for<'tcx> FnOnce(TyCtxt<'tcx>) -> R + Send, R: Send> rustc_driver::Callbacks for TestCallbacks<F, R> {
+    fn after_analysis<'tcx>(
+        &mut self,
+        _compiler: &rustc_interface::interface::Compiler,
+        queries: &'tcx rustc_interface::Queries<'tcx>,
+    ) -> rustc_driver::Compilation {
+        let test = self.test.take().unwrap();
+        self.result = Some(queries.global_ctxt().unwrap().peek_mut().enter(test));
+        rustc_driver::Compilation::Stop
+    }
+}
+
+// Compile the source as the lib crate `name` and run the test on its TyCtxt, for the tests that need the
+// MIR of real functions. The source is written to the same file for the same name, thus the spans of
+// the same source are the same across the compilations.
+#[cfg(test)]
+pub(crate) fn with_tcx_of<R, F>(name: &str, source: &str, test: F) -> R
+where
+    R: Send,
+    F: for<'tcx> FnOnce(TyCtxt<'tcx>) -> R + Send,
+{
+    let dir = std::env::temp_dir().join(format!("rlc-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.rs", name));
+    std::fs::write(&path, source).unwrap();
+    let sysroot = compile_time_sysroot().expect("the sysroot is known at compile time");
+    let args: Vec<String> = vec![
+        "rustc".to_string(), path.display().to_string(), "--crate-type=lib".to_string(),
+        "--emit=metadata".to_string(), "--out-dir".to_string(), dir.display().to_string(),
+        "--sysroot".to_string(), sysroot,
+    ];
+    let mut callbacks = TestCallbacks { test: Some(test), result: None };
+    rustc_driver::RunCompiler::new(&args, &mut callbacks).run().expect("the source compiles");
+    callbacks.result.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::model::ParamModel;
+
+    #[test]
+    fn fn_model_of_longest_path_wins() {
+        let mut config = RlcConfig::default();
+        config.fn_models_mut().push(FnModel::new("free", vec![ParamModel::Take], false));
+        config.fn_models_mut().push(FnModel::new("ffi::free", vec![ParamModel::Borrow], false));
+
+        assert_eq!(config.fn_model_of("sys::ffi::free").map(|model| model.path().as_str()), Some("ffi::free"));
+        assert_eq!(config.fn_model_of("libc::free").map(|model| model.path().as_str()), Some("free"));
+        assert!(config.fn_model_of("libc::free_all").is_none());
+    }
+
+    #[test]
+    fn solver_timeout_of_longest_prefix_wins() {
+        let mut config = RlcConfig::default();
+        config.set_solver_timeout(1000);
+        config.fn_timeouts_mut().insert("parser".to_string(), 2000);
+        config.fn_timeouts_mut().insert("parser::parse".to_string(), 5000);
+
+        assert_eq!(config.solver_timeout_of("parser"), 2000);
+        assert_eq!(config.solver_timeout_of("parser::lex"), 2000);
+        assert_eq!(config.solver_timeout_of("parser::parse"), 5000);
+        assert_eq!(config.solver_timeout_of("parser::parse::expr"), 5000);
+        // the prefix only matches whole segments, and the timeouts do not match the tails
+        assert_eq!(config.solver_timeout_of("parser2::parse"), 1000);
+        assert_eq!(config.solver_timeout_of("crate::parser::parse"), 1000);
+    }
+}
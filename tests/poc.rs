@@ -0,0 +1,34 @@
+// The PoCs are checked end to end by the rlc driver, thus the constraints are solved by the real z3
+// rather than being built only.
+use std::path::Path;
+use std::process::Command;
+
+// Run rlc on the PoC and return the diagnostics it emits, the metadata is written to the temp dir
+// of the test and the results are never cached.
+fn run_rlc(poc: &str, magic_args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_rlc"))
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join(poc))
+        .args(["--emit=metadata", "--out-dir", env!("CARGO_TARGET_TMPDIR")])
+        .args(magic_args)
+        .env_remove("RLC_CACHE_DIR")
+        .output()
+        .expect("failed to run rlc");
+    assert!(output.status.success(), "rlc failed on {}", poc);
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn owned_pointer_leak_is_unsat() {
+    let diagnostics = run_rlc("PoC/OwnedPointer/op.rs", &[]);
+    assert!(diagnostics.contains("rlc::memory_leak"), "{}", diagnostics);
+    // the label is only attached to the leaks proven by an unsat result
+    assert!(diagnostics.contains("leaks when"), "{}", diagnostics);
+}
+
+#[test]
+fn owned_pointer_leak_does_not_depend_on_jobs() {
+    let serial = run_rlc("PoC/OwnedPointer/op.rs", &["-JOBS=1"]);
+    let parallel = run_rlc("PoC/OwnedPointer/op.rs", &["-JOBS=4"]);
+    assert!(parallel.contains("leaks when"), "{}", parallel);
+    assert_eq!(serial, parallel);
+}